@@ -0,0 +1,474 @@
+use anyhow::{anyhow, Result};
+
+use crate::cell_info::CellularType;
+
+/*
+ * 3GPP band tables to translate (NR-)ARFCNs into frequencies
+ *
+ * LTE: TS 36.101, Table 5.7.3-1 (DL EARFCN)
+ *   F_DL = F_DL_low + 0.1 MHz * (N_DL - N_Offs-DL)
+ *
+ * NR: TS 38.104, Table 5.4.2.1-1 (global raster) and
+ *     TS 38.101-1/-2, Table 5.4.2.3-1 (applicable NR-ARFCN per band)
+ *   F_REF = F_REF-Offs + dF_Global * (N_REF - N_REF-Offs)
+ *
+ * The UL frequency is derived with the default duplex spacing of the band.
+ * */
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum DuplexMode {
+    FDD,
+    TDD,
+    /// Supplemental downlink, no UL
+    SDL,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArfcnInfo {
+    pub arfcn: u64,
+    pub cell_type: CellularType,
+    pub band: u16,
+    pub duplex_mode: DuplexMode,
+    pub dl_frequency_hz: u64,
+    /// None if the band has no UL for this channel
+    pub ul_frequency_hz: Option<u64>,
+    pub channel_raster_khz: u32,
+}
+
+/// Frequency ranges are given in 100 kHz
+struct LteBand {
+    band: u16,
+    duplex_mode: DuplexMode,
+    dl_low: u64,
+    n_offs_dl: u64,
+    n_dl_last: u64,
+    /// (low, high) of the paired UL range for FDD bands
+    ul_range: Option<(u64, u64)>,
+}
+
+/// Frequency ranges are given in MHz * 10 to represent e.g. 2483.5 MHz
+struct NrBand {
+    band: u16,
+    duplex_mode: DuplexMode,
+    dl_range: (u64, u64),
+    ul_range: Option<(u64, u64)>,
+    n_ref_first: u64,
+    n_ref_last: u64,
+    /// Smallest channel raster of the band
+    raster_khz: u32,
+}
+
+const LTE_CHANNEL_RASTER_KHZ: u32 = 100;
+
+#[rustfmt::skip]
+const LTE_BANDS: &[LteBand] = &[
+    LteBand { band: 1, duplex_mode: DuplexMode::FDD, dl_low: 21100, n_offs_dl: 0, n_dl_last: 599, ul_range: Some((19200, 19800)) },
+    LteBand { band: 2, duplex_mode: DuplexMode::FDD, dl_low: 19300, n_offs_dl: 600, n_dl_last: 1199, ul_range: Some((18500, 19100)) },
+    LteBand { band: 3, duplex_mode: DuplexMode::FDD, dl_low: 18050, n_offs_dl: 1200, n_dl_last: 1949, ul_range: Some((17100, 17850)) },
+    LteBand { band: 4, duplex_mode: DuplexMode::FDD, dl_low: 21100, n_offs_dl: 1950, n_dl_last: 2399, ul_range: Some((17100, 17550)) },
+    LteBand { band: 5, duplex_mode: DuplexMode::FDD, dl_low: 8690, n_offs_dl: 2400, n_dl_last: 2649, ul_range: Some((8240, 8490)) },
+    LteBand { band: 6, duplex_mode: DuplexMode::FDD, dl_low: 8750, n_offs_dl: 2650, n_dl_last: 2749, ul_range: Some((8300, 8400)) },
+    LteBand { band: 7, duplex_mode: DuplexMode::FDD, dl_low: 26200, n_offs_dl: 2750, n_dl_last: 3449, ul_range: Some((25000, 25700)) },
+    LteBand { band: 8, duplex_mode: DuplexMode::FDD, dl_low: 9250, n_offs_dl: 3450, n_dl_last: 3799, ul_range: Some((8800, 9150)) },
+    LteBand { band: 9, duplex_mode: DuplexMode::FDD, dl_low: 18449, n_offs_dl: 3800, n_dl_last: 4149, ul_range: Some((17499, 17849)) },
+    LteBand { band: 10, duplex_mode: DuplexMode::FDD, dl_low: 21100, n_offs_dl: 4150, n_dl_last: 4749, ul_range: Some((17100, 17700)) },
+    LteBand { band: 11, duplex_mode: DuplexMode::FDD, dl_low: 14759, n_offs_dl: 4750, n_dl_last: 4949, ul_range: Some((14279, 14479)) },
+    LteBand { band: 12, duplex_mode: DuplexMode::FDD, dl_low: 7290, n_offs_dl: 5010, n_dl_last: 5179, ul_range: Some((6990, 7160)) },
+    LteBand { band: 13, duplex_mode: DuplexMode::FDD, dl_low: 7460, n_offs_dl: 5180, n_dl_last: 5279, ul_range: Some((7770, 7870)) },
+    LteBand { band: 14, duplex_mode: DuplexMode::FDD, dl_low: 7580, n_offs_dl: 5280, n_dl_last: 5379, ul_range: Some((7880, 7980)) },
+    LteBand { band: 17, duplex_mode: DuplexMode::FDD, dl_low: 7340, n_offs_dl: 5730, n_dl_last: 5849, ul_range: Some((7040, 7160)) },
+    LteBand { band: 18, duplex_mode: DuplexMode::FDD, dl_low: 8600, n_offs_dl: 5850, n_dl_last: 5999, ul_range: Some((8150, 8300)) },
+    LteBand { band: 19, duplex_mode: DuplexMode::FDD, dl_low: 8750, n_offs_dl: 6000, n_dl_last: 6149, ul_range: Some((8300, 8450)) },
+    LteBand { band: 20, duplex_mode: DuplexMode::FDD, dl_low: 7910, n_offs_dl: 6150, n_dl_last: 6449, ul_range: Some((8320, 8620)) },
+    LteBand { band: 21, duplex_mode: DuplexMode::FDD, dl_low: 14959, n_offs_dl: 6450, n_dl_last: 6599, ul_range: Some((14479, 14629)) },
+    LteBand { band: 22, duplex_mode: DuplexMode::FDD, dl_low: 35100, n_offs_dl: 6600, n_dl_last: 7399, ul_range: Some((34100, 34900)) },
+    LteBand { band: 23, duplex_mode: DuplexMode::FDD, dl_low: 21800, n_offs_dl: 7500, n_dl_last: 7699, ul_range: Some((20000, 20200)) },
+    LteBand { band: 24, duplex_mode: DuplexMode::FDD, dl_low: 15250, n_offs_dl: 7700, n_dl_last: 8039, ul_range: Some((16265, 16605)) },
+    LteBand { band: 25, duplex_mode: DuplexMode::FDD, dl_low: 19300, n_offs_dl: 8040, n_dl_last: 8689, ul_range: Some((18500, 19150)) },
+    LteBand { band: 26, duplex_mode: DuplexMode::FDD, dl_low: 8590, n_offs_dl: 8690, n_dl_last: 9039, ul_range: Some((8140, 8490)) },
+    LteBand { band: 27, duplex_mode: DuplexMode::FDD, dl_low: 8520, n_offs_dl: 9040, n_dl_last: 9209, ul_range: Some((8070, 8240)) },
+    LteBand { band: 28, duplex_mode: DuplexMode::FDD, dl_low: 7580, n_offs_dl: 9210, n_dl_last: 9659, ul_range: Some((7030, 7480)) },
+    LteBand { band: 29, duplex_mode: DuplexMode::SDL, dl_low: 7170, n_offs_dl: 9660, n_dl_last: 9769, ul_range: None },
+    LteBand { band: 30, duplex_mode: DuplexMode::FDD, dl_low: 23500, n_offs_dl: 9770, n_dl_last: 9869, ul_range: Some((23050, 23150)) },
+    LteBand { band: 31, duplex_mode: DuplexMode::FDD, dl_low: 4625, n_offs_dl: 9870, n_dl_last: 9919, ul_range: Some((4525, 4575)) },
+    LteBand { band: 32, duplex_mode: DuplexMode::SDL, dl_low: 14520, n_offs_dl: 9920, n_dl_last: 10359, ul_range: None },
+    LteBand { band: 33, duplex_mode: DuplexMode::TDD, dl_low: 19000, n_offs_dl: 36000, n_dl_last: 36199, ul_range: None },
+    LteBand { band: 34, duplex_mode: DuplexMode::TDD, dl_low: 20100, n_offs_dl: 36200, n_dl_last: 36349, ul_range: None },
+    LteBand { band: 35, duplex_mode: DuplexMode::TDD, dl_low: 18500, n_offs_dl: 36350, n_dl_last: 36949, ul_range: None },
+    LteBand { band: 36, duplex_mode: DuplexMode::TDD, dl_low: 19300, n_offs_dl: 36950, n_dl_last: 37549, ul_range: None },
+    LteBand { band: 37, duplex_mode: DuplexMode::TDD, dl_low: 19100, n_offs_dl: 37550, n_dl_last: 37749, ul_range: None },
+    LteBand { band: 38, duplex_mode: DuplexMode::TDD, dl_low: 25700, n_offs_dl: 37750, n_dl_last: 38249, ul_range: None },
+    LteBand { band: 39, duplex_mode: DuplexMode::TDD, dl_low: 18800, n_offs_dl: 38250, n_dl_last: 38649, ul_range: None },
+    LteBand { band: 40, duplex_mode: DuplexMode::TDD, dl_low: 23000, n_offs_dl: 38650, n_dl_last: 39649, ul_range: None },
+    LteBand { band: 41, duplex_mode: DuplexMode::TDD, dl_low: 24960, n_offs_dl: 39650, n_dl_last: 41589, ul_range: None },
+    LteBand { band: 42, duplex_mode: DuplexMode::TDD, dl_low: 34000, n_offs_dl: 41590, n_dl_last: 43589, ul_range: None },
+    LteBand { band: 43, duplex_mode: DuplexMode::TDD, dl_low: 36000, n_offs_dl: 43590, n_dl_last: 45589, ul_range: None },
+    LteBand { band: 44, duplex_mode: DuplexMode::TDD, dl_low: 7030, n_offs_dl: 45590, n_dl_last: 46589, ul_range: None },
+    LteBand { band: 45, duplex_mode: DuplexMode::TDD, dl_low: 14470, n_offs_dl: 46590, n_dl_last: 46789, ul_range: None },
+    LteBand { band: 46, duplex_mode: DuplexMode::TDD, dl_low: 51500, n_offs_dl: 46790, n_dl_last: 54539, ul_range: None },
+    LteBand { band: 47, duplex_mode: DuplexMode::TDD, dl_low: 58550, n_offs_dl: 54540, n_dl_last: 55239, ul_range: None },
+    LteBand { band: 48, duplex_mode: DuplexMode::TDD, dl_low: 35500, n_offs_dl: 55240, n_dl_last: 56739, ul_range: None },
+    LteBand { band: 49, duplex_mode: DuplexMode::TDD, dl_low: 35500, n_offs_dl: 56740, n_dl_last: 58239, ul_range: None },
+    LteBand { band: 50, duplex_mode: DuplexMode::TDD, dl_low: 14320, n_offs_dl: 58240, n_dl_last: 59089, ul_range: None },
+    LteBand { band: 51, duplex_mode: DuplexMode::TDD, dl_low: 14270, n_offs_dl: 59090, n_dl_last: 59139, ul_range: None },
+    LteBand { band: 52, duplex_mode: DuplexMode::TDD, dl_low: 33000, n_offs_dl: 59140, n_dl_last: 60139, ul_range: None },
+    LteBand { band: 53, duplex_mode: DuplexMode::TDD, dl_low: 24835, n_offs_dl: 60140, n_dl_last: 60254, ul_range: None },
+    LteBand { band: 65, duplex_mode: DuplexMode::FDD, dl_low: 21100, n_offs_dl: 65536, n_dl_last: 66435, ul_range: Some((19200, 20100)) },
+    LteBand { band: 66, duplex_mode: DuplexMode::FDD, dl_low: 21100, n_offs_dl: 66436, n_dl_last: 67335, ul_range: Some((17100, 17800)) },
+    LteBand { band: 67, duplex_mode: DuplexMode::SDL, dl_low: 7380, n_offs_dl: 67336, n_dl_last: 67535, ul_range: None },
+    LteBand { band: 68, duplex_mode: DuplexMode::FDD, dl_low: 7530, n_offs_dl: 67536, n_dl_last: 67835, ul_range: Some((6980, 7280)) },
+    LteBand { band: 69, duplex_mode: DuplexMode::SDL, dl_low: 25700, n_offs_dl: 67836, n_dl_last: 68335, ul_range: None },
+    LteBand { band: 70, duplex_mode: DuplexMode::FDD, dl_low: 19950, n_offs_dl: 68336, n_dl_last: 68585, ul_range: Some((16950, 17100)) },
+    LteBand { band: 71, duplex_mode: DuplexMode::FDD, dl_low: 6170, n_offs_dl: 68586, n_dl_last: 68935, ul_range: Some((6630, 6980)) },
+    LteBand { band: 72, duplex_mode: DuplexMode::FDD, dl_low: 4610, n_offs_dl: 68936, n_dl_last: 68985, ul_range: Some((4510, 4560)) },
+    LteBand { band: 73, duplex_mode: DuplexMode::FDD, dl_low: 4600, n_offs_dl: 68986, n_dl_last: 69035, ul_range: Some((4500, 4550)) },
+    LteBand { band: 74, duplex_mode: DuplexMode::FDD, dl_low: 14750, n_offs_dl: 69036, n_dl_last: 69465, ul_range: Some((14270, 14700)) },
+    LteBand { band: 75, duplex_mode: DuplexMode::SDL, dl_low: 14320, n_offs_dl: 69466, n_dl_last: 70315, ul_range: None },
+    LteBand { band: 76, duplex_mode: DuplexMode::SDL, dl_low: 14270, n_offs_dl: 70316, n_dl_last: 70365, ul_range: None },
+    LteBand { band: 85, duplex_mode: DuplexMode::FDD, dl_low: 7280, n_offs_dl: 70366, n_dl_last: 70545, ul_range: Some((6980, 7160)) },
+    LteBand { band: 87, duplex_mode: DuplexMode::FDD, dl_low: 4200, n_offs_dl: 70546, n_dl_last: 70595, ul_range: Some((4100, 4150)) },
+    LteBand { band: 88, duplex_mode: DuplexMode::FDD, dl_low: 4220, n_offs_dl: 70596, n_dl_last: 70645, ul_range: Some((4120, 4170)) },
+];
+
+/* Bands sharing NR-ARFCNs are listed in the order they are picked without band hint */
+#[rustfmt::skip]
+const NR_BANDS: &[NrBand] = &[
+    NrBand { band: 1, duplex_mode: DuplexMode::FDD, dl_range: (21100, 21700), ul_range: Some((19200, 19800)), n_ref_first: 422000, n_ref_last: 434000, raster_khz: 100 },
+    NrBand { band: 2, duplex_mode: DuplexMode::FDD, dl_range: (19300, 19900), ul_range: Some((18500, 19100)), n_ref_first: 386000, n_ref_last: 398000, raster_khz: 100 },
+    NrBand { band: 3, duplex_mode: DuplexMode::FDD, dl_range: (18050, 18800), ul_range: Some((17100, 17850)), n_ref_first: 361000, n_ref_last: 376000, raster_khz: 100 },
+    NrBand { band: 5, duplex_mode: DuplexMode::FDD, dl_range: (8690, 8940), ul_range: Some((8240, 8490)), n_ref_first: 173800, n_ref_last: 178800, raster_khz: 100 },
+    NrBand { band: 7, duplex_mode: DuplexMode::FDD, dl_range: (26200, 26900), ul_range: Some((25000, 25700)), n_ref_first: 524000, n_ref_last: 538000, raster_khz: 100 },
+    NrBand { band: 8, duplex_mode: DuplexMode::FDD, dl_range: (9250, 9600), ul_range: Some((8800, 9150)), n_ref_first: 185000, n_ref_last: 192000, raster_khz: 100 },
+    NrBand { band: 12, duplex_mode: DuplexMode::FDD, dl_range: (7290, 7460), ul_range: Some((6990, 7160)), n_ref_first: 145800, n_ref_last: 149200, raster_khz: 100 },
+    NrBand { band: 13, duplex_mode: DuplexMode::FDD, dl_range: (7460, 7560), ul_range: Some((7770, 7870)), n_ref_first: 149200, n_ref_last: 151200, raster_khz: 100 },
+    NrBand { band: 14, duplex_mode: DuplexMode::FDD, dl_range: (7580, 7680), ul_range: Some((7880, 7980)), n_ref_first: 151600, n_ref_last: 153600, raster_khz: 100 },
+    NrBand { band: 18, duplex_mode: DuplexMode::FDD, dl_range: (8600, 8750), ul_range: Some((8150, 8300)), n_ref_first: 172000, n_ref_last: 175000, raster_khz: 100 },
+    NrBand { band: 20, duplex_mode: DuplexMode::FDD, dl_range: (7910, 8210), ul_range: Some((8320, 8620)), n_ref_first: 158200, n_ref_last: 164200, raster_khz: 100 },
+    NrBand { band: 24, duplex_mode: DuplexMode::FDD, dl_range: (15250, 15590), ul_range: Some((16265, 16605)), n_ref_first: 305000, n_ref_last: 311800, raster_khz: 100 },
+    NrBand { band: 25, duplex_mode: DuplexMode::FDD, dl_range: (19300, 19950), ul_range: Some((18500, 19150)), n_ref_first: 386000, n_ref_last: 399000, raster_khz: 100 },
+    NrBand { band: 26, duplex_mode: DuplexMode::FDD, dl_range: (8590, 8940), ul_range: Some((8140, 8490)), n_ref_first: 171800, n_ref_last: 178800, raster_khz: 100 },
+    NrBand { band: 28, duplex_mode: DuplexMode::FDD, dl_range: (7580, 8030), ul_range: Some((7030, 7480)), n_ref_first: 151600, n_ref_last: 160600, raster_khz: 100 },
+    NrBand { band: 29, duplex_mode: DuplexMode::SDL, dl_range: (7170, 7280), ul_range: None, n_ref_first: 143400, n_ref_last: 145600, raster_khz: 100 },
+    NrBand { band: 30, duplex_mode: DuplexMode::FDD, dl_range: (23500, 23600), ul_range: Some((23050, 23150)), n_ref_first: 470000, n_ref_last: 472000, raster_khz: 100 },
+    NrBand { band: 34, duplex_mode: DuplexMode::TDD, dl_range: (20100, 20250), ul_range: None, n_ref_first: 402000, n_ref_last: 405000, raster_khz: 100 },
+    NrBand { band: 38, duplex_mode: DuplexMode::TDD, dl_range: (25700, 26200), ul_range: None, n_ref_first: 514000, n_ref_last: 524000, raster_khz: 100 },
+    NrBand { band: 39, duplex_mode: DuplexMode::TDD, dl_range: (18800, 19200), ul_range: None, n_ref_first: 376000, n_ref_last: 384000, raster_khz: 100 },
+    NrBand { band: 40, duplex_mode: DuplexMode::TDD, dl_range: (23000, 24000), ul_range: None, n_ref_first: 460000, n_ref_last: 480000, raster_khz: 100 },
+    NrBand { band: 41, duplex_mode: DuplexMode::TDD, dl_range: (24960, 26900), ul_range: None, n_ref_first: 499200, n_ref_last: 537999, raster_khz: 15 },
+    NrBand { band: 46, duplex_mode: DuplexMode::TDD, dl_range: (51500, 59250), ul_range: None, n_ref_first: 743334, n_ref_last: 795000, raster_khz: 15 },
+    NrBand { band: 48, duplex_mode: DuplexMode::TDD, dl_range: (35500, 37000), ul_range: None, n_ref_first: 636667, n_ref_last: 646666, raster_khz: 15 },
+    NrBand { band: 50, duplex_mode: DuplexMode::TDD, dl_range: (14320, 15170), ul_range: None, n_ref_first: 286400, n_ref_last: 303400, raster_khz: 100 },
+    NrBand { band: 51, duplex_mode: DuplexMode::TDD, dl_range: (14270, 14320), ul_range: None, n_ref_first: 285400, n_ref_last: 286400, raster_khz: 100 },
+    NrBand { band: 53, duplex_mode: DuplexMode::TDD, dl_range: (24835, 24950), ul_range: None, n_ref_first: 496700, n_ref_last: 499000, raster_khz: 100 },
+    NrBand { band: 65, duplex_mode: DuplexMode::FDD, dl_range: (21100, 22000), ul_range: Some((19200, 20100)), n_ref_first: 422000, n_ref_last: 440000, raster_khz: 100 },
+    NrBand { band: 66, duplex_mode: DuplexMode::FDD, dl_range: (21100, 22000), ul_range: Some((17100, 17800)), n_ref_first: 422000, n_ref_last: 440000, raster_khz: 100 },
+    NrBand { band: 67, duplex_mode: DuplexMode::SDL, dl_range: (7380, 7580), ul_range: None, n_ref_first: 147600, n_ref_last: 151600, raster_khz: 100 },
+    NrBand { band: 70, duplex_mode: DuplexMode::FDD, dl_range: (19950, 20200), ul_range: Some((16950, 17100)), n_ref_first: 399000, n_ref_last: 404000, raster_khz: 100 },
+    NrBand { band: 71, duplex_mode: DuplexMode::FDD, dl_range: (6170, 6520), ul_range: Some((6630, 6980)), n_ref_first: 123400, n_ref_last: 130400, raster_khz: 100 },
+    NrBand { band: 74, duplex_mode: DuplexMode::FDD, dl_range: (14750, 15180), ul_range: Some((14270, 14700)), n_ref_first: 295000, n_ref_last: 303600, raster_khz: 100 },
+    NrBand { band: 75, duplex_mode: DuplexMode::SDL, dl_range: (14320, 15170), ul_range: None, n_ref_first: 286400, n_ref_last: 303400, raster_khz: 100 },
+    NrBand { band: 76, duplex_mode: DuplexMode::SDL, dl_range: (14270, 14320), ul_range: None, n_ref_first: 285400, n_ref_last: 286400, raster_khz: 100 },
+    NrBand { band: 78, duplex_mode: DuplexMode::TDD, dl_range: (33000, 38000), ul_range: None, n_ref_first: 620000, n_ref_last: 653333, raster_khz: 15 },
+    NrBand { band: 77, duplex_mode: DuplexMode::TDD, dl_range: (33000, 42000), ul_range: None, n_ref_first: 620000, n_ref_last: 680000, raster_khz: 15 },
+    NrBand { band: 79, duplex_mode: DuplexMode::TDD, dl_range: (44000, 50000), ul_range: None, n_ref_first: 693334, n_ref_last: 733333, raster_khz: 15 },
+    NrBand { band: 90, duplex_mode: DuplexMode::TDD, dl_range: (24960, 26900), ul_range: None, n_ref_first: 499200, n_ref_last: 538000, raster_khz: 15 },
+    NrBand { band: 96, duplex_mode: DuplexMode::TDD, dl_range: (59250, 71250), ul_range: None, n_ref_first: 795000, n_ref_last: 875000, raster_khz: 15 },
+    NrBand { band: 257, duplex_mode: DuplexMode::TDD, dl_range: (265000, 295000), ul_range: None, n_ref_first: 2054166, n_ref_last: 2104165, raster_khz: 60 },
+    NrBand { band: 258, duplex_mode: DuplexMode::TDD, dl_range: (242500, 275000), ul_range: None, n_ref_first: 2016667, n_ref_last: 2070832, raster_khz: 60 },
+    NrBand { band: 259, duplex_mode: DuplexMode::TDD, dl_range: (395000, 435000), ul_range: None, n_ref_first: 2270833, n_ref_last: 2337499, raster_khz: 60 },
+    NrBand { band: 260, duplex_mode: DuplexMode::TDD, dl_range: (370000, 400000), ul_range: None, n_ref_first: 2229166, n_ref_last: 2279165, raster_khz: 60 },
+    NrBand { band: 261, duplex_mode: DuplexMode::TDD, dl_range: (275000, 283500), ul_range: None, n_ref_first: 2070833, n_ref_last: 2084999, raster_khz: 60 },
+];
+
+/// Max. distance between a frequency designation like "1800" and the band's DL or UL frequencies
+const BAND_DESIGNATION_TOLERANCE_MHZ: u64 = 100;
+/// Band strings above this value are frequency designations (MHz) instead of band numbers
+const BAND_NUMBER_MAX: u64 = 300;
+
+pub fn lookup_arfcn(arfcn: u64, cell_type: &CellularType) -> Result<ArfcnInfo> {
+    match cell_type {
+        CellularType::LTE => lookup_lte_earfcn(arfcn, None),
+        CellularType::NR => lookup_nr_arfcn(arfcn, None),
+    }
+}
+
+/// Look up an ARFCN and check it against the band reported along with it.
+///
+/// The band string is either a band number ("3", "B3", "n78") or a frequency
+/// designation in MHz ("1800"). An empty string is not checked.
+pub fn lookup_arfcn_with_band(
+    arfcn: u64,
+    cell_type: &CellularType,
+    band_str: &str,
+) -> Result<ArfcnInfo> {
+    let reported_band = match parse_band_str(band_str)? {
+        Some(ReportedBand::Number(band)) => Some(band),
+        Some(ReportedBand::DesignationMhz(designation_mhz)) => {
+            let info = lookup_arfcn(arfcn, cell_type)?;
+            if !is_matching_designation(&info, designation_mhz) {
+                return Err(anyhow!(
+                    "ARFCN {} ({} Hz, band {}) does not match the reported band '{}'",
+                    arfcn,
+                    info.dl_frequency_hz,
+                    info.band,
+                    band_str
+                ));
+            }
+            return Ok(info);
+        }
+        None => None,
+    };
+    match cell_type {
+        CellularType::LTE => lookup_lte_earfcn(arfcn, reported_band),
+        CellularType::NR => lookup_nr_arfcn(arfcn, reported_band),
+    }
+}
+
+fn lookup_lte_earfcn(earfcn: u64, reported_band: Option<u16>) -> Result<ArfcnInfo> {
+    let lte_band = LTE_BANDS
+        .iter()
+        .find(|lte_band| (lte_band.n_offs_dl..=lte_band.n_dl_last).contains(&earfcn))
+        .ok_or_else(|| anyhow!("EARFCN {} is not a DL EARFCN of any LTE band", earfcn))?;
+    if let Some(reported_band) = reported_band {
+        if reported_band != lte_band.band {
+            return Err(anyhow!(
+                "EARFCN {} belongs to LTE band {}, but band {} was reported",
+                earfcn,
+                lte_band.band,
+                reported_band
+            ));
+        }
+    }
+
+    let dl_100khz = lte_band.dl_low + (earfcn - lte_band.n_offs_dl);
+    let ul_100khz = match lte_band.duplex_mode {
+        DuplexMode::FDD => lte_band.ul_range.and_then(|(ul_low, ul_high)| {
+            let ul_100khz = ul_low + (dl_100khz - lte_band.dl_low);
+            (ul_100khz <= ul_high).then_some(ul_100khz)
+        }),
+        DuplexMode::TDD => Some(dl_100khz),
+        DuplexMode::SDL => None,
+    };
+    Ok(ArfcnInfo {
+        arfcn: earfcn,
+        cell_type: CellularType::LTE,
+        band: lte_band.band,
+        duplex_mode: lte_band.duplex_mode,
+        dl_frequency_hz: dl_100khz * 100_000,
+        ul_frequency_hz: ul_100khz.map(|ul_100khz| ul_100khz * 100_000),
+        channel_raster_khz: LTE_CHANNEL_RASTER_KHZ,
+    })
+}
+
+/// Global frequency raster: (dF_Global in Hz, F_REF-Offs in Hz, N_REF-Offs)
+fn nr_global_raster(nr_arfcn: u64) -> Result<(u64, u64, u64)> {
+    match nr_arfcn {
+        0..=599999 => Ok((5_000, 0, 0)),
+        600000..=2016666 => Ok((15_000, 3_000_000_000, 600000)),
+        2016667..=3279165 => Ok((60_000, 24_250_080_000, 2016667)),
+        _ => Err(anyhow!("NR-ARFCN {} out of range", nr_arfcn)),
+    }
+}
+
+pub fn nr_arfcn_to_frequency(nr_arfcn: u64) -> Result<u64> {
+    let (delta_f_global, f_ref_offs, n_ref_offs) = nr_global_raster(nr_arfcn)?;
+    Ok(f_ref_offs + delta_f_global * (nr_arfcn - n_ref_offs))
+}
+
+fn lookup_nr_arfcn(nr_arfcn: u64, reported_band: Option<u16>) -> Result<ArfcnInfo> {
+    let (delta_f_global, _, _) = nr_global_raster(nr_arfcn)?;
+    let is_on_band_raster = |nr_band: &&NrBand| {
+        let step = (nr_band.raster_khz as u64 * 1000 / delta_f_global).max(1);
+        (nr_band.n_ref_first..=nr_band.n_ref_last).contains(&nr_arfcn)
+            && (nr_arfcn - nr_band.n_ref_first).is_multiple_of(step)
+    };
+    let nr_band = match reported_band {
+        Some(reported_band) => NR_BANDS
+            .iter()
+            .filter(is_on_band_raster)
+            .find(|nr_band| nr_band.band == reported_band)
+            .ok_or_else(|| {
+                anyhow!(
+                    "NR-ARFCN {} is not a channel of the reported band n{}",
+                    nr_arfcn,
+                    reported_band
+                )
+            })?,
+        None => NR_BANDS
+            .iter()
+            .find(is_on_band_raster)
+            .ok_or_else(|| anyhow!("NR-ARFCN {} is not a channel of any NR band", nr_arfcn))?,
+    };
+
+    let dl_frequency_hz = nr_arfcn_to_frequency(nr_arfcn)?;
+    let ul_frequency_hz = match nr_band.duplex_mode {
+        DuplexMode::FDD => nr_band.ul_range.and_then(|(ul_low, ul_high)| {
+            let dl_offset_hz = dl_frequency_hz.checked_sub(nr_band.dl_range.0 * 100_000)?;
+            let ul_frequency_hz = ul_low * 100_000 + dl_offset_hz;
+            (ul_frequency_hz <= ul_high * 100_000).then_some(ul_frequency_hz)
+        }),
+        DuplexMode::TDD => Some(dl_frequency_hz),
+        DuplexMode::SDL => None,
+    };
+    Ok(ArfcnInfo {
+        arfcn: nr_arfcn,
+        cell_type: CellularType::NR,
+        band: nr_band.band,
+        duplex_mode: nr_band.duplex_mode,
+        dl_frequency_hz,
+        ul_frequency_hz,
+        channel_raster_khz: nr_band.raster_khz,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum ReportedBand {
+    Number(u16),
+    DesignationMhz(u64),
+}
+
+fn parse_band_str(band_str: &str) -> Result<Option<ReportedBand>> {
+    let normalized = band_str.trim().to_lowercase();
+    let stripped = normalized
+        .trim_start_matches("lte")
+        .trim_start_matches("nr")
+        .trim_start_matches("5g")
+        .trim()
+        .trim_start_matches("band")
+        .trim()
+        .trim_start_matches(['b', 'n'])
+        .trim_end_matches("mhz")
+        .trim();
+    if stripped.is_empty() {
+        return Ok(None);
+    }
+    let value = stripped
+        .parse::<u64>()
+        .map_err(|_| anyhow!("Cannot interpret band '{}'", band_str))?;
+    if value > BAND_NUMBER_MAX {
+        Ok(Some(ReportedBand::DesignationMhz(value)))
+    } else {
+        Ok(Some(ReportedBand::Number(value as u16)))
+    }
+}
+
+fn is_matching_designation(info: &ArfcnInfo, designation_mhz: u64) -> bool {
+    let tolerance_hz = BAND_DESIGNATION_TOLERANCE_MHZ * 1_000_000;
+    let designation_hz = designation_mhz * 1_000_000;
+    [Some(info.dl_frequency_hz), info.ul_frequency_hz]
+        .into_iter()
+        .flatten()
+        .any(|frequency_hz| frequency_hz.abs_diff(designation_hz) <= tolerance_hz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_lte_earfcn() -> Result<()> {
+        let info = lookup_arfcn(1801, &CellularType::LTE)?;
+        assert_eq!(info.band, 3);
+        assert_eq!(info.duplex_mode, DuplexMode::FDD);
+        assert_eq!(info.dl_frequency_hz, 1_865_100_000);
+        assert_eq!(info.ul_frequency_hz, Some(1_770_100_000));
+        assert_eq!(info.channel_raster_khz, 100);
+
+        let info = lookup_arfcn(6300, &CellularType::LTE)?;
+        assert_eq!(info.band, 20);
+        assert_eq!(info.dl_frequency_hz, 806_000_000);
+        assert_eq!(info.ul_frequency_hz, Some(847_000_000));
+
+        /* TDD bands */
+        let info = lookup_arfcn(38000, &CellularType::LTE)?;
+        assert_eq!(info.band, 38);
+        assert_eq!(info.duplex_mode, DuplexMode::TDD);
+        assert_eq!(info.dl_frequency_hz, 2_595_000_000);
+        assert_eq!(info.ul_frequency_hz, Some(2_595_000_000));
+        assert_eq!(lookup_arfcn(39150, &CellularType::LTE)?.band, 40);
+        assert_eq!(lookup_arfcn(40620, &CellularType::LTE)?.band, 41);
+        assert_eq!(
+            lookup_arfcn(42590, &CellularType::LTE)?.dl_frequency_hz,
+            3_500_000_000
+        );
+        assert_eq!(lookup_arfcn(44590, &CellularType::LTE)?.band, 43);
+
+        /* SDL and DL-only channels of band 66 */
+        let info = lookup_arfcn(9700, &CellularType::LTE)?;
+        assert_eq!(info.duplex_mode, DuplexMode::SDL);
+        assert_eq!(info.ul_frequency_hz, None);
+        let info = lookup_arfcn(67300, &CellularType::LTE)?;
+        assert_eq!(info.band, 66);
+        assert_eq!(info.dl_frequency_hz, 2_196_400_000);
+        assert_eq!(info.ul_frequency_hz, None);
+
+        assert!(lookup_arfcn(7450, &CellularType::LTE).is_err());
+        assert!(lookup_arfcn(131072, &CellularType::LTE).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_nr_arfcn() -> Result<()> {
+        let info = lookup_arfcn(636666, &CellularType::NR)?;
+        assert_eq!(info.band, 78);
+        assert_eq!(info.duplex_mode, DuplexMode::TDD);
+        assert_eq!(info.dl_frequency_hz, 3_549_990_000);
+        assert_eq!(info.channel_raster_khz, 15);
+
+        let info = lookup_arfcn(155660, &CellularType::NR)?;
+        assert_eq!(info.band, 28);
+        assert_eq!(info.dl_frequency_hz, 778_300_000);
+        assert_eq!(info.ul_frequency_hz, Some(723_300_000));
+
+        /* band 1 is preferred unless the device reports the overlapping n66 */
+        assert_eq!(lookup_arfcn(428000, &CellularType::NR)?.band, 1);
+        let info = lookup_arfcn_with_band(428000, &CellularType::NR, "n66")?;
+        assert_eq!(info.band, 66);
+        assert_eq!(info.ul_frequency_hz, Some(1_740_000_000));
+
+        /* off the 100 kHz channel raster of n1 */
+        assert!(lookup_arfcn(422001, &CellularType::NR).is_err());
+        assert_eq!(lookup_arfcn(2016667, &CellularType::NR)?.band, 258);
+        assert!(lookup_arfcn(3279166, &CellularType::NR).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_arfcn_with_band() -> Result<()> {
+        assert_eq!(
+            lookup_arfcn_with_band(1801, &CellularType::LTE, "1800")?.band,
+            3
+        );
+        assert_eq!(
+            lookup_arfcn_with_band(1801, &CellularType::LTE, "B3")?.band,
+            3
+        );
+        assert_eq!(
+            lookup_arfcn_with_band(1801, &CellularType::LTE, "LTE BAND 3")?.band,
+            3
+        );
+        assert_eq!(
+            lookup_arfcn_with_band(6300, &CellularType::LTE, "800")?.band,
+            20
+        );
+        assert_eq!(
+            lookup_arfcn_with_band(6300, &CellularType::LTE, "")?.band,
+            20
+        );
+        assert_eq!(
+            lookup_arfcn_with_band(636666, &CellularType::NR, "n78")?.band,
+            78
+        );
+        assert_eq!(
+            lookup_arfcn_with_band(636666, &CellularType::NR, "NR5G BAND 78")?.band,
+            78
+        );
+
+        assert!(lookup_arfcn_with_band(1801, &CellularType::LTE, "20").is_err());
+        assert!(lookup_arfcn_with_band(1801, &CellularType::LTE, "800").is_err());
+        assert!(lookup_arfcn_with_band(636666, &CellularType::NR, "n1").is_err());
+        assert!(lookup_arfcn_with_band(1801, &CellularType::LTE, "unknown").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::{Instant, SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+use serde_derive::Deserialize;
+
+use crate::band_table::lookup_arfcn_with_band;
+use crate::cell_info::{CellInfo, CellularType, SingleCell};
+
+/*
+ * Cell source that reads the cells from a YAML/JSON file
+ *
+ * Static file:
+ *
+ *   cells:
+ *     - cell_type: LTE
+ *       arfcn: 1801
+ *       pci: 301
+ *
+ * Playback of timestamped cell changes, relative to the (re)load of the file:
+ *
+ *   repeat_after_ms: 60000
+ *   steps:
+ *     - at_ms: 0
+ *       cells: [ { cell_type: LTE, arfcn: 1801, pci: 301 } ]
+ *     - at_ms: 30000
+ *       cells: [ { cell_type: LTE, arfcn: 6300, pci: 12 } ]
+ * */
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileCell {
+    #[serde(default)]
+    pub cell_id: Option<u64>,
+    pub cell_type: String,
+    /// Either the (E)ARFCN or the DL frequency in Hz has to be given
+    #[serde(default)]
+    pub arfcn: Option<u64>,
+    #[serde(default)]
+    pub band: Option<String>,
+    #[serde(default)]
+    pub frequency: Option<u64>,
+    #[serde(default)]
+    pub pci: Option<u16>,
+    #[serde(default)]
+    pub rssi: f64,
+    #[serde(default)]
+    pub rsrp: f64,
+    #[serde(default)]
+    pub rsrq: f64,
+    #[serde(default)]
+    pub sinr: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileStep {
+    /// Time since the file was (re)loaded at which the cells become active
+    pub at_ms: u64,
+    pub cells: Vec<FileCell>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CellFileContent {
+    #[serde(default)]
+    pub cells: Option<Vec<FileCell>>,
+    #[serde(default)]
+    pub steps: Option<Vec<FileStep>>,
+    /// Start the playback over after this time
+    #[serde(default)]
+    pub repeat_after_ms: Option<u64>,
+}
+
+/// Modification time, length and content hash of a loaded cell file.
+/// The mtime alone misses two writes within one tick of a coarse filesystem clock.
+#[derive(Clone, Debug, PartialEq)]
+struct CellFileVersion {
+    modified: SystemTime,
+    len: u64,
+    content_hash: u64,
+}
+
+/// Cell information taken from a file that is reloaded whenever it changes
+pub struct CellFileSource {
+    path: String,
+    last_version: Option<CellFileVersion>,
+    loaded_at: Instant,
+    static_cells: CellInfo,
+    steps: Vec<(u64, CellInfo)>,
+    repeat_after_ms: Option<u64>,
+}
+
+impl FileCell {
+    fn to_single_cell(&self) -> Result<SingleCell> {
+        let cell_type = self.cell_type.parse::<CellularType>()?;
+        let frequency = match (self.arfcn, self.frequency) {
+            (Some(arfcn), _) => {
+                let band = self.band.clone().unwrap_or_default();
+                lookup_arfcn_with_band(arfcn, &cell_type, &band)?.dl_frequency_hz
+            }
+            (None, Some(frequency)) => frequency,
+            (None, None) => return Err(anyhow!("Cell without arfcn or frequency: {:?}", self)),
+        };
+        Ok(SingleCell {
+            cell_id: self.cell_id.or(self.pci.map(u64::from)).unwrap_or(0),
+            cell_type,
+            frequency,
+            pci: self.pci,
+            rssi: self.rssi,
+            rsrp: self.rsrp,
+            rsrq: self.rsrq,
+            sinr: self.sinr,
+            dl_est: None,
+            ul_est: None,
+        })
+    }
+}
+
+fn to_cell_info(cells: &[FileCell]) -> Result<CellInfo> {
+    Ok(CellInfo {
+        cells: cells
+            .iter()
+            .map(|cell| cell.to_single_cell())
+            .collect::<Result<Vec<SingleCell>>>()?,
+        neighbours: vec![],
+    })
+}
+
+pub fn parse_cell_file(path: &str, content: &str) -> Result<CellFileContent> {
+    let is_json = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    let file_content: CellFileContent = match is_json {
+        true => serde_json::from_str(content)?,
+        false => serde_yaml::from_str(content)?,
+    };
+    if file_content.cells.is_none() && file_content.steps.is_none() {
+        return Err(anyhow!("Cell file contains neither 'cells' nor 'steps'"));
+    }
+    Ok(file_content)
+}
+
+impl CellFileSource {
+    pub fn new(path: &str) -> CellFileSource {
+        CellFileSource {
+            path: path.to_string(),
+            last_version: None,
+            loaded_at: Instant::now(),
+            static_cells: CellInfo::default(),
+            steps: vec![],
+            repeat_after_ms: None,
+        }
+    }
+
+    /// Reload the file if it changed since the last call
+    fn reload_if_modified(&mut self) -> Result<()> {
+        let metadata = fs::metadata(&self.path)
+            .with_context(|| format!("Error reading cell file {}", self.path))?;
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Error reading cell file {}", self.path))?;
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let version = CellFileVersion {
+            modified: metadata.modified()?,
+            len: metadata.len(),
+            content_hash: hasher.finish(),
+        };
+        if self.last_version.as_ref() == Some(&version) {
+            return Ok(());
+        }
+        let file_content = parse_cell_file(&self.path, &content)
+            .with_context(|| format!("Error parsing cell file {}", self.path))?;
+
+        self.static_cells = to_cell_info(file_content.cells.as_deref().unwrap_or_default())?;
+        self.steps = file_content
+            .steps
+            .unwrap_or_default()
+            .iter()
+            .map(|step| Ok((step.at_ms, to_cell_info(&step.cells)?)))
+            .collect::<Result<Vec<(u64, CellInfo)>>>()?;
+        self.steps.sort_by_key(|(at_ms, _)| *at_ms);
+        self.repeat_after_ms = file_content.repeat_after_ms;
+        self.last_version = Some(version);
+        self.loaded_at = Instant::now();
+        Ok(())
+    }
+
+    /// Cells that are active `elapsed_ms` after loading the file
+    fn cells_at(&self, elapsed_ms: u64) -> CellInfo {
+        if self.steps.is_empty() {
+            return self.static_cells.clone();
+        }
+        let elapsed_ms = match self.repeat_after_ms {
+            Some(repeat_after_ms) if repeat_after_ms > 0 => elapsed_ms % repeat_after_ms,
+            _ => elapsed_ms,
+        };
+        self.steps
+            .iter()
+            .rev()
+            .find(|(at_ms, _)| *at_ms <= elapsed_ms)
+            .map(|(_, cell_info)| cell_info.clone())
+            .unwrap_or(self.static_cells.clone())
+    }
+
+    pub fn retrieve(&mut self) -> Result<CellInfo> {
+        self.reload_if_modified()?;
+        Ok(self.cells_at(self.loaded_at.elapsed().as_millis() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATIC_YAML: &str = "cells:\n  - cell_type: LTE\n    arfcn: 1801\n    pci: 301\n  - cell_type: NR\n    frequency: 3549990000\n";
+    const PLAYBACK_JSON: &str = r#"{
+        "repeat_after_ms": 200,
+        "steps": [
+            { "at_ms": 0, "cells": [ { "cell_type": "LTE", "arfcn": 1801, "pci": 301 } ] },
+            { "at_ms": 100, "cells": [ { "cell_type": "LTE", "arfcn": 6300, "band": "20", "pci": 12 } ] }
+        ]
+    }"#;
+
+    fn write_temp_file(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_static_cell_file() -> Result<()> {
+        let path = write_temp_file("cells.yaml", STATIC_YAML);
+        let mut source = CellFileSource::new(&path);
+        let cell_info = source.retrieve()?;
+        assert_eq!(cell_info.cells.len(), 2);
+        assert_eq!(cell_info.cells[0].frequency, 1_865_100_000);
+        assert_eq!(cell_info.cells[0].pci, Some(301));
+        assert_eq!(cell_info.cells[0].cell_id, 301);
+        assert_eq!(cell_info.cells[1].cell_type, CellularType::NR);
+        assert_eq!(cell_info.cells[1].frequency, 3_549_990_000);
+
+        fs::write(&path, "cells:\n  - cell_type: LTE\n    arfcn: 6300\n")?;
+        let cell_info = source.retrieve()?;
+        assert_eq!(cell_info.cells.len(), 1);
+        assert_eq!(cell_info.cells[0].frequency, 806_000_000);
+
+        /* same length and modification time, only the content differs */
+        let modified = fs::metadata(&path)?.modified()?;
+        fs::write(&path, "cells:\n  - cell_type: LTE\n    arfcn: 6400\n")?;
+        fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified)?;
+        let cell_info = source.retrieve()?;
+        assert_eq!(cell_info.cells[0].frequency, 816_000_000);
+
+        fs::write(&path, "cells:\n  - cell_type: LTE\n")?;
+        assert!(source.retrieve().is_err());
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_playback_cell_file() -> Result<()> {
+        let path = write_temp_file("cells.json", PLAYBACK_JSON);
+        let mut source = CellFileSource::new(&path);
+        source.reload_if_modified()?;
+        assert_eq!(source.cells_at(0).cells[0].pci, Some(301));
+        assert_eq!(source.cells_at(99).cells[0].pci, Some(301));
+        assert_eq!(source.cells_at(150).cells[0].pci, Some(12));
+        assert_eq!(source.cells_at(150).cells[0].frequency, 806_000_000);
+        /* repeats after the last step */
+        assert_eq!(source.cells_at(201).cells[0].pci, Some(301));
+
+        source.repeat_after_ms = None;
+        assert_eq!(source.cells_at(201).cells[0].pci, Some(12));
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_cell_file_errors() {
+        assert!(parse_cell_file("cells.yaml", "repeat_after_ms: 10\n").is_err());
+        assert!(parse_cell_file("cells.json", STATIC_YAML).is_err());
+        assert!(parse_cell_file("cells.yml", PLAYBACK_JSON).is_ok());
+    }
+}
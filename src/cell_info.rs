@@ -1,16 +1,12 @@
-use std::time::Duration;
-
 use anyhow::{anyhow, Result};
 
 use regex::Regex;
-use reqwest::header::{HeaderMap, HeaderName};
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
+use crate::band_table::{lookup_arfcn, lookup_arfcn_with_band};
 use crate::util::helper_json_pointer;
 
-pub const REQUEST_TIMEOUT_MS: u64 = 2000;
-
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum CellularType {
     #[default]
@@ -28,21 +24,45 @@ impl CellularType {
     }
 }
 
-#[derive(Debug, Clone)]
+impl std::str::FromStr for CellularType {
+    type Err = anyhow::Error;
+
+    fn from_str(cell_str: &str) -> Result<CellularType> {
+        CellularType::from_str(cell_str)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct CellInfo {
+    /// Connected cells, the first one is the primary cell
     pub cells: Vec<SingleCell>,
+    /// Neighbour cells the UE can see, if reported by the cell API
+    pub neighbours: Vec<SingleCell>,
 }
 
-#[derive(Debug, Clone, Default)]
+/// Result of a single cell source poll
+#[derive(Debug, Clone, Serialize)]
+pub struct CellInfoRecord {
+    pub timestamp_us: u64,
+    pub source: String,
+    pub cells: Vec<SingleCell>,
+    pub neighbours: Vec<SingleCell>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
 #[allow(dead_code)]
 pub struct SingleCell {
     pub cell_id: u64,
     pub cell_type: CellularType,
     /// Number of PRB per slot
     pub frequency: u64,
+    /// Physical cell ID, if reported by the cell API
+    pub pci: Option<u16>,
     pub rssi: f64,
     pub rsrp: f64,
     pub rsrq: f64,
+    /// Signal to interference plus noise ratio in dB, if reported by the cell API
+    pub sinr: Option<f64>,
     pub dl_est: Option<f64>,
     pub ul_est: Option<f64>,
 }
@@ -63,123 +83,9 @@ pub struct CellData {
     pub estimatedUpBandwidth: Option<f64>,
 }
 
+/// DL frequency of an EARFCN (LTE) or NR-ARFCN (NR), see band_table for details
 pub fn arfcn_to_frequency(arfcn: u64, cell_type: &CellularType) -> Result<u64> {
-    match *cell_type {
-        CellularType::LTE => {
-            if (0..=599).contains(&arfcn) {
-                // Band 1
-                Ok(2110000000 + 100000 * arfcn)
-            } else if (600..=1199).contains(&arfcn) {
-                // Band 2
-                Ok(1930000000 + 100000 * (arfcn - 600))
-            } else if (1200..=1949).contains(&arfcn) {
-                // Band 3
-                Ok(1805000000 + 100000 * (arfcn - 1200))
-            } else if (1950..=2399).contains(&arfcn) {
-                // Band 4
-                Ok(2110000000 + 100000 * (arfcn - 1949))
-            } else if (2400..=2649).contains(&arfcn) {
-                // Band 5
-                Ok(869000000 + 100000 * (arfcn - 2400))
-            } else if (2750..=3449).contains(&arfcn) {
-                // Band 7
-                Ok(2620000000 + 100000 * (arfcn - 2750))
-            } else if (3450..=3799).contains(&arfcn) {
-                // Band 8
-                Ok(925000000 + 100000 * (arfcn - 3450))
-            } else if (3800..=4149).contains(&arfcn) {
-                // Band 9
-                Ok(1844900000 + 100000 * (arfcn - 3800))
-            } else if (4150..=4749).contains(&arfcn) {
-                // Band 10
-                Ok(2110000000 + 100000 * (arfcn - 4150))
-            } else if (4750..=4949).contains(&arfcn) {
-                // Band 11
-                Ok(1475900000 + 100000 * (arfcn - 4750))
-            } else if (5010..=5179).contains(&arfcn) {
-                // Band 12
-                Ok(729000000 + 100000 * (arfcn - 5010))
-            } else if (5180..=5279).contains(&arfcn) {
-                // Band 13
-                Ok(746000000 + 100000 * (arfcn - 5180))
-            } else if (5280..=5379).contains(&arfcn) {
-                // Band 14
-                Ok(758000000 + 100000 * (arfcn - 5280))
-            } else if (5730..=5849).contains(&arfcn) {
-                // Band 17
-                Ok(734000000 + 100000 * (arfcn - 5730))
-            } else if (5850..=5999).contains(&arfcn) {
-                // Band 18
-                Ok(860000000 + 100000 * (arfcn - 5850))
-            } else if (6000..=6149).contains(&arfcn) {
-                // Band 19
-                Ok(875000000 + 100000 * (arfcn - 6000))
-            } else if (6150..=6449).contains(&arfcn) {
-                // Band 20
-                Ok(791000000 + 100000 * (arfcn - 6150))
-            } else if (6450..=6599).contains(&arfcn) {
-                // Band 21
-                Ok(1495900000 + 100000 * (arfcn - 6450))
-            } else if (6600..=7399).contains(&arfcn) {
-                // Band 22
-                Ok(3510000000 + 100000 * (arfcn - 6600))
-            } else if (7700..=8039).contains(&arfcn) {
-                // Band 24
-                Ok(1525000000 + 100000 * (arfcn - 7700))
-            } else if (8040..=8689).contains(&arfcn) {
-                // Band 25
-                Ok(1930000000 + 100000 * (arfcn - 8040))
-            } else if (8690..=9039).contains(&arfcn) {
-                // Band 26
-                Ok(859000000 + 100000 * (arfcn - 8690))
-            } else if (9040..=9209).contains(&arfcn) {
-                // Band 27
-                Ok(852000000 + 100000 * (arfcn - 9040))
-            } else if (9210..=9659).contains(&arfcn) {
-                // Band 28
-                Ok(758000000 + 100000 * (arfcn - 9210))
-            } else if (9660..=9769).contains(&arfcn) {
-                // Band 29
-                Ok(728000000 + 100000 * (arfcn - 9660))
-            } else if (9770..=9869).contains(&arfcn) {
-                // Band 30
-                Ok(2350000000 + 100000 * (arfcn - 9770))
-            } else if (9870..=9919).contains(&arfcn) {
-                // Band 31
-                Ok(462500000 + 100000 * (arfcn - 9870))
-            } else if (9919..=10359).contains(&arfcn) {
-                // Band 32
-                Ok(1492000000 + 100000 * (arfcn - 9919))
-            } else if (131072..=131971).contains(&arfcn) {
-                // Band 65
-                Ok(2110000000 + 100000 * (arfcn - 131072))
-            } else if (131972..=132671).contains(&arfcn) {
-                // Band 66
-                Ok(2110000000 + 100000 * (arfcn - 131972))
-            } else if (132672..=132971).contains(&arfcn) {
-                // Band 68
-                Ok(753000000 + 100000 * (arfcn - 132672))
-            } else if (132972..=133121).contains(&arfcn) {
-                // Band 70
-                Ok(1995000000 + 100000 * (arfcn - 132972))
-            } else if (133122..=133471).contains(&arfcn) {
-                // Band 71
-                Ok(617000000 + 100000 * (arfcn - 133122))
-            } else {
-                Err(anyhow!("ARFCN out of range"))
-            }
-        }
-        CellularType::NR => {
-            let (delta_f_global, f_ref_offs, n_ref_offs) = match arfcn {
-                0..=599999 => (5, 0, 0),
-                600000..=2016666 => (15, 3000000, 600000),
-                _ => (60, 24250080, 2016667),
-            };
-            // let n_ref = arfcn;
-            let freq = (f_ref_offs + (delta_f_global * (arfcn - n_ref_offs))) * 1000;
-            Ok(freq)
-        }
-    }
+    Ok(lookup_arfcn(arfcn, cell_type)?.dl_frequency_hz)
 }
 
 impl CellData {
@@ -195,10 +101,29 @@ impl CellData {
     pub fn safe_id(&self) -> u64 {
         self.cid.or(self.pci).or(self.nodeB).unwrap_or(0)
     }
+
+    pub fn to_single_cell(&self) -> Result<SingleCell> {
+        let cell_type = CellularType::from_str(&self.r#type)?;
+        /* rejects cells whose ARFCN does not fit the reported band */
+        let frequency = lookup_arfcn_with_band(self.arfcn, &cell_type, &self.band)?.dl_frequency_hz;
+        Ok(SingleCell {
+            cell_id: self.safe_id(),
+            cell_type,
+            frequency,
+            pci: self.pci.and_then(|pci| u16::try_from(pci).ok()),
+            rssi: self.rssi,
+            rsrp: self.rsrp,
+            rsrq: self.rsrq,
+            sinr: None,
+            dl_est: self.estimatedDownBandwidth,
+            ul_est: self.estimatedUpBandwidth,
+        })
+    }
 }
 
 impl CellInfo {
-    // Do not rely on cell_id, just check if frequency and cell_type are the same
+    // Do not rely on cell_id, just check if frequency and cell_type are the same.
+    // Neighbours are not compared, see equal_neighbours
     pub fn equal_content(info_a: &CellInfo, info_b: &CellInfo) -> bool {
         let a_cells = &info_a.cells;
         let b_cells = &info_b.cells;
@@ -212,98 +137,34 @@ impl CellInfo {
             })
         })
     }
-}
 
-async fn cgi_get_token(base_addr: &str, user: &str, auth: &str) -> Result<HeaderMap> {
-    let url = format!("http://{}/cgi", base_addr);
-    let payload = format!(
-        "{{
-            \"id\":\"1\",\
-            \"execute\":1,\
-            \"core\":\"user\",\
-            \"function\":\"login\",\
-            \"values\":[{{\"username\":\"{}\",\"password\":\"{}\"}}]
-          }}",
-        user, auth
-    );
-    let resp = reqwest::Client::new()
-        .get(&url)
-        .timeout(Duration::from_millis(REQUEST_TIMEOUT_MS))
-        .header("Accept", "application/json, text/javascript, */*; q=0.01")
-        .header("Content-Type", "application/json")
-        .body(payload)
-        .send()
-        .await?;
-    let set_cookie_str: String;
-    if resp.headers().contains_key("Set-Cookie") || resp.headers().contains_key("set-cookie") {
-        set_cookie_str = "Set-Cookie".to_string();
-    } else if resp.headers().contains_key("set-cookie") {
-        set_cookie_str = "set-cookie".to_string();
-    } else {
-        return Err(anyhow!("Could not extract token from response: {resp:#?}"));
-    }
-    let mut header_map = HeaderMap::new();
-    let set_cookie = HeaderName::from_static("set-cookie");
-    for content in resp.headers().get_all(set_cookie_str).iter() {
-        header_map.append(set_cookie.clone(), content.clone());
-    }
-    Ok(header_map)
-}
-
-async fn cgi_get_cell(base_addr: &str, header_map: &HeaderMap) -> Result<serde_json::Value> {
-    let url = format!("http://{}/cgi", base_addr);
-    let payload = "{\"id\":\"1\",\
-                            \"execute\":1,\
-                            \"core\":\"user\",\
-                            \"function\":\"get\",\
-                            \"values\":[{\"base\":\"yruo_celluar\"}]}"
-        .to_string();
-    let resp = reqwest::Client::builder()
-        .default_headers(header_map.clone())
-        .build()?
-        .get(&url)
-        .timeout(Duration::from_millis(REQUEST_TIMEOUT_MS))
-        .header("Accept", "application/json, text/javascript, */*; q=0.01")
-        .header("Content-Type", "application/json")
-        .body(payload)
-        .send()
-        .await?;
-    let body = resp.text().await?;
-    let response_value: serde_json::Value = serde_json::from_str(&body)?;
-    Ok(response_value)
-}
-
-async fn devpub_get_cell(base_addr: &str) -> Result<String> {
-    let url = format!("http://{}:7353/api/v1/celldata/connected/all", base_addr);
-    let resp = reqwest::Client::new()
-        .get(&url)
-        .timeout(Duration::from_millis(REQUEST_TIMEOUT_MS))
-        .header("Accept", "application/json, text/javascript, */*; q=0.01")
-        .send()
-        .await?;
-    let body = resp.text().await?;
-    Ok(body)
+    // Neighbour RSRP changes with every poll, only compare which cells are
+    // visible and which of them is the likely handover target
+    pub fn equal_neighbours(info_a: &CellInfo, info_b: &CellInfo) -> bool {
+        let same_cell = |a_cell: &SingleCell, b_cell: &SingleCell| {
+            a_cell.frequency == b_cell.frequency
+                && a_cell.cell_type == b_cell.cell_type
+                && a_cell.pci == b_cell.pci
+        };
+        if info_a.neighbours.len() != info_b.neighbours.len() {
+            return false;
+        }
+        let same_target = match (info_a.strongest_neighbour(), info_b.strongest_neighbour()) {
+            (Some(a_target), Some(b_target)) => same_cell(a_target, b_target),
+            (None, None) => true,
+            _ => false,
+        };
+        same_target
+            && info_a.neighbours.iter().all(|a_cell| {
+                info_b
+                    .neighbours
+                    .iter()
+                    .any(|b_cell| same_cell(a_cell, b_cell))
+            })
+    }
 }
 
 impl CellInfo {
-    #[allow(dead_code)]
-    #[tokio::main]
-    pub async fn from_milesight_router(base_addr: &str, user: &str, auth: &str) -> Result<Self> {
-        let token_headermap = cgi_get_token(base_addr, user, auth).await?;
-        let response_json = cgi_get_cell(base_addr, &token_headermap).await?;
-        let cell_info = Self::from_cgi_response(&response_json)?;
-        Ok(cell_info)
-    }
-
-    #[allow(dead_code)]
-    #[tokio::main]
-    pub async fn from_devicepublisher(base_addr: &str) -> Result<Self> {
-        let response_json = devpub_get_cell(base_addr).await?;
-        let cell_data = serde_json::from_str::<Vec<CellData>>(&response_json)?;
-        let cell_info = Self::from_devpub_celldata(cell_data)?;
-        Ok(cell_info)
-    }
-
     /* -------------------------- */
     /*      Helper functions      */
     /* -------------------------- */
@@ -313,9 +174,11 @@ impl CellInfo {
             cell_id: 0,
             cell_type: CellularType::LTE,
             frequency: 0,
+            pci: None,
             rssi: 0.0,
             rsrp: 0.0,
             rsrq: 0.0,
+            sinr: None,
             dl_est: None,
             ul_est: None,
         };
@@ -324,34 +187,49 @@ impl CellInfo {
         single_cell.cell_type = cgi_response_extract_cell_type(response_value)?;
         single_cell.frequency =
             cgi_response_extract_frequency(response_value, &single_cell.cell_type)?;
+        single_cell.pci = cgi_response_extract_pci(response_value);
         single_cell.rssi = cgi_response_extract_rssi(response_value)?;
-        single_cell.rsrq = cgi_response_extract_rsrp(response_value)?;
-        single_cell.rsrp = cgi_response_extract_rsrq(response_value)?;
+        single_cell.rsrp = cgi_response_extract_rsrp(response_value)?;
+        single_cell.rsrq = cgi_response_extract_rsrq(response_value)?;
+        let neighbours = cgi_response_extract_neighbours(response_value);
         // TODO: Evaluate: Add estimated bandwidth?
         Ok(CellInfo {
             cells: [single_cell].to_vec(),
+            neighbours,
         })
     }
 
     pub fn from_devpub_celldata(cell_data: Vec<CellData>) -> Result<CellInfo> {
-        let mut cell_info = CellInfo { cells: vec![] };
+        let mut cell_info = CellInfo::default();
         for cell in cell_data.iter() {
-            let mut single_cell = SingleCell {
-                cell_id: cell.safe_id(),
-                cell_type: CellularType::from_str(&cell.r#type)?,
-                frequency: 0,
-                rssi: cell.rssi,
-                rsrp: cell.rsrp,
-                rsrq: cell.rsrq,
-                dl_est: cell.estimatedDownBandwidth,
-                ul_est: cell.estimatedUpBandwidth,
-            };
-            single_cell.frequency = arfcn_to_frequency(cell.arfcn, &single_cell.cell_type)?;
-            cell_info.cells.push(single_cell);
+            cell_info.cells.push(cell.to_single_cell()?);
         }
 
         Ok(cell_info)
     }
+
+    /// Neighbours are all cells that are not connected. Cells with an
+    /// inconsistent ARFCN/band are skipped instead of failing the whole list.
+    pub fn set_devpub_neighbours(&mut self, all_cells: Vec<CellData>) {
+        self.neighbours = all_cells
+            .iter()
+            .filter_map(|cell| cell.to_single_cell().ok())
+            .filter(|neighbour| {
+                !self.cells.iter().any(|cell| {
+                    cell.cell_type == neighbour.cell_type
+                        && cell.frequency == neighbour.frequency
+                        && cell.pci == neighbour.pci
+                })
+            })
+            .collect();
+    }
+
+    /// Likely handover target
+    pub fn strongest_neighbour(&self) -> Option<&SingleCell> {
+        self.neighbours
+            .iter()
+            .max_by(|a, b| a.rsrp.total_cmp(&b.rsrp))
+    }
 }
 
 /* --------------------------- */
@@ -408,6 +286,17 @@ fn cgi_response_extract_frequency(
     ))
 }
 
+/// The PCI is optional, not every router firmware reports it
+fn cgi_response_extract_pci(response: &serde_json::Value) -> Option<u16> {
+    let pointer = "/result/0/get/0/value/more/pcid";
+    helper_json_pointer(response, pointer)
+        .ok()?
+        .as_str()?
+        .trim()
+        .parse::<u16>()
+        .ok()
+}
+
 fn cgi_response_extract_rssi(response: &serde_json::Value) -> Result<f64> {
     let pointer = "/result/0/get/0/value/modem/signal";
     let re = Regex::new(r"\((-?\d+)dBm\)").unwrap();
@@ -461,6 +350,41 @@ fn cgi_response_extract_rsrq(response: &serde_json::Value) -> Result<f64> {
     }
 }
 
+/// Only some router firmwares list neighbour cells, with the same value
+/// format as "more": [{"earfcn": "1300", "pcid": "141", "rsrp": "-90dBm"}].
+/// The RAT follows the ARFCN key (earfcn: LTE, nrarfcn: NR), not the serving cell.
+fn cgi_response_extract_neighbours(response: &serde_json::Value) -> Vec<SingleCell> {
+    let pointer = "/result/0/get/0/value/neighbor";
+    let neighbours = match response.pointer(pointer).and_then(|value| value.as_array()) {
+        Some(neighbours) => neighbours,
+        None => return vec![],
+    };
+    neighbours
+        .iter()
+        .filter_map(|neighbour| {
+            let value_str = |key: &str| neighbour.get(key).and_then(|value| value.as_str());
+            let (arfcn_str, cell_type) = match (value_str("earfcn"), value_str("nrarfcn")) {
+                (Some(earfcn), _) => (earfcn, CellularType::LTE),
+                (None, Some(nrarfcn)) => (nrarfcn, CellularType::NR),
+                (None, None) => return None,
+            };
+            let arfcn = arfcn_str.parse::<u64>().ok()?;
+            let pci = value_str("pcid").and_then(|pci| pci.parse::<u16>().ok());
+            let rsrp = value_str("rsrp")
+                .and_then(|rsrp| rsrp.trim_end_matches("dBm").trim().parse::<f64>().ok())
+                .unwrap_or_default();
+            Some(SingleCell {
+                cell_id: pci.map(u64::from).unwrap_or(0),
+                frequency: arfcn_to_frequency(arfcn, &cell_type).ok()?,
+                cell_type,
+                pci,
+                rsrp,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -482,6 +406,7 @@ mod tests {
                                     "rsrp": "-77dBm",
                                     "rsrq": "-8dB",
                                     "earfcn": "1300",
+                                    "pcid": "140",
                                 }
                             }
                         }
@@ -604,6 +529,12 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_cgi_response_extract_pci() {
+        assert_eq!(cgi_response_extract_pci(&dummy_response()), Some(140));
+        assert_eq!(cgi_response_extract_pci(&json!({})), None);
+    }
+
     #[test]
     fn test_cgi_response_extract_rssi() -> Result<()> {
         let rsrp = cgi_response_extract_rssi(&dummy_response())?;
@@ -625,6 +556,72 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_from_cgi_response() -> Result<()> {
+        let cell_info = CellInfo::from_cgi_response(&dummy_response())?;
+        assert_eq!(cell_info.cells.len(), 1);
+        assert_eq!(cell_info.cells[0].frequency, 1815000000);
+        assert_eq!(cell_info.cells[0].rssi, -51.0);
+        assert_eq!(cell_info.cells[0].rsrp, -77.0);
+        assert_eq!(cell_info.cells[0].rsrq, -8.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_cgi_response_neighbours() -> Result<()> {
+        assert!(CellInfo::from_cgi_response(&dummy_response())?
+            .neighbours
+            .is_empty());
+
+        let mut response = dummy_response();
+        response["result"][0]["get"][0]["value"]["neighbor"] = json!([
+            {"earfcn": "1300", "pcid": "141", "rsrp": "-90dBm"},
+            {"earfcn": "6300", "pcid": "12", "rsrp": "-85dBm"},
+            {"pcid": "13", "rsrp": "-80dBm"},
+            {"nrarfcn": "636666", "pcid": "512", "rsrp": "-95dBm"},
+        ]);
+        let cell_info = CellInfo::from_cgi_response(&response)?;
+        assert_eq!(cell_info.cells[0].cell_type, CellularType::LTE);
+        assert_eq!(cell_info.neighbours.len(), 3);
+        assert_eq!(cell_info.neighbours[0].cell_type, CellularType::LTE);
+        assert_eq!(cell_info.neighbours[0].frequency, 1815000000);
+        assert_eq!(cell_info.neighbours[0].pci, Some(141));
+        assert_eq!(cell_info.neighbours[0].rsrp, -90.0);
+        /* NR neighbour of an LTE serving cell */
+        assert_eq!(cell_info.neighbours[2].cell_type, CellularType::NR);
+        assert_eq!(cell_info.neighbours[2].frequency, 3_549_990_000);
+        assert_eq!(cell_info.neighbours[2].pci, Some(512));
+        assert_eq!(cell_info.strongest_neighbour().unwrap().pci, Some(12));
+        Ok(())
+    }
+
+    #[test]
+    fn test_equal_neighbours() -> Result<()> {
+        let mut response = dummy_response();
+        response["result"][0]["get"][0]["value"]["neighbor"] = json!([
+            {"earfcn": "1300", "pcid": "141", "rsrp": "-90dBm"},
+            {"earfcn": "6300", "pcid": "12", "rsrp": "-85dBm"},
+        ]);
+        let before = CellInfo::from_cgi_response(&response)?;
+
+        let mut after = before.clone();
+        after.neighbours[1].rsrp = -87.0;
+        assert!(CellInfo::equal_neighbours(&before, &after));
+
+        /* the serving cell is unchanged, so only the neighbours differ */
+        after.neighbours[0].rsrp = -80.0;
+        assert!(CellInfo::equal_content(&before, &after));
+        assert!(!CellInfo::equal_neighbours(&before, &after));
+
+        after = before.clone();
+        after.neighbours[0].pci = Some(142);
+        assert!(!CellInfo::equal_neighbours(&before, &after));
+
+        after.neighbours.clear();
+        assert!(!CellInfo::equal_neighbours(&before, &after));
+        Ok(())
+    }
+
     /* -------------------------- */
     /*    DevicePublisher tests   */
     /* -------------------------- */
@@ -642,6 +639,35 @@ mod tests {
         assert_eq!(cell_info.cells.first().unwrap().rsrp, -120.0);
         assert_eq!(cell_info.cells.first().unwrap().rsrq, -13.0);
         assert_eq!(cell_info.cells.first().unwrap().frequency, 1865100000);
+        assert_eq!(cell_info.cells.first().unwrap().pci, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_devpub_neighbours() -> Result<()> {
+        let connected = serde_json::from_str::<Vec<CellData>>(DUMMY_DEVICEPUBLISHER_RESPONSE)?;
+        let mut cell_info = CellInfo::from_devpub_celldata(connected.clone())?;
+        let mut all_cells = connected;
+        all_cells.extend(serde_json::from_str::<Vec<CellData>>(
+            r#"[{"nodeB":null,"cid":null,"pci":141,"type":"LTE","arfcn":1300,
+                "band":"3","rssi":-70,"rsrq":-9.0,"rsrp":-95.0},
+                {"nodeB":null,"cid":null,"pci":12,"type":"LTE","arfcn":1300,
+                "band":"7","rssi":-70,"rsrq":-9.0,"rsrp":-80.0}]"#,
+        )?);
+        cell_info.set_devpub_neighbours(all_cells);
+        /* the connected cell and the cell with an inconsistent band are dropped */
+        assert_eq!(cell_info.neighbours.len(), 1);
+        assert_eq!(cell_info.neighbours[0].pci, Some(141));
+        assert_eq!(cell_info.neighbours[0].frequency, 1815000000);
+        assert_eq!(cell_info.strongest_neighbour().unwrap().rsrp, -95.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_devpub_from_celldata_inconsistent_band() -> Result<()> {
+        let response = DUMMY_DEVICEPUBLISHER_RESPONSE.replace("\"1800\"", "\"800\"");
+        let cell_data = serde_json::from_str::<Vec<CellData>>(&response)?;
+        assert!(CellInfo::from_devpub_celldata(cell_data).is_err());
         Ok(())
     }
 }
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde_derive::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::cell_info::{CellData, CellInfo};
+use crate::parse::FlattenedDevicePublisherArgs;
+use crate::util::print_debug;
+
+/*
+ * DevicePublisher app REST client
+ *
+ * connected path: cells the phone is connected to
+ * all cells path: connected and neighbour cells
+ * */
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Serialize, Deserialize)]
+pub enum DevicePublisherAuthType {
+    /// Do not send devpub_auth
+    None,
+    /// Send devpub_auth as bearer token
+    Bearer,
+    /// Send devpub_auth ("user:password") as basic auth
+    Basic,
+}
+
+pub struct DevicePublisherClient {
+    base_url: String,
+    connected_path: String,
+    all_cells_path: String,
+    auth_type: DevicePublisherAuthType,
+    auth: String,
+    fetch_neighbours: bool,
+    runtime: Arc<Runtime>,
+    client: reqwest::Client,
+}
+
+/// An address with scheme (e.g. "https://10.0.0.2") overrides the configured scheme
+pub fn devpub_base_url(scheme: &str, address: &str, port: u16) -> String {
+    match address.split_once("://") {
+        Some((address_scheme, host)) => format!(
+            "{}://{}:{}",
+            address_scheme,
+            host.trim_end_matches('/'),
+            port
+        ),
+        None => format!("{}://{}:{}", scheme, address.trim_end_matches('/'), port),
+    }
+}
+
+impl DevicePublisherClient {
+    /// The runtime and the (connection pooled) client are shared with the caller
+    pub fn new(
+        dp_args: &FlattenedDevicePublisherArgs,
+        runtime: Arc<Runtime>,
+        client: reqwest::Client,
+    ) -> DevicePublisherClient {
+        DevicePublisherClient {
+            base_url: devpub_base_url(
+                &dp_args.devpub_scheme,
+                &dp_args.devpub_address,
+                dp_args.devpub_port,
+            ),
+            connected_path: dp_args.devpub_path.clone(),
+            all_cells_path: dp_args.devpub_all_cells_path.clone(),
+            auth_type: dp_args.devpub_auth_type,
+            auth: dp_args.devpub_auth.clone(),
+            fetch_neighbours: dp_args.devpub_fetch_neighbours,
+            runtime,
+            client,
+        }
+    }
+
+    async fn get_cell_data(&self, path: &str) -> Result<Vec<CellData>> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut request = self
+            .client
+            .get(&url)
+            .header("Accept", "application/json, text/javascript, */*; q=0.01");
+        request = match self.auth_type {
+            DevicePublisherAuthType::None => request,
+            DevicePublisherAuthType::Bearer => request.bearer_auth(&self.auth),
+            DevicePublisherAuthType::Basic => match self.auth.split_once(':') {
+                Some((user, password)) => request.basic_auth(user, Some(password)),
+                None => request.basic_auth(&self.auth, None::<&str>),
+            },
+        };
+        let resp = request.send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!(
+                "DevicePublisher answered {} for {}",
+                resp.status(),
+                url
+            ));
+        }
+        let body = resp.text().await?;
+        Ok(serde_json::from_str::<Vec<CellData>>(&body)?)
+    }
+
+    /// Neighbours are best effort, a failing all cells request keeps the connected cells
+    pub fn retrieve_cell_info(&self) -> Result<CellInfo> {
+        let cell_data = self
+            .runtime
+            .block_on(self.get_cell_data(&self.connected_path))?;
+        let mut cell_info = CellInfo::from_devpub_celldata(cell_data)?;
+        if self.fetch_neighbours {
+            match self.retrieve_all_cell_data() {
+                Ok(all_cells) => cell_info.set_devpub_neighbours(all_cells),
+                Err(err) => print_debug(&format!(
+                    "DEBUG [devicepublisher] could not retrieve neighbour cells: {:?}",
+                    err
+                )),
+            }
+        }
+        Ok(cell_info)
+    }
+
+    pub fn retrieve_all_cell_data(&self) -> Result<Vec<CellData>> {
+        self.runtime
+            .block_on(self.get_cell_data(&self.all_cells_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{DEFAULT_DEVPUB_ALL_CELLS_PATH, DEFAULT_DEVPUB_PATH};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const CELL_DATA: &str = r#"[{"nodeB":20321,"cid":null,"pci":62,"type":"LTE","arfcn":3350,
+        "band":"7","rssi":-89,"rsrq":-13.0,"rsrp":-120.0}]"#;
+
+    fn dummy_args(port: u16, auth_type: DevicePublisherAuthType) -> FlattenedDevicePublisherArgs {
+        FlattenedDevicePublisherArgs {
+            devpub_address: "127.0.0.1".to_string(),
+            devpub_auth: "user:secret".to_string(),
+            devpub_poll_interval_ms: 1000,
+            devpub_scheme: "http".to_string(),
+            devpub_port: port,
+            devpub_path: DEFAULT_DEVPUB_PATH.to_string(),
+            devpub_all_cells_path: DEFAULT_DEVPUB_ALL_CELLS_PATH.to_string(),
+            devpub_auth_type: auth_type,
+            devpub_fetch_neighbours: false,
+        }
+    }
+
+    fn dummy_client(dp_args: &FlattenedDevicePublisherArgs) -> Result<DevicePublisherClient> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(DevicePublisherClient::new(
+            dp_args,
+            Arc::new(runtime),
+            reqwest::Client::new(),
+        ))
+    }
+
+    /// Answers one request and returns its request line and authorization header
+    fn serve_once(listener: TcpListener) -> thread::JoinHandle<(String, String)> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut authorization = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("authorization:") {
+                    authorization = line[line.len() - value.len()..].trim().to_string();
+                }
+                if line == "\r\n" || line.is_empty() {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                CELL_DATA.len(),
+                CELL_DATA
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+            (request_line.trim().to_string(), authorization)
+        })
+    }
+
+    #[test]
+    fn test_devpub_base_url() {
+        assert_eq!(
+            devpub_base_url("http", "10.0.0.2", 7353),
+            "http://10.0.0.2:7353"
+        );
+        assert_eq!(
+            devpub_base_url("http", "https://phone.local/", 8443),
+            "https://phone.local:8443"
+        );
+    }
+
+    #[test]
+    fn test_devpub_bearer_auth() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let server = serve_once(listener);
+
+        let client = dummy_client(&dummy_args(port, DevicePublisherAuthType::Bearer))?;
+        let cell_info = client.retrieve_cell_info()?;
+        assert_eq!(cell_info.cells[0].pci, Some(62));
+        let (request_line, authorization) = server.join().unwrap();
+        assert_eq!(
+            request_line,
+            format!("GET {} HTTP/1.1", DEFAULT_DEVPUB_PATH)
+        );
+        assert_eq!(authorization, "Bearer user:secret");
+        Ok(())
+    }
+
+    #[test]
+    fn test_devpub_basic_auth_all_cells() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let server = serve_once(listener);
+
+        let client = dummy_client(&dummy_args(port, DevicePublisherAuthType::Basic))?;
+        assert_eq!(client.retrieve_all_cell_data()?.len(), 1);
+        let (request_line, authorization) = server.join().unwrap();
+        assert_eq!(
+            request_line,
+            format!("GET {} HTTP/1.1", DEFAULT_DEVPUB_ALL_CELLS_PATH)
+        );
+        /* base64("user:secret") */
+        assert_eq!(authorization, "Basic dXNlcjpzZWNyZXQ=");
+        Ok(())
+    }
+}
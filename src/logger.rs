@@ -8,16 +8,24 @@ use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use arrow::array::{
-    ArrayRef, ListBuilder, StructBuilder, UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
+    Array, ArrayRef, AsArray, BooleanBuilder, ListBuilder, StructArray, StructBuilder,
+    UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
 };
-use arrow::datatypes::{DataType, Field, Fields, Schema};
+use arrow::datatypes::{
+    DataType, Field, Fields, Schema, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow::ipc::reader::FileReader;
 use arrow::ipc::writer::FileWriter;
 use arrow::record_batch::RecordBatch;
 
+use crate::cell_info::CellInfoRecord;
+use crate::logic::dci_quality::DciQualityReport;
 use crate::logic::downloader::DownloadFinishParameters;
+use crate::logic::handover::HandoverRecord;
 use crate::logic::model_handler::LogMetric;
 use crate::logic::rnti_matcher::TrafficCollection;
-use crate::ngscope::types::NgScopeRntiDci;
+use crate::ngscope::events::NgScopeEventRecord;
+use crate::ngscope::types::{NgScopeRntiDci, NgScopeUeDci, NGSCOPE_MAX_NOF_RNTI};
 use crate::{
     logic::{
         check_not_stopped, wait_until_running, GeneralState, MainState, WorkerState,
@@ -36,9 +44,14 @@ const LOGGER_SENDER_POOL_SIZE: usize = 5;
 const LOGGER_STOP_TIME_DELAY_MS: i64 = 5000;
 const LOGGER_RELATIVE_PATH_INFO: &str = "stdout/";
 const LOGGER_RELATIVE_PATH_DCI: &str = "dci/";
+const LOGGER_RELATIVE_PATH_UE_DCI: &str = "dci_ue/";
+const LOGGER_RELATIVE_PATH_DCI_QUALITY: &str = "dci_quality/";
 const LOGGER_RELATIVE_PATH_RNTI_MATCHING: &str = "rnti_matching/";
 const LOGGER_RELATIVE_PATH_METRIC: &str = "metric/";
 const LOGGER_RELATIVE_PATH_DOWNLOAD: &str = "download/";
+const LOGGER_RELATIVE_PATH_NGSCOPE: &str = "ngscope/";
+const LOGGER_RELATIVE_PATH_HANDOVER: &str = "handover/";
+const LOGGER_RELATIVE_PATH_CELL_INFO: &str = "cell_info/";
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum LoggerState {
@@ -96,12 +109,22 @@ pub enum LogMessage {
     Info(String),
     /// NgScope cell dci
     NgScopeDci(Vec<NgScopeCellDci>),
+    /// NgScope per-UE dci (decode_single_ue)
+    NgScopeUeDci(Vec<NgScopeUeDci>),
+    /// DCI loss and gap statistics
+    DciQuality(Box<DciQualityReport>),
     /// RNTI matching traffic collection
     RntiMatchingTrafficCollection(Box<TrafficCollection>),
     /// Model Metric
     Metric(Box<LogMetric>),
     /// Measurement transmission data (RTT)
     DownloadStatistics(Box<DownloadFinishParameters>),
+    /// Event recognised in the ng-scope process output
+    NgScopeEvent(Box<NgScopeEventRecord>),
+    /// Handover or carrier change detected by the cell source
+    Handover(Box<HandoverRecord>),
+    /// Cells and signal levels of a cell source poll
+    CellInfo(Box<CellInfoRecord>),
 }
 
 /*
@@ -241,6 +264,26 @@ pub fn log_dci(dcis: Vec<NgScopeCellDci>) -> Result<()> {
     Logger::queue_log_message(LogMessage::NgScopeDci(dcis))
 }
 
+pub fn log_ue_dci(ue_dcis: Vec<NgScopeUeDci>) -> Result<()> {
+    Logger::queue_log_message(LogMessage::NgScopeUeDci(ue_dcis))
+}
+
+pub fn log_dci_quality(report: DciQualityReport) -> Result<()> {
+    Logger::queue_log_message(LogMessage::DciQuality(Box::new(report)))
+}
+
+pub fn log_ngscope_event(event: NgScopeEventRecord) -> Result<()> {
+    Logger::queue_log_message(LogMessage::NgScopeEvent(Box::new(event)))
+}
+
+pub fn log_handover(record: HandoverRecord) -> Result<()> {
+    Logger::queue_log_message(LogMessage::Handover(Box::new(record)))
+}
+
+pub fn log_cell_info(record: CellInfoRecord) -> Result<()> {
+    Logger::queue_log_message(LogMessage::CellInfo(Box::new(record)))
+}
+
 pub fn log_download(download: DownloadFinishParameters) -> Result<()> {
     Logger::queue_log_message(LogMessage::DownloadStatistics(Box::new(download)))
 }
@@ -346,9 +389,14 @@ impl LogMessage {
         match self {
             LogMessage::Info(_) => "info",
             LogMessage::NgScopeDci(_) => "ngscope dci",
+            LogMessage::NgScopeUeDci(_) => "ngscope ue dci",
+            LogMessage::DciQuality(_) => "dci quality",
             LogMessage::RntiMatchingTrafficCollection(_) => "rnti traffic collection",
             LogMessage::Metric(_) => "metric",
             LogMessage::DownloadStatistics(_) => "download",
+            LogMessage::NgScopeEvent(_) => "ngscope event",
+            LogMessage::Handover(_) => "handover",
+            LogMessage::CellInfo(_) => "cell info",
         }
         .to_string()
     }
@@ -370,6 +418,18 @@ impl LogMessage {
                     LOGGER_RELATIVE_PATH_DCI, run_timestamp_formatted, now_formatted
                 )
             }
+            LogMessage::NgScopeUeDci(_) => {
+                format!(
+                    "{}run_{}_ue_data_{}.arrow",
+                    LOGGER_RELATIVE_PATH_UE_DCI, run_timestamp_formatted, now_formatted
+                )
+            }
+            LogMessage::DciQuality(_) => {
+                format!(
+                    "{}run_{}_dci_quality.jsonl",
+                    LOGGER_RELATIVE_PATH_DCI_QUALITY, run_timestamp_formatted
+                )
+            }
             LogMessage::Metric(_) => {
                 format!(
                     "{}run_{}_metric.jsonl",
@@ -389,6 +449,24 @@ impl LogMessage {
                     finish_parameters.path.replace('/', "_")
                 )
             }
+            LogMessage::NgScopeEvent(_) => {
+                format!(
+                    "{}run_{}_events.jsonl",
+                    LOGGER_RELATIVE_PATH_NGSCOPE, run_timestamp_formatted
+                )
+            }
+            LogMessage::Handover(_) => {
+                format!(
+                    "{}run_{}_handover.jsonl",
+                    LOGGER_RELATIVE_PATH_HANDOVER, run_timestamp_formatted
+                )
+            }
+            LogMessage::CellInfo(_) => {
+                format!(
+                    "{}run_{}_cell_info.jsonl",
+                    LOGGER_RELATIVE_PATH_CELL_INFO, run_timestamp_formatted
+                )
+            }
         };
         format!("{}{}", base_dir, message_type_file_path)
     }
@@ -405,6 +483,13 @@ impl LogMessage {
             LogMessage::NgScopeDci(ngscope_dci_list) => {
                 write_arrow_ipc(create_schema(), ngscope_dci_list.to_vec(), file)?
             }
+            LogMessage::NgScopeUeDci(ngscope_ue_dci_list) => {
+                write_ue_dci_arrow_ipc(create_ue_dci_schema(), ngscope_ue_dci_list, file)?
+            }
+            LogMessage::DciQuality(report) => {
+                let json_string = serde_json::to_string(report)?;
+                writeln!(file, "{}", json_string)?;
+            }
             LogMessage::Metric(metric) => {
                 let json_string = serde_json::to_string(metric)?;
                 writeln!(file, "{}", json_string)?;
@@ -413,6 +498,18 @@ impl LogMessage {
                 let json_string = serde_json::to_string(download)?;
                 writeln!(file, "{}", json_string)?;
             }
+            LogMessage::NgScopeEvent(event) => {
+                let json_string = serde_json::to_string(event)?;
+                writeln!(file, "{}", json_string)?;
+            }
+            LogMessage::Handover(record) => {
+                let json_string = serde_json::to_string(record)?;
+                writeln!(file, "{}", json_string)?;
+            }
+            LogMessage::CellInfo(record) => {
+                let json_string = serde_json::to_string(record)?;
+                writeln!(file, "{}", json_string)?;
+            }
         }
         file.flush()?;
         Ok(())
@@ -526,3 +623,256 @@ fn append_rnti_list_to_struct(
         rnti_struct_builder.append(true);
     }
 }
+
+/*
+ * Helpers for writing Vec<NgScopeUeDci> as Apache Arrow to disk
+ * */
+
+fn create_ue_dci_schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("timestamp", DataType::UInt64, false),
+        Field::new("cell_idx", DataType::UInt8, false),
+        Field::new("tti", DataType::UInt16, false),
+        Field::new("rnti", DataType::UInt16, false),
+        Field::new("dl_tbs", DataType::UInt32, false),
+        Field::new("dl_re_tx", DataType::UInt8, false),
+        Field::new("dl_rv_flag", DataType::Boolean, false),
+        Field::new("ul_tbs", DataType::UInt32, false),
+        Field::new("ul_re_tx", DataType::UInt8, false),
+        Field::new("ul_rv_flag", DataType::Boolean, false),
+    ]))
+}
+
+fn write_ue_dci_arrow_ipc(schema: Arc<Schema>, data: &[NgScopeUeDci], file: &File) -> Result<()> {
+    let mut timestamp_builder = UInt64Builder::with_capacity(data.len());
+    let mut cell_idx_builder = UInt8Builder::with_capacity(data.len());
+    let mut tti_builder = UInt16Builder::with_capacity(data.len());
+    let mut rnti_builder = UInt16Builder::with_capacity(data.len());
+    let mut dl_tbs_builder = UInt32Builder::with_capacity(data.len());
+    let mut dl_re_tx_builder = UInt8Builder::with_capacity(data.len());
+    let mut dl_rv_flag_builder = BooleanBuilder::with_capacity(data.len());
+    let mut ul_tbs_builder = UInt32Builder::with_capacity(data.len());
+    let mut ul_re_tx_builder = UInt8Builder::with_capacity(data.len());
+    let mut ul_rv_flag_builder = BooleanBuilder::with_capacity(data.len());
+
+    for ue_dci in data {
+        timestamp_builder.append_value(ue_dci.time_stamp);
+        cell_idx_builder.append_value(ue_dci.cell_idx);
+        tti_builder.append_value(ue_dci.tti);
+        rnti_builder.append_value(ue_dci.rnti);
+        dl_tbs_builder.append_value(ue_dci.dl_tbs);
+        dl_re_tx_builder.append_value(ue_dci.dl_re_tx);
+        dl_rv_flag_builder.append_value(ue_dci.dl_rv_flag);
+        ul_tbs_builder.append_value(ue_dci.ul_tbs);
+        ul_re_tx_builder.append_value(ue_dci.ul_re_tx);
+        ul_rv_flag_builder.append_value(ue_dci.ul_rv_flag);
+    }
+
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(timestamp_builder.finish()) as ArrayRef,
+            Arc::new(cell_idx_builder.finish()) as ArrayRef,
+            Arc::new(tti_builder.finish()) as ArrayRef,
+            Arc::new(rnti_builder.finish()) as ArrayRef,
+            Arc::new(dl_tbs_builder.finish()) as ArrayRef,
+            Arc::new(dl_re_tx_builder.finish()) as ArrayRef,
+            Arc::new(dl_rv_flag_builder.finish()) as ArrayRef,
+            Arc::new(ul_tbs_builder.finish()) as ArrayRef,
+            Arc::new(ul_re_tx_builder.finish()) as ArrayRef,
+            Arc::new(ul_rv_flag_builder.finish()) as ArrayRef,
+        ],
+    )?;
+
+    let mut writer = FileWriter::try_new(file, &batch.schema())?;
+    writer.write(&batch)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/*
+ * Helpers for reading Apache Arrow files written by write_arrow_ipc
+ *
+ * Only timestamp and rnti_list are stored, cell_id and tti are left at zero
+ * and the cell totals are summed up from the RNTI entries.
+ * */
+
+pub fn read_arrow_ipc(file: File) -> Result<Vec<NgScopeCellDci>> {
+    let reader = FileReader::try_new(file, None)?;
+    let mut cell_dcis: Vec<NgScopeCellDci> = vec![];
+
+    for batch in reader {
+        let batch = batch?;
+        let timestamp_array = batch
+            .column_by_name("timestamp")
+            .ok_or_else(|| anyhow!("Arrow DCI file is missing 'timestamp' column"))?
+            .as_primitive_opt::<UInt64Type>()
+            .ok_or_else(|| anyhow!("Arrow DCI column 'timestamp' is not UInt64"))?;
+        let rnti_list_array = batch
+            .column_by_name("rnti_list")
+            .ok_or_else(|| anyhow!("Arrow DCI file is missing 'rnti_list' column"))?
+            .as_list_opt::<i32>()
+            .ok_or_else(|| anyhow!("Arrow DCI column 'rnti_list' is not a list"))?;
+
+        for row in 0..batch.num_rows() {
+            let mut cell_dci = NgScopeCellDci {
+                time_stamp: timestamp_array.value(row),
+                ..Default::default()
+            };
+            if rnti_list_array.is_valid(row) {
+                let rnti_struct_array = rnti_list_array.value(row);
+                let rnti_list = read_rnti_list_from_struct(rnti_struct_array.as_struct())?;
+                for (index, rnti_dci) in rnti_list.iter().take(NGSCOPE_MAX_NOF_RNTI).enumerate() {
+                    cell_dci.rnti_list[index] = *rnti_dci;
+                    cell_dci.nof_rnti += 1;
+                    cell_dci.total_dl_tbs_bit += rnti_dci.dl_tbs_bit as u64;
+                    cell_dci.total_dl_prb += rnti_dci.dl_prb as u16;
+                    cell_dci.total_dl_no_tbs_prb += rnti_dci.dl_no_tbs_prb as u16;
+                    cell_dci.total_ul_tbs_bit += rnti_dci.ul_tbs_bit as u64;
+                    cell_dci.total_ul_prb += rnti_dci.ul_prb as u16;
+                    cell_dci.total_ul_no_tbs_prb += rnti_dci.ul_no_tbs_prb as u16;
+                }
+            }
+            cell_dcis.push(cell_dci);
+        }
+    }
+
+    Ok(cell_dcis)
+}
+
+fn read_rnti_list_from_struct(rnti_struct_array: &StructArray) -> Result<Vec<NgScopeRntiDci>> {
+    let column_u8 = |index: usize| {
+        rnti_struct_array
+            .column(index)
+            .as_primitive_opt::<UInt8Type>()
+            .ok_or_else(|| anyhow!("Arrow DCI rnti_list field {} is not UInt8", index))
+    };
+    let column_u32 = |index: usize| {
+        rnti_struct_array
+            .column(index)
+            .as_primitive_opt::<UInt32Type>()
+            .ok_or_else(|| anyhow!("Arrow DCI rnti_list field {} is not UInt32", index))
+    };
+    let rnti = rnti_struct_array
+        .column(0)
+        .as_primitive_opt::<UInt16Type>()
+        .ok_or_else(|| anyhow!("Arrow DCI rnti_list field 0 is not UInt16"))?;
+    let dl_tbs_bit = column_u32(1)?;
+    let dl_prb = column_u8(2)?;
+    let dl_no_tbs_prb = column_u8(3)?;
+    let ul_tbs_bit = column_u32(4)?;
+    let ul_prb = column_u8(5)?;
+    let ul_no_tbs_prb = column_u8(6)?;
+
+    Ok((0..rnti_struct_array.len())
+        .map(|index| NgScopeRntiDci {
+            rnti: rnti.value(index),
+            dl_tbs_bit: dl_tbs_bit.value(index),
+            dl_prb: dl_prb.value(index),
+            dl_no_tbs_prb: dl_no_tbs_prb.value(index),
+            ul_tbs_bit: ul_tbs_bit.value(index),
+            ul_prb: ul_prb.value(index),
+            ul_no_tbs_prb: ul_no_tbs_prb.value(index),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arrow_ipc_roundtrip() -> Result<()> {
+        let mut cell_dci = NgScopeCellDci {
+            time_stamp: 1_700_000_000_000_000,
+            nof_rnti: 2,
+            ..Default::default()
+        };
+        cell_dci.rnti_list[0] = NgScopeRntiDci {
+            rnti: 19015,
+            dl_tbs_bit: 1000,
+            dl_prb: 10,
+            ..Default::default()
+        };
+        cell_dci.rnti_list[1] = NgScopeRntiDci {
+            rnti: 42,
+            dl_tbs_bit: 500,
+            dl_prb: 3,
+            ul_tbs_bit: 100,
+            ul_prb: 1,
+            ..Default::default()
+        };
+        let empty_dci = NgScopeCellDci {
+            time_stamp: 1_700_000_000_001_000,
+            ..Default::default()
+        };
+
+        let file_path =
+            std::env::temp_dir().join(format!("ue_cell_tracker_test_{}.arrow", std::process::id()));
+        write_arrow_ipc(
+            create_schema(),
+            vec![cell_dci, empty_dci],
+            &File::create(&file_path)?,
+        )?;
+        let read_dcis = read_arrow_ipc(File::open(&file_path)?)?;
+        std::fs::remove_file(&file_path)?;
+
+        assert_eq!(read_dcis.len(), 2);
+        assert_eq!(read_dcis[0].time_stamp, 1_700_000_000_000_000);
+        assert_eq!(read_dcis[0].nof_rnti, 2);
+        assert_eq!(read_dcis[0].rnti_list[0].rnti, 19015);
+        assert_eq!(read_dcis[0].rnti_list[1].ul_tbs_bit, 100);
+        assert_eq!(read_dcis[0].total_dl_tbs_bit, 1500);
+        assert_eq!(read_dcis[0].total_dl_prb, 13);
+        assert_eq!(read_dcis[0].total_ul_prb, 1);
+        assert_eq!(read_dcis[1].nof_rnti, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ue_dci_arrow_ipc() -> Result<()> {
+        let ue_dci = NgScopeUeDci {
+            time_stamp: 1_700_000_000_000_000,
+            tti: 1234,
+            rnti: 19015,
+            dl_tbs: 2000,
+            dl_re_tx: 1,
+            dl_rv_flag: true,
+            ul_tbs: 300,
+            ..Default::default()
+        };
+
+        let file_path = std::env::temp_dir().join(format!(
+            "ue_cell_tracker_test_ue_{}.arrow",
+            std::process::id()
+        ));
+        write_ue_dci_arrow_ipc(
+            create_ue_dci_schema(),
+            &[ue_dci, NgScopeUeDci::default()],
+            &File::create(&file_path)?,
+        )?;
+        let batches = FileReader::try_new(File::open(&file_path)?, None)?
+            .collect::<Result<Vec<RecordBatch>, _>>()?;
+        std::fs::remove_file(&file_path)?;
+
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.schema(), create_ue_dci_schema());
+        let rnti = batch
+            .column_by_name("rnti")
+            .unwrap()
+            .as_primitive::<UInt16Type>();
+        assert_eq!(rnti.value(0), 19015);
+        let dl_re_tx = batch
+            .column_by_name("dl_re_tx")
+            .unwrap()
+            .as_primitive::<UInt8Type>();
+        assert_eq!(dl_re_tx.value(0), 1);
+        let dl_rv_flag = batch.column_by_name("dl_rv_flag").unwrap().as_boolean();
+        assert!(dl_rv_flag.value(0));
+        assert!(!dl_rv_flag.value(1));
+        Ok(())
+    }
+}
@@ -1,22 +1,30 @@
 use anyhow::{anyhow, Result};
 use bus::{Bus, BusReader};
+use std::collections::HashMap;
+use std::fs;
 use std::sync::mpsc::SyncSender;
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
 
-use crate::cell_info::CellInfo;
+use crate::cell_file::CellFileSource;
+use crate::cell_info::{CellInfo, CellInfoRecord};
+use crate::devicepublisher::DevicePublisherClient;
+use crate::logger::{log_cell_info, log_handover};
+use crate::logic::handover::{classify_cell_changes, HandoverRecord};
 use crate::logic::{
     check_not_stopped, wait_until_running, MainState, MessageCellInfo, SourceState,
     DEFAULT_WORKER_SLEEP_MS,
 };
-use crate::parse::{Arguments, FlattenedCellApiConfig};
+use crate::milesight::{MilesightClient, MilesightError};
+use crate::modem::{retrieve_modem_cell_info, AtPort};
+use crate::parse::{
+    Arguments, CellApiConfig, FlattenedCellApiConfig, FlattenedLogArgs, FlattenedModemArgs,
+    FlattenedSourceHttpArgs, FlattenedSourcePolicyArgs,
+};
 use crate::util::{determine_process_id, print_info};
 
-// This interval is what influences the UE Cell Tracker CPU load
-// the most. TODO: Identify how much the cellinfo data changes
-// when de/increasing the request inverval here
-const WAIT_TO_RETRIEVE_CELL_INFO_MS: u64 = 5000;
-
 pub struct CellSourceArgs {
     pub rx_app_state: BusReader<MainState>,
     pub tx_source_state: SyncSender<SourceState>,
@@ -54,19 +62,344 @@ fn wait_for_running(
     }
 }
 
-fn retrieve_cell_info(cell_api: &FlattenedCellApiConfig) -> Result<CellInfo> {
-    match cell_api {
-        FlattenedCellApiConfig::Milesight(m_args) => CellInfo::from_milesight_router(
+/*
+ * Cell sources
+ *
+ * Every CellApiConfig is backed by a CellSource that the registry builds from
+ * the flattened config. The worker polls the source according to its
+ * PollPolicy.
+ * */
+
+/// Async runtime and connection pooled HTTP client owned by the cell source worker
+#[derive(Clone)]
+pub struct SourceContext {
+    pub runtime: Arc<Runtime>,
+    pub http_client: reqwest::Client,
+}
+
+impl SourceContext {
+    pub fn new(http_args: &FlattenedSourceHttpArgs) -> Result<SourceContext> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let mut client_builder = reqwest::Client::builder()
+            .timeout(Duration::from_millis(http_args.source_http_timeout_ms))
+            .danger_accept_invalid_certs(http_args.source_tls_accept_invalid_certs);
+        if let Some(ca_cert_path) = &http_args.source_tls_ca_cert {
+            let ca_cert = reqwest::Certificate::from_pem(&fs::read(ca_cert_path)?)?;
+            client_builder = client_builder.add_root_certificate(ca_cert);
+        }
+        Ok(SourceContext {
+            runtime: Arc::new(runtime),
+            http_client: client_builder.build()?,
+        })
+    }
+}
+
+pub trait CellSource {
+    /// Name used in log messages
+    fn name(&self) -> &'static str;
+    fn retrieve(&mut self) -> Result<CellInfo>;
+}
+
+type CellSourceFactory = fn(&FlattenedCellApiConfig, &SourceContext) -> Result<Box<dyn CellSource>>;
+
+pub struct CellSourceRegistry {
+    factories: HashMap<CellApiConfig, CellSourceFactory>,
+}
+
+impl Default for CellSourceRegistry {
+    fn default() -> Self {
+        let mut registry = CellSourceRegistry {
+            factories: HashMap::new(),
+        };
+        registry.register(CellApiConfig::Milesight, build_milesight_source);
+        registry.register(CellApiConfig::DevicePublisher, build_devpub_source);
+        registry.register(CellApiConfig::Modem, build_modem_source);
+        registry.register(CellApiConfig::File, build_file_source);
+        registry
+    }
+}
+
+impl CellSourceRegistry {
+    /// Replaces the factory if the API is already registered
+    pub fn register(&mut self, cell_api: CellApiConfig, factory: CellSourceFactory) {
+        self.factories.insert(cell_api, factory);
+    }
+
+    pub fn build(
+        &self,
+        cell_api_args: &FlattenedCellApiConfig,
+        context: &SourceContext,
+    ) -> Result<Box<dyn CellSource>> {
+        match self.factories.get(&cell_api_args.cell_api()) {
+            Some(factory) => factory(cell_api_args, context),
+            None => Err(anyhow!(
+                "[source] no cell source registered for {:?}",
+                cell_api_args.cell_api()
+            )),
+        }
+    }
+}
+
+fn unexpected_config(cell_api_args: &FlattenedCellApiConfig) -> anyhow::Error {
+    anyhow!("[source] unexpected cell API config: {:?}", cell_api_args)
+}
+
+impl CellSource for MilesightClient {
+    fn name(&self) -> &'static str {
+        "milesight"
+    }
+
+    fn retrieve(&mut self) -> Result<CellInfo> {
+        self.retrieve_cell_info()
+    }
+}
+
+fn build_milesight_source(
+    cell_api_args: &FlattenedCellApiConfig,
+    context: &SourceContext,
+) -> Result<Box<dyn CellSource>> {
+    match cell_api_args {
+        FlattenedCellApiConfig::Milesight(m_args) => Ok(Box::new(MilesightClient::new(
             &m_args.milesight_address,
             &m_args.milesight_user,
             &m_args.milesight_auth,
-        ),
+            context.runtime.clone(),
+            context.http_client.clone(),
+        )?)),
+        _ => Err(unexpected_config(cell_api_args)),
+    }
+}
+
+impl CellSource for DevicePublisherClient {
+    fn name(&self) -> &'static str {
+        "devicepublisher"
+    }
+
+    fn retrieve(&mut self) -> Result<CellInfo> {
+        self.retrieve_cell_info()
+    }
+}
+
+fn build_devpub_source(
+    cell_api_args: &FlattenedCellApiConfig,
+    context: &SourceContext,
+) -> Result<Box<dyn CellSource>> {
+    match cell_api_args {
         FlattenedCellApiConfig::DevicePublisher(dp_args) => {
-            CellInfo::from_devicepublisher(&dp_args.devpub_address)
+            Ok(Box::new(DevicePublisherClient::new(
+                dp_args,
+                context.runtime.clone(),
+                context.http_client.clone(),
+            )))
         }
+        _ => Err(unexpected_config(cell_api_args)),
     }
 }
 
+/// Opens the AT port on demand and reopens it after an error
+struct ModemSource {
+    args: FlattenedModemArgs,
+    port: Option<AtPort>,
+}
+
+impl CellSource for ModemSource {
+    fn name(&self) -> &'static str {
+        "modem"
+    }
+
+    fn retrieve(&mut self) -> Result<CellInfo> {
+        let port = match &mut self.port {
+            Some(port) => port,
+            None => self.port.insert(AtPort::open(
+                &self.args.modem_device,
+                self.args.modem_baud_rate,
+            )?),
+        };
+        let result = retrieve_modem_cell_info(port, self.args.modem_vendor);
+        if result.is_err() {
+            self.port = None;
+        }
+        result
+    }
+}
+
+fn build_modem_source(
+    cell_api_args: &FlattenedCellApiConfig,
+    _: &SourceContext,
+) -> Result<Box<dyn CellSource>> {
+    match cell_api_args {
+        FlattenedCellApiConfig::Modem(modem_args) => Ok(Box::new(ModemSource {
+            args: modem_args.clone(),
+            port: None,
+        })),
+        _ => Err(unexpected_config(cell_api_args)),
+    }
+}
+
+impl CellSource for CellFileSource {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn retrieve(&mut self) -> Result<CellInfo> {
+        CellFileSource::retrieve(self)
+    }
+}
+
+fn build_file_source(
+    cell_api_args: &FlattenedCellApiConfig,
+    _: &SourceContext,
+) -> Result<Box<dyn CellSource>> {
+    match cell_api_args {
+        FlattenedCellApiConfig::File(file_args) => {
+            Ok(Box::new(CellFileSource::new(&file_args.cellfile_path)))
+        }
+        _ => Err(unexpected_config(cell_api_args)),
+    }
+}
+
+/*
+ * Polling policy
+ *
+ * A source is polled every interval_ms. After n failed requests in a row the
+ * next request waits interval_ms * 2^n, at most backoff_max_ms. If no request
+ * succeeded for stale_timeout_ms, the cell is reported as unknown once.
+ * */
+
+#[derive(Clone, Debug, PartialEq)]
+struct PollPolicy {
+    interval_ms: u64,
+    backoff_max_ms: u64,
+    stale_timeout_ms: u64,
+}
+
+#[derive(Clone, Debug)]
+struct PollState {
+    policy: PollPolicy,
+    nof_failures: u32,
+    next_poll_ms: u64,
+    last_success_ms: u64,
+    is_stale: bool,
+}
+
+impl PollState {
+    fn new(policy: PollPolicy, now_ms: u64) -> PollState {
+        PollState {
+            policy,
+            nof_failures: 0,
+            next_poll_ms: now_ms,
+            last_success_ms: now_ms,
+            is_stale: false,
+        }
+    }
+
+    fn is_due(&self, now_ms: u64) -> bool {
+        now_ms >= self.next_poll_ms
+    }
+
+    fn on_success(&mut self, now_ms: u64) {
+        self.nof_failures = 0;
+        self.last_success_ms = now_ms;
+        self.next_poll_ms = now_ms + self.policy.interval_ms;
+        self.is_stale = false;
+    }
+
+    /// Returns the delay until the next request
+    fn on_failure(&mut self, now_ms: u64) -> u64 {
+        self.nof_failures = self.nof_failures.saturating_add(1);
+        let delay_ms = self
+            .policy
+            .interval_ms
+            .saturating_mul(2u64.saturating_pow(self.nof_failures))
+            .min(self.policy.backoff_max_ms.max(self.policy.interval_ms));
+        self.next_poll_ms = now_ms + delay_ms;
+        delay_ms
+    }
+
+    /// True only on the transition to stale
+    fn check_stale(&mut self, now_ms: u64) -> bool {
+        if self.is_stale || self.policy.stale_timeout_ms == 0 {
+            return false;
+        }
+        if now_ms.saturating_sub(self.last_success_ms) >= self.policy.stale_timeout_ms {
+            self.is_stale = true;
+            return true;
+        }
+        false
+    }
+}
+
+fn print_source_error(source_name: &str, some_err: &anyhow::Error, retry_in_ms: u64) {
+    match some_err.downcast_ref::<MilesightError>() {
+        Some(auth_err @ MilesightError::AuthFailed(_)) => {
+            print_info(&format!(
+                "ERROR [source] {} (retry in {} ms)",
+                auth_err, retry_in_ms
+            ));
+        }
+        _ => {
+            print_info(&format!(
+                "[source] err retrieving cell info from {} (retry in {} ms): {:#?}",
+                source_name, retry_in_ms, some_err
+            ));
+        }
+    }
+}
+
+fn handle_cell_changes(
+    before: &CellInfo,
+    after: &CellInfo,
+    tx_cell_info: &mut Bus<MessageCellInfo>,
+) {
+    for change in classify_cell_changes(before, after) {
+        print_info(&format!(
+            "[source] {:?}: {:?} -> {:?}",
+            change.change_type,
+            change
+                .before
+                .as_ref()
+                .map(|cell| (cell.frequency, cell.pci)),
+            change.after.as_ref().map(|cell| (cell.frequency, cell.pci)),
+        ));
+        let _ = log_handover(HandoverRecord {
+            timestamp_us: chrono::Local::now().timestamp_micros() as u64,
+            change: change.clone(),
+        });
+        tx_cell_info.broadcast(MessageCellInfo::CellChanged(Box::new(change)));
+    }
+}
+
+/*
+ * equal_content ignores the PCI, but ng-scope derives N_id_2 from it and
+ * checks the PCI it locks to. After an intra-frequency handover the cells are
+ * sent again, otherwise ng-scope would keep decoding the old cell.
+ * */
+fn is_pci_changed(cell_info: &CellInfo, last_cell_info: &CellInfo) -> bool {
+    cell_info.cells.iter().any(|cell| {
+        cell.pci.is_some()
+            && last_cell_info.cells.iter().any(|last_cell| {
+                last_cell.frequency == cell.frequency
+                    && last_cell.cell_type == cell.cell_type
+                    && last_cell.pci != cell.pci
+            })
+    })
+}
+
+/// Message for the ngcontrol, None if nothing changed that it cares about
+fn cell_update_message(cell_info: &CellInfo, last_cell_info: &CellInfo) -> Option<MessageCellInfo> {
+    if !CellInfo::equal_content(cell_info, last_cell_info)
+        || is_pci_changed(cell_info, last_cell_info)
+    {
+        return Some(MessageCellInfo::Cells(cell_info.clone()));
+    }
+    if !CellInfo::equal_neighbours(cell_info, last_cell_info) {
+        return Some(MessageCellInfo::Neighbours(cell_info.clone()));
+    }
+    None
+}
+
 fn run(
     mut rx_app_state: BusReader<MainState>,
     tx_source_state: SyncSender<SourceState>,
@@ -81,8 +414,28 @@ fn run(
         app_args.cellapi.unwrap(),
         app_args.milesight.unwrap(),
         app_args.devicepublisher.unwrap(),
+        app_args.modem.unwrap_or_default(),
+        app_args.cellfile.unwrap_or_default(),
     )?;
-    let mut last_cell_info: CellInfo = CellInfo { cells: vec![] };
+    let policy_args =
+        FlattenedSourcePolicyArgs::from_unflattened(app_args.sourcepolicy.unwrap_or_default())?;
+    let log_args = FlattenedLogArgs::from_unflattened(app_args.log.unwrap())?;
+    let http_args =
+        FlattenedSourceHttpArgs::from_unflattened(app_args.sourcehttp.unwrap_or_default())?;
+    let context = SourceContext::new(&http_args)?;
+    let mut cell_source = CellSourceRegistry::default().build(&cell_api_args, &context)?;
+    let start = Instant::now();
+    let mut poll_state = PollState::new(
+        PollPolicy {
+            interval_ms: cell_api_args.poll_interval_ms(),
+            backoff_max_ms: policy_args.source_backoff_max_ms,
+            stale_timeout_ms: policy_args.source_stale_timeout_ms,
+        },
+        0,
+    );
+    let mut last_cell_info: CellInfo = CellInfo::default();
+    /* not reset when the source becomes stale, to classify changes during outages */
+    let mut last_known_cell_info: CellInfo = CellInfo::default();
 
     loop {
         /* <precheck> */
@@ -92,29 +445,175 @@ fn run(
         }
         /* </precheck> */
 
-        match retrieve_cell_info(&cell_api_args) {
+        let now_ms = start.elapsed().as_millis() as u64;
+        if poll_state.check_stale(now_ms) {
+            print_info(&format!(
+                "ERROR [source] no cell info from {} for {} ms, cell unknown",
+                cell_source.name(),
+                now_ms - poll_state.last_success_ms
+            ));
+            tx_cell_info.broadcast(MessageCellInfo::CellUnknown);
+            /* report the cells again once the source recovers */
+            last_cell_info = CellInfo::default();
+        }
+        if !poll_state.is_due(now_ms) {
+            continue;
+        }
+
+        match cell_source.retrieve() {
             Ok(cell_info) => {
-                if !CellInfo::equal_content(&cell_info, &last_cell_info) {
-                    tx_cell_info.broadcast(MessageCellInfo {
-                        cell_info: cell_info.clone(),
+                poll_state.on_success(now_ms);
+                if log_args.log_cell_info {
+                    let _ = log_cell_info(CellInfoRecord {
+                        timestamp_us: chrono::Local::now().timestamp_micros() as u64,
+                        source: cell_source.name().to_string(),
+                        cells: cell_info.cells.clone(),
+                        neighbours: cell_info.neighbours.clone(),
                     });
+                }
+                handle_cell_changes(&last_known_cell_info, &cell_info, &mut tx_cell_info);
+                last_known_cell_info = cell_info.clone();
+                if let Some(message) = cell_update_message(&cell_info, &last_cell_info) {
+                    tx_cell_info.broadcast(message);
                     last_cell_info = cell_info;
                 }
             }
             Err(some_err) => {
-                // TODO: print error properly
-                print_info(&format!(
-                    "[source] err retrieving cell info: {:#?}",
-                    some_err
-                ));
+                let retry_in_ms = poll_state.on_failure(now_ms);
+                print_source_error(cell_source.name(), &some_err, retry_in_ms);
             }
         }
-
-        thread::sleep(Duration::from_millis(
-            WAIT_TO_RETRIEVE_CELL_INFO_MS - DEFAULT_WORKER_SLEEP_MS,
-        ));
     }
 
     send_final_state(&tx_source_state)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell_info::{CellularType, SingleCell};
+    use crate::parse::{FlattenedCellFileArgs, SourceHttpArgs};
+
+    fn dummy_policy() -> PollPolicy {
+        PollPolicy {
+            interval_ms: 1000,
+            backoff_max_ms: 5000,
+            stale_timeout_ms: 10000,
+        }
+    }
+
+    #[test]
+    fn test_poll_backoff() {
+        let mut poll_state = PollState::new(dummy_policy(), 0);
+        assert!(poll_state.is_due(0));
+        poll_state.on_success(0);
+        assert!(!poll_state.is_due(999));
+        assert!(poll_state.is_due(1000));
+
+        assert_eq!(poll_state.on_failure(1000), 2000);
+        assert!(!poll_state.is_due(2999));
+        assert_eq!(poll_state.on_failure(3000), 4000);
+        assert_eq!(poll_state.on_failure(7000), 5000);
+        assert_eq!(poll_state.on_failure(12000), 5000);
+
+        poll_state.on_success(17000);
+        assert_eq!(poll_state.on_failure(18000), 2000);
+    }
+
+    #[test]
+    fn test_poll_stale() {
+        let mut poll_state = PollState::new(dummy_policy(), 0);
+        assert!(!poll_state.check_stale(9999));
+        assert!(poll_state.check_stale(10000));
+        /* reported once */
+        assert!(!poll_state.check_stale(20000));
+
+        poll_state.on_success(21000);
+        assert!(!poll_state.check_stale(30999));
+        assert!(poll_state.check_stale(31000));
+
+        let mut never_stale = PollState::new(
+            PollPolicy {
+                stale_timeout_ms: 0,
+                ..dummy_policy()
+            },
+            0,
+        );
+        assert!(!never_stale.check_stale(u64::MAX));
+    }
+
+    struct FakeSource;
+
+    impl CellSource for FakeSource {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn retrieve(&mut self) -> Result<CellInfo> {
+            Ok(CellInfo::default())
+        }
+    }
+
+    fn build_fake_source(
+        _: &FlattenedCellApiConfig,
+        _: &SourceContext,
+    ) -> Result<Box<dyn CellSource>> {
+        Ok(Box::new(FakeSource))
+    }
+
+    #[test]
+    fn test_cell_update_message() {
+        let last_cell_info = CellInfo {
+            cells: vec![SingleCell {
+                cell_id: 100,
+                cell_type: CellularType::LTE,
+                frequency: 1_815_000_000,
+                pci: Some(140),
+                ..Default::default()
+            }],
+            neighbours: vec![],
+        };
+        let mut cell_info = last_cell_info.clone();
+        cell_info.cells[0].rsrp = -90.0;
+        assert!(cell_update_message(&cell_info, &last_cell_info).is_none());
+
+        /* intra-frequency handover */
+        cell_info.cells[0].pci = Some(301);
+        assert!(matches!(
+            cell_update_message(&cell_info, &last_cell_info),
+            Some(MessageCellInfo::Cells(_))
+        ));
+
+        /* a source that does not report the PCI */
+        cell_info.cells[0].pci = None;
+        assert!(cell_update_message(&cell_info, &last_cell_info).is_none());
+
+        cell_info.cells[0].pci = Some(140);
+        cell_info.neighbours = cell_info.cells.clone();
+        assert!(matches!(
+            cell_update_message(&cell_info, &last_cell_info),
+            Some(MessageCellInfo::Neighbours(_))
+        ));
+    }
+
+    #[test]
+    fn test_cell_source_registry() -> Result<()> {
+        let file_args = FlattenedCellApiConfig::File(FlattenedCellFileArgs {
+            cellfile_path: "./does-not-exist.yaml".to_string(),
+            cellfile_poll_interval_ms: 1000,
+        });
+        let context = SourceContext::new(&FlattenedSourceHttpArgs::from_unflattened(
+            SourceHttpArgs::default(),
+        )?)?;
+        let mut registry = CellSourceRegistry::default();
+        assert_eq!(registry.build(&file_args, &context)?.name(), "file");
+        assert!(build_modem_source(&file_args, &context).is_err());
+
+        registry.register(CellApiConfig::File, build_fake_source);
+        let mut source = registry.build(&file_args, &context)?;
+        assert_eq!(source.name(), "fake");
+        assert!(source.retrieve()?.cells.is_empty());
+        Ok(())
+    }
+}
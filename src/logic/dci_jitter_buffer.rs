@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::ngscope::types::NgScopeCellDci;
+
+pub const NOF_TTI: i64 = 10240;
+pub const SUBFRAME_US: i64 = 1000;
+
+/* Number of TTIs a CellDci is held back to give reordered packets a chance */
+pub const DCI_JITTER_BUFFER_HOLD_TTI: i64 = 4;
+/* Maximum time a CellDci is held back when no newer TTI arrives */
+pub const DCI_JITTER_BUFFER_HOLD_US: i64 = 10_000;
+/* Timestamps jumping back further than this are treated as a restarted stream */
+pub const DCI_JITTER_BUFFER_RESET_US: i64 = 5_000_000;
+const DCI_JITTER_BUFFER_RECENT_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DciJitterStats {
+    pub nof_released: u64,
+    pub nof_late: u64,
+    pub nof_duplicate: u64,
+    pub nof_missing: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct DciJitterBuffer {
+    cells: HashMap<u8, CellJitterBuffer>,
+}
+
+#[derive(Debug, Default)]
+struct CellJitterBuffer {
+    /* key: TTI counter without wrap-around, value: (arrival us, dci) */
+    pending: BTreeMap<i64, (i64, NgScopeCellDci)>,
+    /* (key, time_stamp) of the newest CellDci seen, used to unwrap the TTI */
+    reference: Option<(i64, i64)>,
+    last_released_key: Option<i64>,
+    recent_released_keys: VecDeque<i64>,
+    stats: DciJitterStats,
+}
+
+impl DciJitterBuffer {
+    pub fn new() -> DciJitterBuffer {
+        Default::default()
+    }
+
+    /// Queue a received CellDci, `now_us` is the local arrival time
+    pub fn push(&mut self, cell_dci: NgScopeCellDci, now_us: i64) {
+        self.cells
+            .entry(cell_dci.cell_id)
+            .or_default()
+            .push(cell_dci, now_us);
+    }
+
+    /// Take all CellDci that are ready, ordered by TTI per cell
+    pub fn pop_ready(&mut self, now_us: i64) -> Vec<NgScopeCellDci> {
+        let mut ready: Vec<NgScopeCellDci> = vec![];
+        for cell in self.cells.values_mut() {
+            cell.pop_ready(now_us, &mut ready);
+        }
+        ready
+    }
+
+    pub fn stats(&self) -> HashMap<u8, DciJitterStats> {
+        self.cells
+            .iter()
+            .map(|(&cell_id, cell)| (cell_id, cell.stats))
+            .collect()
+    }
+}
+
+impl CellJitterBuffer {
+    fn push(&mut self, cell_dci: NgScopeCellDci, now_us: i64) {
+        let time_stamp = cell_dci.time_stamp as i64;
+        if let Some((_, reference_time_stamp)) = self.reference {
+            if reference_time_stamp - time_stamp > DCI_JITTER_BUFFER_RESET_US {
+                self.reset();
+            }
+        }
+
+        let key = self.unwrap_tti(cell_dci.tti, time_stamp);
+        match self.reference {
+            Some((reference_key, _)) if reference_key >= key => {}
+            _ => self.reference = Some((key, time_stamp)),
+        }
+
+        if self.pending.contains_key(&key) || self.recent_released_keys.contains(&key) {
+            self.stats.nof_duplicate += 1;
+            return;
+        }
+        if let Some(last_released_key) = self.last_released_key {
+            if key <= last_released_key {
+                self.stats.nof_late += 1;
+                return;
+            }
+        }
+        self.pending.insert(key, (now_us, cell_dci));
+    }
+
+    fn pop_ready(&mut self, now_us: i64, ready: &mut Vec<NgScopeCellDci>) {
+        let newest_key = match self.pending.keys().next_back() {
+            Some(&key) => key,
+            None => return,
+        };
+        while let Some((&key, &(arrival_us, _))) = self.pending.iter().next() {
+            let is_held_long_enough = newest_key - key >= DCI_JITTER_BUFFER_HOLD_TTI
+                || now_us.saturating_sub(arrival_us) >= DCI_JITTER_BUFFER_HOLD_US;
+            if !is_held_long_enough {
+                break;
+            }
+            let (_, cell_dci) = self.pending.remove(&key).unwrap();
+            self.release(key);
+            ready.push(cell_dci);
+        }
+    }
+
+    fn release(&mut self, key: i64) {
+        if let Some(last_released_key) = self.last_released_key {
+            self.stats.nof_missing += (key - last_released_key - 1).max(0) as u64;
+        }
+        self.last_released_key = Some(key);
+        if self.recent_released_keys.len() >= DCI_JITTER_BUFFER_RECENT_SIZE {
+            self.recent_released_keys.pop_front();
+        }
+        self.recent_released_keys.push_back(key);
+        self.stats.nof_released += 1;
+    }
+
+    fn unwrap_tti(&self, tti: u16, time_stamp: i64) -> i64 {
+        match self.reference {
+            Some((reference_key, reference_time_stamp)) => {
+                reference_key
+                    + tti_distance(
+                        reference_key.rem_euclid(NOF_TTI) as u16,
+                        reference_time_stamp,
+                        tti,
+                        time_stamp,
+                    )
+            }
+            None => tti as i64,
+        }
+    }
+
+    fn reset(&mut self) {
+        /* pending entries of the old stream are dropped, they would be late anyway */
+        self.pending.clear();
+        self.reference = None;
+        self.last_released_key = None;
+        self.recent_released_keys.clear();
+    }
+}
+
+/// Number of TTIs from one CellDci to another, beyond the 10240 TTI wrap-around.
+///
+/// The timestamps give a rough estimate that is corrected with the exact TTI.
+pub fn tti_distance(from_tti: u16, from_time_stamp: i64, to_tti: u16, to_time_stamp: i64) -> i64 {
+    let estimated_distance = (to_time_stamp - from_time_stamp) / SUBFRAME_US;
+    let estimated_tti = from_tti as i64 + estimated_distance;
+    let mut correction = (to_tti as i64 - estimated_tti).rem_euclid(NOF_TTI);
+    if correction >= NOF_TTI / 2 {
+        correction -= NOF_TTI;
+    }
+    estimated_distance + correction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START_TIME_STAMP_US: i64 = 1_700_000_000_000_000;
+
+    fn dummy_dci(tti: i64) -> NgScopeCellDci {
+        NgScopeCellDci {
+            tti: tti.rem_euclid(NOF_TTI) as u16,
+            time_stamp: (START_TIME_STAMP_US + tti * SUBFRAME_US) as u64,
+            ..Default::default()
+        }
+    }
+
+    fn released_ttis(buffer: &mut DciJitterBuffer, now_us: i64) -> Vec<u16> {
+        buffer
+            .pop_ready(now_us)
+            .iter()
+            .map(|cell_dci| cell_dci.tti)
+            .collect()
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut buffer = DciJitterBuffer::new();
+        for tti in [1, 3, 2, 4, 5, 6, 7] {
+            buffer.push(dummy_dci(tti), 0);
+        }
+        assert_eq!(released_ttis(&mut buffer, 0), vec![1, 2, 3]);
+        assert_eq!(
+            released_ttis(&mut buffer, DCI_JITTER_BUFFER_HOLD_US),
+            vec![4, 5, 6, 7]
+        );
+        assert_eq!(buffer.stats()[&0].nof_released, 7);
+        assert_eq!(buffer.stats()[&0].nof_missing, 0);
+    }
+
+    #[test]
+    fn test_duplicate_late_missing() {
+        let mut buffer = DciJitterBuffer::new();
+        for tti in [10, 10, 11, 13, 20] {
+            buffer.push(dummy_dci(tti), 0);
+        }
+        assert_eq!(released_ttis(&mut buffer, 0), vec![10, 11, 13]);
+        /* duplicate of an already released TTI and a TTI that is too late */
+        buffer.push(dummy_dci(11), 0);
+        buffer.push(dummy_dci(12), 0);
+
+        let stats = buffer.stats()[&0];
+        assert_eq!(stats.nof_duplicate, 2);
+        assert_eq!(stats.nof_late, 1);
+        assert_eq!(stats.nof_missing, 1);
+    }
+
+    #[test]
+    fn test_tti_wraparound() {
+        let mut buffer = DciJitterBuffer::new();
+        for tti in [NOF_TTI - 2, NOF_TTI, NOF_TTI - 1, NOF_TTI + 1] {
+            buffer.push(dummy_dci(tti), 0);
+        }
+        assert_eq!(
+            released_ttis(&mut buffer, DCI_JITTER_BUFFER_HOLD_US),
+            vec![10238, 10239, 0, 1]
+        );
+        assert_eq!(buffer.stats()[&0].nof_missing, 0);
+    }
+
+    #[test]
+    fn test_clock_backwards() {
+        let mut buffer = DciJitterBuffer::new();
+        buffer.push(dummy_dci(1), DCI_JITTER_BUFFER_HOLD_US);
+        assert!(released_ttis(&mut buffer, i64::MIN).is_empty());
+        assert_eq!(
+            released_ttis(&mut buffer, 2 * DCI_JITTER_BUFFER_HOLD_US),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_per_cell() {
+        let mut buffer = DciJitterBuffer::new();
+        let mut secondary = dummy_dci(5);
+        secondary.cell_id = 1;
+        buffer.push(dummy_dci(5), 0);
+        buffer.push(secondary, 0);
+
+        assert_eq!(buffer.pop_ready(DCI_JITTER_BUFFER_HOLD_US).len(), 2);
+        assert_eq!(buffer.stats().len(), 2);
+        assert_eq!(buffer.stats()[&0].nof_duplicate, 0);
+    }
+
+    #[test]
+    fn test_stream_restart() {
+        let mut buffer = DciJitterBuffer::new();
+        buffer.push(dummy_dci(500), 0);
+        assert_eq!(
+            released_ttis(&mut buffer, DCI_JITTER_BUFFER_HOLD_US).len(),
+            1
+        );
+
+        let mut restarted = dummy_dci(100);
+        restarted.time_stamp -= 2 * DCI_JITTER_BUFFER_RESET_US as u64;
+        buffer.push(restarted, 0);
+        assert_eq!(
+            released_ttis(&mut buffer, DCI_JITTER_BUFFER_HOLD_US),
+            vec![100]
+        );
+        assert_eq!(buffer.stats()[&0].nof_late, 0);
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::logic::dci_jitter_buffer::{tti_distance, DciJitterStats};
+use crate::ngscope::types::NgScopeCellDci;
+
+/* Upper bounds (inclusive) of the gap histogram buckets in missing TTIs */
+pub const DCI_QUALITY_GAP_BUCKETS_TTI: [u64; 6] = [1, 4, 9, 99, 999, u64::MAX];
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DciQualityReportType {
+    /// Statistics since the previous interval report
+    Interval,
+    /// Statistics of the whole run
+    Run,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DciQualityReport {
+    pub report_type: DciQualityReportType,
+    pub timestamp_us: u64,
+    pub start_timestamp_us: u64,
+    pub gap_histogram_upper_bounds_tti: Vec<u64>,
+    pub cells: Vec<CellDciQuality>,
+    /// Cumulative reordering statistics of the jitter buffer per cell
+    pub jitter: HashMap<u8, DciJitterStats>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CellDciQuality {
+    pub cell_id: u8,
+    pub nof_dci: u64,
+    pub nof_missing_tti: u64,
+    pub nof_gaps: u64,
+    pub longest_gap_tti: u64,
+    pub gap_histogram: Vec<u64>,
+    pub first_dci_timestamp_us: u64,
+    pub last_dci_timestamp_us: u64,
+    #[serde(skip)]
+    last_tti: u16,
+}
+
+/// Snapshot of the live counters, summed over all cells
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DciQualityLive {
+    pub nof_dci: u64,
+    pub nof_missing_tti: u64,
+    pub nof_gaps: u64,
+}
+
+#[derive(Debug, Default)]
+struct DciQualityLiveCounter {
+    nof_dci: AtomicU64,
+    nof_missing_tti: AtomicU64,
+    nof_gaps: AtomicU64,
+}
+
+lazy_static! {
+    static ref DCI_QUALITY_LIVE: DciQualityLiveCounter = DciQualityLiveCounter::default();
+}
+
+pub fn dci_quality_live() -> DciQualityLive {
+    DciQualityLive {
+        nof_dci: DCI_QUALITY_LIVE.nof_dci.load(Ordering::Relaxed),
+        nof_missing_tti: DCI_QUALITY_LIVE.nof_missing_tti.load(Ordering::Relaxed),
+        nof_gaps: DCI_QUALITY_LIVE.nof_gaps.load(Ordering::Relaxed),
+    }
+}
+
+/// Tracks the TTI continuity of the ordered CellDci stream per cell
+#[derive(Debug)]
+pub struct DciQualityTracker {
+    interval_start_us: u64,
+    run_start_us: u64,
+    interval_cells: HashMap<u8, CellDciQuality>,
+    run_cells: HashMap<u8, CellDciQuality>,
+}
+
+impl DciQualityTracker {
+    pub fn new(now_us: u64) -> DciQualityTracker {
+        DciQualityTracker {
+            interval_start_us: now_us,
+            run_start_us: now_us,
+            interval_cells: HashMap::new(),
+            run_cells: HashMap::new(),
+        }
+    }
+
+    /// Account an ordered CellDci, returns the number of TTIs missing before it
+    pub fn update(&mut self, cell_dci: &NgScopeCellDci) -> u64 {
+        let missing_tti = self
+            .run_cells
+            .entry(cell_dci.cell_id)
+            .or_insert_with(|| CellDciQuality::new(cell_dci.cell_id))
+            .update(cell_dci);
+        self.interval_cells
+            .entry(cell_dci.cell_id)
+            .or_insert_with(|| CellDciQuality::new(cell_dci.cell_id))
+            .update(cell_dci);
+
+        DCI_QUALITY_LIVE.nof_dci.fetch_add(1, Ordering::Relaxed);
+        if missing_tti > 0 {
+            DCI_QUALITY_LIVE
+                .nof_missing_tti
+                .fetch_add(missing_tti, Ordering::Relaxed);
+            DCI_QUALITY_LIVE.nof_gaps.fetch_add(1, Ordering::Relaxed);
+        }
+        missing_tti
+    }
+
+    pub fn interval_start_us(&self) -> u64 {
+        self.interval_start_us
+    }
+
+    /// Report the statistics since the last interval report and start a new interval
+    pub fn take_interval_report(
+        &mut self,
+        now_us: u64,
+        jitter: HashMap<u8, DciJitterStats>,
+    ) -> DciQualityReport {
+        let report = DciQualityReport {
+            report_type: DciQualityReportType::Interval,
+            timestamp_us: now_us,
+            start_timestamp_us: self.interval_start_us,
+            gap_histogram_upper_bounds_tti: DCI_QUALITY_GAP_BUCKETS_TTI.to_vec(),
+            cells: sorted_cells(&self.interval_cells),
+            jitter,
+        };
+        /* keep the last TTI per cell so the first gap of the next interval is not lost */
+        for cell in self.interval_cells.values_mut() {
+            *cell = CellDciQuality {
+                last_tti: cell.last_tti,
+                last_dci_timestamp_us: cell.last_dci_timestamp_us,
+                ..CellDciQuality::new(cell.cell_id)
+            };
+        }
+        self.interval_start_us = now_us;
+        report
+    }
+
+    pub fn run_report(&self, now_us: u64, jitter: HashMap<u8, DciJitterStats>) -> DciQualityReport {
+        DciQualityReport {
+            report_type: DciQualityReportType::Run,
+            timestamp_us: now_us,
+            start_timestamp_us: self.run_start_us,
+            gap_histogram_upper_bounds_tti: DCI_QUALITY_GAP_BUCKETS_TTI.to_vec(),
+            cells: sorted_cells(&self.run_cells),
+            jitter,
+        }
+    }
+}
+
+impl CellDciQuality {
+    fn new(cell_id: u8) -> CellDciQuality {
+        CellDciQuality {
+            cell_id,
+            gap_histogram: vec![0; DCI_QUALITY_GAP_BUCKETS_TTI.len()],
+            ..Default::default()
+        }
+    }
+
+    fn update(&mut self, cell_dci: &NgScopeCellDci) -> u64 {
+        let mut missing_tti: u64 = 0;
+        if self.last_dci_timestamp_us != 0 {
+            let distance = tti_distance(
+                self.last_tti,
+                self.last_dci_timestamp_us as i64,
+                cell_dci.tti,
+                cell_dci.time_stamp as i64,
+            );
+            if distance > 1 {
+                missing_tti = (distance - 1) as u64;
+                self.add_gap(missing_tti);
+            }
+        }
+        if self.first_dci_timestamp_us == 0 {
+            self.first_dci_timestamp_us = cell_dci.time_stamp;
+        }
+        self.nof_dci += 1;
+        self.last_tti = cell_dci.tti;
+        self.last_dci_timestamp_us = cell_dci.time_stamp;
+        missing_tti
+    }
+
+    fn add_gap(&mut self, missing_tti: u64) {
+        self.nof_missing_tti += missing_tti;
+        self.nof_gaps += 1;
+        self.longest_gap_tti = self.longest_gap_tti.max(missing_tti);
+        let bucket = DCI_QUALITY_GAP_BUCKETS_TTI
+            .iter()
+            .position(|&upper_bound| missing_tti <= upper_bound)
+            .unwrap();
+        self.gap_histogram[bucket] += 1;
+    }
+
+    pub fn missing_ratio(&self) -> f64 {
+        let nof_expected = self.nof_dci + self.nof_missing_tti;
+        if nof_expected == 0 {
+            return 0.0;
+        }
+        self.nof_missing_tti as f64 / nof_expected as f64
+    }
+}
+
+fn sorted_cells(cells: &HashMap<u8, CellDciQuality>) -> Vec<CellDciQuality> {
+    let mut sorted: Vec<CellDciQuality> = cells.values().cloned().collect();
+    sorted.sort_by_key(|cell| cell.cell_id);
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::dci_jitter_buffer::{NOF_TTI, SUBFRAME_US};
+
+    const START_TIME_STAMP_US: i64 = 1_700_000_000_000_000;
+
+    fn dummy_dci(tti: i64) -> NgScopeCellDci {
+        NgScopeCellDci {
+            tti: tti.rem_euclid(NOF_TTI) as u16,
+            time_stamp: (START_TIME_STAMP_US + tti * SUBFRAME_US) as u64,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_gap_accounting() {
+        let mut tracker = DciQualityTracker::new(0);
+        let missing: Vec<u64> = [0, 1, 3, 8, 9, 2009]
+            .iter()
+            .map(|&tti| tracker.update(&dummy_dci(tti)))
+            .collect();
+        assert_eq!(missing, vec![0, 0, 1, 4, 0, 1999]);
+
+        let report = tracker.run_report(1, HashMap::new());
+        let cell = &report.cells[0];
+        assert_eq!(cell.nof_dci, 6);
+        assert_eq!(cell.nof_missing_tti, 2004);
+        assert_eq!(cell.nof_gaps, 3);
+        assert_eq!(cell.longest_gap_tti, 1999);
+        assert_eq!(cell.gap_histogram, vec![1, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_gap_over_tti_wraparound() {
+        let mut tracker = DciQualityTracker::new(0);
+        tracker.update(&dummy_dci(NOF_TTI - 1));
+        assert_eq!(tracker.update(&dummy_dci(NOF_TTI + 2)), 2);
+        /* a gap longer than a full TTI cycle */
+        assert_eq!(
+            tracker.update(&dummy_dci(3 * NOF_TTI + 3)),
+            2 * NOF_TTI as u64
+        );
+    }
+
+    #[test]
+    fn test_interval_report() {
+        let mut tracker = DciQualityTracker::new(0);
+        tracker.update(&dummy_dci(0));
+        tracker.update(&dummy_dci(2));
+        let first = tracker.take_interval_report(10, HashMap::new());
+        assert_eq!(first.cells[0].nof_missing_tti, 1);
+        assert_eq!(first.start_timestamp_us, 0);
+
+        tracker.update(&dummy_dci(5));
+        let second = tracker.take_interval_report(20, HashMap::new());
+        assert_eq!(second.start_timestamp_us, 10);
+        assert_eq!(second.cells[0].nof_dci, 1);
+        assert_eq!(second.cells[0].nof_missing_tti, 2);
+
+        let run = tracker.run_report(30, HashMap::new());
+        assert_eq!(run.cells[0].nof_missing_tti, 3);
+        assert!((run.cells[0].missing_ratio() - 0.5).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,202 @@
+use serde_derive::Serialize;
+
+use crate::cell_info::{CellInfo, SingleCell};
+
+/*
+ * Classifies the difference between two consecutive cell infos
+ *
+ * The first cell is the primary cell (PCell), the remaining cells are
+ * secondary carriers. PCell changes are handovers, SCell changes are
+ * carriers that were added or removed.
+ * */
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum CellChangeType {
+    /// Same RAT and frequency, different PCI
+    IntraFrequencyHandover,
+    /// Same RAT, different frequency
+    InterFrequencyHandover,
+    /// Different RAT (e.g. LTE -> NR)
+    RatChange,
+    CarrierAdded,
+    CarrierRemoved,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CellChange {
+    pub change_type: CellChangeType,
+    /// Cell before the change, contains the signal levels of the last poll
+    pub before: Option<SingleCell>,
+    pub after: Option<SingleCell>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HandoverRecord {
+    pub timestamp_us: u64,
+    #[serde(flatten)]
+    pub change: CellChange,
+}
+
+fn is_same_carrier(cell_a: &SingleCell, cell_b: &SingleCell) -> bool {
+    cell_a.cell_type == cell_b.cell_type
+        && cell_a.frequency == cell_b.frequency
+        && cell_a.pci == cell_b.pci
+}
+
+fn classify_primary_change(before: &SingleCell, after: &SingleCell) -> Option<CellChangeType> {
+    if before.cell_type != after.cell_type {
+        Some(CellChangeType::RatChange)
+    } else if before.frequency != after.frequency {
+        Some(CellChangeType::InterFrequencyHandover)
+    } else if before.pci.is_some() && after.pci.is_some() && before.pci != after.pci {
+        Some(CellChangeType::IntraFrequencyHandover)
+    } else {
+        None
+    }
+}
+
+/// Without a previous cell (startup) nothing is classified
+pub fn classify_cell_changes(before: &CellInfo, after: &CellInfo) -> Vec<CellChange> {
+    let (before_primary, after_primary) = match (before.cells.first(), after.cells.first()) {
+        (Some(before_primary), Some(after_primary)) => (before_primary, after_primary),
+        _ => return vec![],
+    };
+    let mut changes: Vec<CellChange> = vec![];
+    if let Some(change_type) = classify_primary_change(before_primary, after_primary) {
+        changes.push(CellChange {
+            change_type,
+            before: Some(before_primary.clone()),
+            after: Some(after_primary.clone()),
+        });
+    }
+
+    let before_secondary = &before.cells[1..];
+    let after_secondary = &after.cells[1..];
+    for cell in before_secondary.iter() {
+        if !after_secondary
+            .iter()
+            .any(|other| is_same_carrier(cell, other))
+        {
+            changes.push(CellChange {
+                change_type: CellChangeType::CarrierRemoved,
+                before: Some(cell.clone()),
+                after: None,
+            });
+        }
+    }
+    for cell in after_secondary.iter() {
+        if !before_secondary
+            .iter()
+            .any(|other| is_same_carrier(cell, other))
+        {
+            changes.push(CellChange {
+                change_type: CellChangeType::CarrierAdded,
+                before: None,
+                after: Some(cell.clone()),
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell_info::CellularType;
+
+    fn cell(cell_type: CellularType, frequency: u64, pci: u16, rsrp: f64) -> SingleCell {
+        SingleCell {
+            cell_id: pci as u64,
+            cell_type,
+            frequency,
+            pci: Some(pci),
+            rsrp,
+            ..Default::default()
+        }
+    }
+
+    fn change_types(before: Vec<SingleCell>, after: Vec<SingleCell>) -> Vec<CellChangeType> {
+        let before = CellInfo {
+            cells: before,
+            ..Default::default()
+        };
+        let after = CellInfo {
+            cells: after,
+            ..Default::default()
+        };
+        classify_cell_changes(&before, &after)
+            .into_iter()
+            .map(|change| change.change_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_classify_primary_changes() {
+        let lte_a = cell(CellularType::LTE, 1_815_000_000, 140, -90.0);
+        let lte_b = cell(CellularType::LTE, 1_815_000_000, 141, -80.0);
+        let lte_c = cell(CellularType::LTE, 806_000_000, 12, -85.0);
+        let nr = cell(CellularType::NR, 3_549_990_000, 512, -95.0);
+
+        assert!(change_types(vec![lte_a.clone()], vec![lte_a.clone()]).is_empty());
+        assert!(change_types(vec![], vec![lte_a.clone()]).is_empty());
+        assert_eq!(
+            change_types(vec![lte_a.clone()], vec![lte_b.clone()]),
+            vec![CellChangeType::IntraFrequencyHandover]
+        );
+        assert_eq!(
+            change_types(vec![lte_a.clone()], vec![lte_c.clone()]),
+            vec![CellChangeType::InterFrequencyHandover]
+        );
+        assert_eq!(
+            change_types(vec![lte_a.clone()], vec![nr.clone()]),
+            vec![CellChangeType::RatChange]
+        );
+
+        let changes = classify_cell_changes(
+            &CellInfo {
+                cells: vec![lte_a.clone()],
+                ..Default::default()
+            },
+            &CellInfo {
+                cells: vec![lte_b.clone()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(changes[0].before.as_ref().unwrap().rsrp, -90.0);
+        assert_eq!(changes[0].after.as_ref().unwrap().rsrp, -80.0);
+    }
+
+    #[test]
+    fn test_classify_carrier_changes() {
+        let primary = cell(CellularType::LTE, 1_815_000_000, 140, -90.0);
+        let scell_a = cell(CellularType::LTE, 806_000_000, 12, -85.0);
+        let scell_b = cell(CellularType::NR, 3_549_990_000, 512, -95.0);
+
+        assert_eq!(
+            change_types(
+                vec![primary.clone()],
+                vec![primary.clone(), scell_a.clone()]
+            ),
+            vec![CellChangeType::CarrierAdded]
+        );
+        assert_eq!(
+            change_types(
+                vec![primary.clone(), scell_a.clone()],
+                vec![primary.clone(), scell_b.clone()]
+            ),
+            vec![CellChangeType::CarrierRemoved, CellChangeType::CarrierAdded]
+        );
+        /* PCell swap with the SCell */
+        assert_eq!(
+            change_types(
+                vec![primary.clone(), scell_a.clone()],
+                vec![scell_a.clone(), primary.clone()]
+            ),
+            vec![
+                CellChangeType::InterFrequencyHandover,
+                CellChangeType::CarrierRemoved,
+                CellChangeType::CarrierAdded
+            ]
+        );
+    }
+}
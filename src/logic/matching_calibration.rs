@@ -0,0 +1,446 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::logic::matching_replay::{
+    evaluate_ranking, load_replay_runs, GroundTruth, ReplayResult, ReplayRun, ReplaySummary,
+};
+use crate::logic::rnti_matcher::MatchingWeightsProfile;
+use crate::logic::traffic_patterns::RntiMatchingTrafficPatternType;
+use crate::math_util::calculate_weighted_euclidean_distance;
+use crate::parse::{CalibrateMatchingArgs, FlattenedRntiMatchingArgs};
+use crate::util::print_info;
+
+/*
+ * Searches the matching weightings on labelled traffic collections
+ *
+ * The basic filter does not depend on the weightings, so the standardized
+ * feature vectors are determined once and every candidate only ranks them
+ * again. A candidate is better if it has a higher top-1 accuracy or, at the
+ * same accuracy, a larger mean margin to the closest other RNTI.
+ *
+ * Without a pattern, only the global weightings are calibrated. Collections
+ * of patterns with their own weightings are skipped, those are not affected.
+ *
+ * Search: random perturbations of the best weightings with a shrinking step,
+ * every fourth candidate is drawn freshly to leave local optima. Weightings
+ * are normalized to a sum of 1, otherwise scaling them would inflate the margin.
+ * */
+
+const CALIBRATION_INITIAL_STEP: f64 = 1.0;
+const CALIBRATION_MIN_STEP: f64 = 0.05;
+const CALIBRATION_FRESH_CANDIDATE_INTERVAL: u64 = 4;
+const CALIBRATION_ACCURACY_EPSILON: f64 = 1e-9;
+
+/// One cell of one collection after the basic filter
+#[derive(Clone, Debug, PartialEq)]
+struct CalibrationSample {
+    true_rnti: u16,
+    pattern_features: Vec<f64>,
+    rnti_features: Vec<(u16, Vec<f64>)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CalibrationScore {
+    accuracy: f64,
+    mean_margin: Option<f64>,
+}
+
+struct WeightingSearch {
+    state: u64,
+}
+
+impl CalibrationScore {
+    fn is_better_than(&self, other: &CalibrationScore) -> bool {
+        if (self.accuracy - other.accuracy).abs() > CALIBRATION_ACCURACY_EPSILON {
+            return self.accuracy > other.accuracy;
+        }
+        self.mean_margin.unwrap_or(f64::MIN) > other.mean_margin.unwrap_or(f64::MIN)
+    }
+}
+
+impl WeightingSearch {
+    fn new(seed: u64) -> WeightingSearch {
+        WeightingSearch { state: seed.max(1) }
+    }
+
+    /* xorshift64, the search only needs to be reproducible */
+    fn next_unit(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn fresh_candidate(&mut self, nof_features: usize) -> Vec<f64> {
+        normalize((0..nof_features).map(|_| self.next_unit()).collect())
+    }
+
+    fn perturbed_candidate(&mut self, weightings: &[f64], step: f64) -> Vec<f64> {
+        normalize(
+            weightings
+                .iter()
+                .map(|weighting| weighting * (step * (2.0 * self.next_unit() - 1.0)).exp())
+                .collect(),
+        )
+    }
+}
+
+fn normalize(weightings: Vec<f64>) -> Vec<f64> {
+    let sum: f64 = weightings.iter().sum();
+    if sum <= 0.0 {
+        return weightings;
+    }
+    weightings.iter().map(|weighting| weighting / sum).collect()
+}
+
+/// Patterns whose configured or loaded override replaces the global weightings
+fn patterns_with_own_weightings(
+    matching_args: &FlattenedRntiMatchingArgs,
+) -> Vec<RntiMatchingTrafficPatternType> {
+    matching_args
+        .matching_pattern_overrides
+        .iter()
+        .filter(|pattern_override| pattern_override.weightings.is_some())
+        .map(|pattern_override| pattern_override.pattern)
+        .collect()
+}
+
+/// Collections the matching fails on are skipped, like in the replay
+fn prepare_samples(
+    runs: &[ReplayRun],
+    matching_args: &FlattenedRntiMatchingArgs,
+    pattern: Option<RntiMatchingTrafficPatternType>,
+) -> Vec<CalibrationSample> {
+    let skipped_patterns = match pattern {
+        Some(_) => vec![],
+        None => patterns_with_own_weightings(matching_args),
+    };
+    let mut samples: Vec<CalibrationSample> = vec![];
+    for run in runs.iter() {
+        for collection in run.collections.iter() {
+            let pattern_type = collection.traffic_pattern_features.pattern_type;
+            if pattern.is_some_and(|pattern| pattern != pattern_type)
+                || skipped_patterns.contains(&pattern_type)
+            {
+                continue;
+            }
+            let mut collection = collection.clone();
+            collection.matching_parameters = matching_args.parameters_for(pattern_type);
+            collection.apply_basic_filter();
+
+            let pattern_std_vec = &collection.traffic_pattern_features.std_vec;
+            let mut cell_ids: Vec<u64> = collection.cell_traffic.keys().cloned().collect();
+            cell_ids.sort();
+            let collection_samples = cell_ids
+                .iter()
+                .map(|cell_id| {
+                    let rnti_features = collection.cell_traffic[cell_id]
+                        .traffic
+                        .iter()
+                        .map(|(&rnti, ue_traffic)| {
+                            Ok((
+                                rnti,
+                                ue_traffic.generate_standardized_feature_vec(pattern_std_vec)?,
+                            ))
+                        })
+                        .collect::<Result<Vec<(u16, Vec<f64>)>>>()?;
+                    Ok(CalibrationSample {
+                        true_rnti: run.true_rnti,
+                        pattern_features: collection
+                            .traffic_pattern_features
+                            .std_feature_vec
+                            .clone(),
+                        rnti_features,
+                    })
+                })
+                .collect::<Result<Vec<CalibrationSample>>>();
+            if let Ok(collection_samples) = collection_samples {
+                samples.extend(
+                    collection_samples
+                        .into_iter()
+                        .filter(|sample| !sample.rnti_features.is_empty()),
+                );
+            }
+        }
+    }
+    samples
+}
+
+/// Same order as TrafficCollection::rank_rntis, sorted by distance
+fn rank_sample(sample: &CalibrationSample, weightings: &[f64]) -> Vec<(u16, f64)> {
+    let mut rnti_and_distance: Vec<(u16, f64)> = sample
+        .rnti_features
+        .iter()
+        .map(|(rnti, features)| {
+            (
+                *rnti,
+                calculate_weighted_euclidean_distance(
+                    &sample.pattern_features,
+                    features,
+                    weightings,
+                ),
+            )
+        })
+        .collect();
+    rnti_and_distance.sort_by(|a, b| a.1.total_cmp(&b.1));
+    rnti_and_distance
+}
+
+fn evaluate_weightings(samples: &[CalibrationSample], weightings: &[f64]) -> CalibrationScore {
+    let mut nof_correct = 0;
+    let mut margins: Vec<f64> = vec![];
+    for sample in samples.iter() {
+        let rnti_and_distance = rank_sample(sample, weightings);
+        let evaluation = evaluate_ranking(&rnti_and_distance, sample.true_rnti);
+        if evaluation.true_rank == Some(1) {
+            nof_correct += 1;
+        }
+        if let Some(margin) = evaluation.margin {
+            margins.push(margin);
+        }
+    }
+    CalibrationScore {
+        accuracy: nof_correct as f64 / samples.len().max(1) as f64,
+        mean_margin: match margins.is_empty() {
+            true => None,
+            false => Some(margins.iter().sum::<f64>() / margins.len() as f64),
+        },
+    }
+}
+
+fn search_weightings(
+    samples: &[CalibrationSample],
+    start_weightings: &[f64],
+    iterations: u64,
+    seed: u64,
+) -> (Vec<f64>, CalibrationScore) {
+    let mut search = WeightingSearch::new(seed);
+    let mut best_weightings = normalize(start_weightings.to_vec());
+    let mut best_score = evaluate_weightings(samples, &best_weightings);
+    for iteration in 0..iterations {
+        let progress = iteration as f64 / iterations as f64;
+        let step = CALIBRATION_MIN_STEP
+            + (CALIBRATION_INITIAL_STEP - CALIBRATION_MIN_STEP) * (1.0 - progress);
+        let candidate = match iteration % CALIBRATION_FRESH_CANDIDATE_INTERVAL {
+            0 => search.fresh_candidate(best_weightings.len()),
+            _ => search.perturbed_candidate(&best_weightings, step),
+        };
+        let score = evaluate_weightings(samples, &candidate);
+        if score.is_better_than(&best_score) {
+            best_weightings = candidate;
+            best_score = score;
+        }
+    }
+    (best_weightings, best_score)
+}
+
+fn replay_summary(runs: &[ReplayRun], matching_args: &FlattenedRntiMatchingArgs) -> ReplaySummary {
+    let results: Vec<ReplayResult> = runs.iter().map(|run| run.replay(matching_args)).collect();
+    let results_with_truth: Vec<(&ReplayResult, u16)> = results
+        .iter()
+        .zip(runs.iter())
+        .map(|(result, run)| (result, run.true_rnti))
+        .collect();
+    ReplaySummary::from_results(&results_with_truth)
+}
+
+pub fn run_calibrate_matching(
+    matching_args: &FlattenedRntiMatchingArgs,
+    calibrate_args: &CalibrateMatchingArgs,
+) -> Result<()> {
+    let ground_truth = GroundTruth::parse(&calibrate_args.replay.replay_ground_truth)?;
+    let runs = load_replay_runs(
+        Path::new(&calibrate_args.replay.replay_source),
+        &ground_truth,
+    )?;
+    let pattern = calibrate_args.calibrate_pattern;
+    if pattern.is_none() {
+        let skipped_patterns = patterns_with_own_weightings(matching_args);
+        if !skipped_patterns.is_empty() {
+            print_info(&format!(
+                "[calibrate] skipping patterns {:?}, their own weightings are not affected by the global weightings (use --calibrate-pattern)",
+                skipped_patterns
+            ));
+        }
+    }
+    let samples = prepare_samples(&runs, matching_args, pattern);
+    if samples.is_empty() {
+        return Err(anyhow!("no matchable traffic collections to calibrate on"));
+    }
+
+    let start_weightings = match pattern {
+        Some(pattern) => matching_args.parameters_for(pattern).weightings,
+        None => matching_args.matching_parameters.weightings.clone(),
+    };
+    let start_score = evaluate_weightings(&samples, &normalize(start_weightings.clone()));
+    let (weightings, score) = search_weightings(
+        &samples,
+        &start_weightings,
+        calibrate_args.calibrate_iterations,
+        calibrate_args.calibrate_seed,
+    );
+    print_info(&format!(
+        "[calibrate] {} samples: accuracy {:.3} -> {:.3}, mean margin {:?} -> {:?}",
+        samples.len(),
+        start_score.accuracy,
+        score.accuracy,
+        start_score.mean_margin,
+        score.mean_margin
+    ));
+    print_info(&format!("[calibrate] weightings: {:.4?}", weightings));
+
+    let profile = MatchingWeightsProfile {
+        pattern,
+        weightings,
+        accuracy: score.accuracy,
+        mean_margin: score.mean_margin,
+        nof_samples: samples.len(),
+    };
+    let mut calibrated_args = matching_args.clone();
+    calibrated_args.apply_weights_profile(&profile);
+    replay_summary(&runs, matching_args).print("replay before");
+    replay_summary(&runs, &calibrated_args).print("replay after");
+
+    profile.store(&calibrate_args.calibrate_output)?;
+    print_info(&format!(
+        "[calibrate] weights profile written to {}, load it with --matching-weights-profile",
+        calibrate_args.calibrate_output
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::matching_replay::tests::{dummy_collection, dummy_matching_args};
+    use crate::logic::rnti_matcher::DEFAULT_MATCHING_WEIGHTINGS;
+    use crate::parse::{Arguments, MatchingPatternOverride};
+    use std::path::PathBuf;
+
+    const TRUE_RNTI: u16 = 17921;
+
+    /// The true RNTI differs from the pattern only in the last feature, which
+    /// the default weightings underrate compared to the other RNTI's offset
+    fn dummy_samples() -> Vec<CalibrationSample> {
+        (0..4)
+            .map(|index| CalibrationSample {
+                true_rnti: TRUE_RNTI,
+                pattern_features: vec![0.0; 8],
+                rnti_features: vec![
+                    (TRUE_RNTI, vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 10.0]),
+                    (100 + index, vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0]),
+                ],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_search_weightings() {
+        let samples = dummy_samples();
+        let start_score = evaluate_weightings(&samples, &DEFAULT_MATCHING_WEIGHTINGS);
+        assert_eq!(start_score.accuracy, 0.0);
+
+        let (weightings, score) = search_weightings(&samples, &DEFAULT_MATCHING_WEIGHTINGS, 200, 1);
+        assert_eq!(score.accuracy, 1.0);
+        assert!(score.mean_margin.unwrap() > 0.0);
+        assert!((weightings.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        /* reproducible with the same seed */
+        assert_eq!(
+            search_weightings(&samples, &DEFAULT_MATCHING_WEIGHTINGS, 200, 1).0,
+            weightings
+        );
+    }
+
+    fn dummy_runs() -> Result<Vec<ReplayRun>> {
+        Ok(vec![ReplayRun {
+            path: PathBuf::from("dummy"),
+            true_rnti: TRUE_RNTI,
+            collections: vec![dummy_collection()?],
+        }])
+    }
+
+    #[test]
+    fn test_rank_sample_like_rank_rntis() -> Result<()> {
+        let matching_args = dummy_matching_args()?;
+        let mut collection = dummy_collection()?;
+        collection.matching_parameters =
+            matching_args.parameters_for(collection.traffic_pattern_features.pattern_type);
+        collection.apply_basic_filter();
+        let ranking = collection.rank_rntis()?.remove(&1).unwrap();
+        assert!(ranking.len() > 1);
+
+        let samples = prepare_samples(&dummy_runs()?, &matching_args, None);
+        assert_eq!(samples.len(), 1);
+        let sample_ranking =
+            rank_sample(&samples[0], &matching_args.matching_parameters.weightings);
+        let rntis =
+            |ranking: &[(u16, f64)]| ranking.iter().map(|&(rnti, _)| rnti).collect::<Vec<u16>>();
+        assert_eq!(rntis(&sample_ranking), rntis(&ranking));
+        for ((_, distance), (_, sample_distance)) in ranking.iter().zip(sample_ranking.iter()) {
+            assert!((distance - sample_distance).abs() < 1e-9);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_patterns_with_own_weightings() -> Result<()> {
+        let mut matching_args = dummy_matching_args()?;
+        matching_args
+            .matching_pattern_overrides
+            .push(MatchingPatternOverride {
+                pattern: RntiMatchingTrafficPatternType::A,
+                weightings: Some(DEFAULT_MATCHING_WEIGHTINGS.to_vec()),
+                time_overlap_factor: None,
+                filter_max_total_ul_factor: None,
+                filter_min_total_ul_factor: None,
+                filter_max_ul_per_dci: None,
+                filter_min_occurences_factor: None,
+            });
+        let runs = dummy_runs()?;
+        assert!(prepare_samples(&runs, &matching_args, None).is_empty());
+        assert_eq!(
+            prepare_samples(
+                &runs,
+                &matching_args,
+                Some(RntiMatchingTrafficPatternType::A)
+            )
+            .len(),
+            1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_weights_profile() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("{}-weights.yaml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let weightings = vec![0.3, 0.3, 0.1, 0.2, 0.025, 0.025, 0.025, 0.025];
+        MatchingWeightsProfile {
+            pattern: Some(RntiMatchingTrafficPatternType::D),
+            weightings: weightings.clone(),
+            accuracy: 0.9,
+            mean_margin: Some(0.2),
+            nof_samples: 10,
+        }
+        .store(&path)?;
+
+        let mut rnti_args = Arguments::default().rntimatching.unwrap();
+        rnti_args.matching_weights_profile = Some(vec![path.clone()]);
+        let matching_args = FlattenedRntiMatchingArgs::from_unflattened(rnti_args)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            matching_args
+                .parameters_for(RntiMatchingTrafficPatternType::D)
+                .weightings,
+            weightings
+        );
+        assert_eq!(
+            matching_args
+                .parameters_for(RntiMatchingTrafficPatternType::A)
+                .weightings,
+            DEFAULT_MATCHING_WEIGHTINGS.to_vec()
+        );
+        Ok(())
+    }
+}
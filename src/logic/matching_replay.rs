@@ -0,0 +1,428 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde_derive::Serialize;
+
+use crate::logic::rnti_matcher::TrafficCollection;
+use crate::parse::{FlattenedRntiMatchingArgs, ReplayMatchingArgs};
+use crate::util::{print_info, CellRntiRingBuffer};
+
+/*
+ * Offline RNTI matching on logged traffic collections
+ *
+ * Every collection is filtered and ranked again with the configured matching
+ * parameters. A sample is one cell of one collection, it is compared with the
+ * true RNTI of the run. The voted RNTI is the one the tracker would publish
+ * (ring buffer over the run's previous matches).
+ *
+ * Logs of older versions only contain the RNTIs that passed the basic filter,
+ * looser filters cannot bring back the dropped RNTIs.
+ * */
+
+const TRAFFIC_COLLECTION_FILE_SUFFIX: &str = "_traffic_collection.jsonl";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroundTruth {
+    /* (part of the run path, RNTI), None matches every run */
+    entries: Vec<(Option<String>, u16)>,
+}
+
+pub struct ReplayRun {
+    pub path: PathBuf,
+    pub true_rnti: u16,
+    pub collections: Vec<TrafficCollection>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ReplaySample {
+    pub cell_id: u64,
+    pub best_rnti: Option<u16>,
+    pub voted_rnti: Option<u16>,
+    /// 1 is the best match, None if the true RNTI was not ranked
+    pub true_rank: Option<usize>,
+    /// Distance of the closest other RNTI minus the true RNTI's distance,
+    /// negative if another RNTI matched better
+    pub margin: Option<f64>,
+    /// The true RNTI was in the collection but removed by the basic filter
+    pub true_rnti_filtered: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ReplayResult {
+    pub samples: Vec<ReplaySample>,
+    /// Collections the matching failed on (e.g. too few DCIs)
+    pub nof_failed: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ReplaySummary {
+    pub nof_samples: usize,
+    pub nof_failed: usize,
+    pub nof_true_rnti_missing: usize,
+    pub nof_true_rnti_filtered: usize,
+    pub accuracy: f64,
+    pub voted_accuracy: f64,
+    pub mean_rank: Option<f64>,
+    pub mean_margin: Option<f64>,
+    pub min_margin: Option<f64>,
+}
+
+impl GroundTruth {
+    pub fn parse(specs: &[String]) -> Result<GroundTruth> {
+        let entries = specs
+            .iter()
+            .map(|spec| {
+                let (run, rnti) = match spec.rsplit_once('=') {
+                    Some((run, rnti)) => (Some(run.trim().to_string()), rnti),
+                    None => (None, spec.as_str()),
+                };
+                let rnti = rnti
+                    .trim()
+                    .parse::<u16>()
+                    .map_err(|err| anyhow!("invalid RNTI in ground truth {:?}: {}", spec, err))?;
+                Ok((run, rnti))
+            })
+            .collect::<Result<Vec<(Option<String>, u16)>>>()?;
+        Ok(GroundTruth { entries })
+    }
+
+    /// A run specific entry wins over an entry for all runs
+    pub fn rnti_for(&self, path: &Path) -> Option<u16> {
+        let path = path.to_string_lossy();
+        self.entries
+            .iter()
+            .find(|(run, _)| run.as_ref().is_some_and(|run| path.contains(run.as_str())))
+            .or_else(|| self.entries.iter().find(|(run, _)| run.is_none()))
+            .map(|&(_, rnti)| rnti)
+    }
+}
+
+/// A file is taken as is, a directory is searched recursively
+pub fn find_traffic_collection_files(source: &Path) -> Result<Vec<PathBuf>> {
+    if source.is_file() {
+        return Ok(vec![source.to_path_buf()]);
+    }
+    let mut files: Vec<PathBuf> = vec![];
+    for entry in fs::read_dir(source)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(find_traffic_collection_files(&path)?);
+        } else if path
+            .to_string_lossy()
+            .ends_with(TRAFFIC_COLLECTION_FILE_SUFFIX)
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub fn load_traffic_collections(path: &Path) -> Result<Vec<TrafficCollection>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut collections: Vec<TrafficCollection> = vec![];
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let collection = serde_json::from_str::<TrafficCollection>(&line)
+            .map_err(|err| anyhow!("{}:{}: {}", path.display(), index + 1, err))?;
+        collections.push(collection);
+    }
+    Ok(collections)
+}
+
+/// Runs without ground truth are skipped
+pub fn load_replay_runs(source: &Path, ground_truth: &GroundTruth) -> Result<Vec<ReplayRun>> {
+    let mut runs: Vec<ReplayRun> = vec![];
+    for path in find_traffic_collection_files(source)? {
+        let true_rnti = match ground_truth.rnti_for(&path) {
+            Some(true_rnti) => true_rnti,
+            None => {
+                print_info(&format!(
+                    "[replay] skipping {}: no ground truth RNTI",
+                    path.display()
+                ));
+                continue;
+            }
+        };
+        let collections = load_traffic_collections(&path)?;
+        runs.push(ReplayRun {
+            path,
+            true_rnti,
+            collections,
+        });
+    }
+    if runs.is_empty() {
+        return Err(anyhow!(
+            "no traffic collections with ground truth in {}",
+            source.display()
+        ));
+    }
+    Ok(runs)
+}
+
+impl ReplayRun {
+    pub fn replay(&self, matching_args: &FlattenedRntiMatchingArgs) -> ReplayResult {
+        let mut result = ReplayResult::default();
+        let mut ring_buffer = CellRntiRingBuffer::new(matching_args.matching_rnti_ring_buffer_size);
+        for collection in self.collections.iter() {
+            let mut collection = collection.clone();
+            collection.matching_parameters =
+                matching_args.parameters_for(collection.traffic_pattern_features.pattern_type);
+            let cells_with_true_rnti: Vec<u64> = collection
+                .cell_traffic
+                .iter()
+                .filter(|(_, cell_traffic)| cell_traffic.traffic.contains_key(&self.true_rnti))
+                .map(|(&cell_id, _)| cell_id)
+                .collect();
+
+            collection.apply_basic_filter();
+            let ranking = match collection.rank_rntis() {
+                Ok(ranking) => ranking,
+                Err(_) => {
+                    result.nof_failed += 1;
+                    continue;
+                }
+            };
+            let best_matches = ranking
+                .iter()
+                .filter_map(|(&cell_id, rnti_and_distance)| {
+                    rnti_and_distance.first().map(|&(rnti, _)| (cell_id, rnti))
+                })
+                .collect();
+            ring_buffer.update(&best_matches);
+            let voted_matches = ring_buffer.most_frequent();
+
+            let mut cell_ids: Vec<u64> = ranking.keys().cloned().collect();
+            cell_ids.sort();
+            for cell_id in cell_ids {
+                let mut sample = evaluate_ranking(&ranking[&cell_id], self.true_rnti);
+                sample.cell_id = cell_id;
+                sample.voted_rnti = voted_matches.get(&cell_id).cloned();
+                sample.true_rnti_filtered =
+                    sample.true_rank.is_none() && cells_with_true_rnti.contains(&cell_id);
+                result.samples.push(sample);
+            }
+        }
+        result
+    }
+}
+
+/// The ranking is sorted by distance, as returned by TrafficCollection::rank_rntis
+pub fn evaluate_ranking(rnti_and_distance: &[(u16, f64)], true_rnti: u16) -> ReplaySample {
+    let true_position = rnti_and_distance
+        .iter()
+        .position(|&(rnti, _)| rnti == true_rnti);
+    let closest_other = rnti_and_distance
+        .iter()
+        .find(|&&(rnti, _)| rnti != true_rnti)
+        .map(|&(_, distance)| distance.abs());
+    ReplaySample {
+        best_rnti: rnti_and_distance.first().map(|&(rnti, _)| rnti),
+        true_rank: true_position.map(|position| position + 1),
+        margin: match (true_position, closest_other) {
+            (Some(position), Some(other_distance)) => {
+                Some(other_distance - rnti_and_distance[position].1.abs())
+            }
+            _ => None,
+        },
+        ..Default::default()
+    }
+}
+
+impl ReplaySummary {
+    pub fn from_results(results: &[(&ReplayResult, u16)]) -> ReplaySummary {
+        let mut summary = ReplaySummary::default();
+        let mut nof_correct = 0;
+        let mut nof_voted_correct = 0;
+        let mut ranks: Vec<f64> = vec![];
+        let mut margins: Vec<f64> = vec![];
+        for (result, true_rnti) in results.iter() {
+            summary.nof_failed += result.nof_failed;
+            for sample in result.samples.iter() {
+                summary.nof_samples += 1;
+                if sample.best_rnti == Some(*true_rnti) {
+                    nof_correct += 1;
+                }
+                if sample.voted_rnti == Some(*true_rnti) {
+                    nof_voted_correct += 1;
+                }
+                match sample.true_rank {
+                    Some(rank) => ranks.push(rank as f64),
+                    None if sample.true_rnti_filtered => summary.nof_true_rnti_filtered += 1,
+                    None => summary.nof_true_rnti_missing += 1,
+                }
+                if let Some(margin) = sample.margin {
+                    margins.push(margin);
+                }
+            }
+        }
+        if summary.nof_samples > 0 {
+            summary.accuracy = nof_correct as f64 / summary.nof_samples as f64;
+            summary.voted_accuracy = nof_voted_correct as f64 / summary.nof_samples as f64;
+        }
+        if !ranks.is_empty() {
+            summary.mean_rank = Some(ranks.iter().sum::<f64>() / ranks.len() as f64);
+        }
+        if !margins.is_empty() {
+            summary.mean_margin = Some(margins.iter().sum::<f64>() / margins.len() as f64);
+            summary.min_margin = margins.iter().cloned().reduce(f64::min);
+        }
+        summary
+    }
+
+    pub fn print(&self, name: &str) {
+        let format_option = |value: Option<f64>| match value {
+            Some(value) => format!("{:.3}", value),
+            None => "-".to_string(),
+        };
+        print_info(&format!(
+            "[replay] {}: {} samples, accuracy {:.3} (voted {:.3}), mean rank {}, margin mean {} min {}, true RNTI missing {} filtered {}, failed {}",
+            name,
+            self.nof_samples,
+            self.accuracy,
+            self.voted_accuracy,
+            format_option(self.mean_rank),
+            format_option(self.mean_margin),
+            format_option(self.min_margin),
+            self.nof_true_rnti_missing,
+            self.nof_true_rnti_filtered,
+            self.nof_failed
+        ));
+    }
+}
+
+pub fn run_replay_matching(
+    matching_args: &FlattenedRntiMatchingArgs,
+    replay_args: &ReplayMatchingArgs,
+) -> Result<()> {
+    let ground_truth = GroundTruth::parse(&replay_args.replay_ground_truth)?;
+    let runs = load_replay_runs(Path::new(&replay_args.replay_source), &ground_truth)?;
+    let results: Vec<ReplayResult> = runs.iter().map(|run| run.replay(matching_args)).collect();
+    for (run, result) in runs.iter().zip(results.iter()) {
+        ReplaySummary::from_results(&[(result, run.true_rnti)])
+            .print(&run.path.display().to_string());
+    }
+    let all_results: Vec<(&ReplayResult, u16)> = results
+        .iter()
+        .zip(runs.iter())
+        .map(|(result, run)| (result, run.true_rnti))
+        .collect();
+    ReplaySummary::from_results(&all_results).print("total");
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::logic::rnti_matcher::{Traffic, UeTraffic};
+    use crate::logic::traffic_patterns::{RntiMatchingTrafficPatternType, TrafficPatternFeatures};
+    use crate::parse::Arguments;
+    use std::io::Write;
+
+    pub(crate) const TRUE_RNTI: u16 = 17921;
+
+    /// UE traffic with one DCI per pattern message, scaled in size and interval
+    fn ue_traffic(pattern_type: RntiMatchingTrafficPatternType, scale: f64) -> UeTraffic {
+        let mut ue_traffic = UeTraffic::default();
+        let mut timestamp_us: u64 = 1_000_000;
+        for message in pattern_type.generate_pattern().messages.iter() {
+            let ul_bytes = (message.payload.len() as f64 * scale) as u64;
+            timestamp_us += (message.time_ms as f64 * 1000.0 * scale) as u64;
+            ue_traffic.traffic.insert(
+                timestamp_us,
+                Traffic {
+                    dl_bytes: 0,
+                    ul_bytes,
+                },
+            );
+            ue_traffic.total_ul_bytes += ul_bytes;
+        }
+        ue_traffic
+    }
+
+    pub(crate) fn dummy_collection() -> Result<TrafficCollection> {
+        let pattern_type = RntiMatchingTrafficPatternType::A;
+        let mut collection = TrafficCollection {
+            traffic_pattern_features: TrafficPatternFeatures::from_traffic_pattern(
+                &pattern_type.generate_pattern(),
+            )?,
+            ..Default::default()
+        };
+        let cell_traffic = collection.cell_traffic.entry(1).or_default();
+        cell_traffic
+            .traffic
+            .insert(TRUE_RNTI, ue_traffic(pattern_type, 1.0));
+        cell_traffic
+            .traffic
+            .insert(100, ue_traffic(pattern_type, 1.5));
+        cell_traffic
+            .traffic
+            .insert(200, ue_traffic(pattern_type, 0.6));
+        Ok(collection)
+    }
+
+    pub(crate) fn dummy_matching_args() -> Result<FlattenedRntiMatchingArgs> {
+        FlattenedRntiMatchingArgs::from_unflattened(Arguments::default().rntimatching.unwrap())
+    }
+
+    #[test]
+    fn test_ground_truth() -> Result<()> {
+        let ground_truth =
+            GroundTruth::parse(&["100".to_string(), "run_2024-05-01=17921".to_string()])?;
+        assert_eq!(
+            ground_truth.rnti_for(Path::new("logs/run_2024-05-01_traffic_collection.jsonl")),
+            Some(17921)
+        );
+        assert_eq!(
+            ground_truth.rnti_for(Path::new("logs/run_2024-05-02_traffic_collection.jsonl")),
+            Some(100)
+        );
+        assert!(GroundTruth::parse(&["run=abc".to_string()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_ranking() {
+        let ranking = [(1, 0.5), (2, 0.75), (3, 1.0)];
+        let sample = evaluate_ranking(&ranking, 1);
+        assert_eq!(sample.true_rank, Some(1));
+        assert_eq!(sample.margin, Some(0.25));
+        let sample = evaluate_ranking(&ranking, 3);
+        assert_eq!(sample.best_rnti, Some(1));
+        assert_eq!(sample.true_rank, Some(3));
+        assert_eq!(sample.margin, Some(-0.5));
+        assert_eq!(evaluate_ranking(&ranking, 4).true_rank, None);
+    }
+
+    #[test]
+    fn test_replay_run() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "{}-replay{}",
+            std::process::id(),
+            TRAFFIC_COLLECTION_FILE_SUFFIX
+        ));
+        let mut file = File::create(&path)?;
+        for _ in 0..3 {
+            writeln!(file, "{}", serde_json::to_string(&dummy_collection()?)?)?;
+        }
+        let ground_truth = GroundTruth::parse(&[TRUE_RNTI.to_string()])?;
+        let runs = load_replay_runs(&path, &ground_truth)?;
+        fs::remove_file(&path)?;
+        assert_eq!(runs[0].collections.len(), 3);
+
+        let result = runs[0].replay(&dummy_matching_args()?);
+        let summary = ReplaySummary::from_results(&[(&result, TRUE_RNTI)]);
+        assert_eq!(summary.nof_samples, 3);
+        assert_eq!(summary.nof_failed, 0);
+        assert_eq!(summary.accuracy, 1.0);
+        assert_eq!(summary.voted_accuracy, 1.0);
+        assert_eq!(summary.mean_rank, Some(1.0));
+        assert!(summary.min_margin.unwrap() > 0.0);
+        Ok(())
+    }
+}
@@ -11,12 +11,18 @@ use bus::BusReader;
 use crate::cell_info::CellInfo;
 use crate::logic::rnti_matcher::TrafficCollection;
 use crate::ngscope::config::NgScopeConfig;
-use crate::ngscope::types::{NgScopeCellDci, NgScopeCellConfig};
+use crate::ngscope::types::{NgScopeCellConfig, NgScopeCellDci, NgScopeUeDci};
 
 use self::downloader::{DownloadConfig, DownloadFinishParameters};
+use self::handover::CellChange;
 
 pub mod cell_source;
+pub mod dci_jitter_buffer;
+pub mod dci_quality;
 pub mod downloader;
+pub mod handover;
+pub mod matching_calibration;
+pub mod matching_replay;
 pub mod model_handler;
 pub mod ngscope_controller;
 pub mod rnti_matcher;
@@ -190,6 +196,7 @@ pub enum RntiMatchingErrorType {
     ExceededDciTimestampDelta,
     ErrorGeneratingTrafficPatternFeatures,
     ErrorFindingBestMatchingRnti,
+    DciStaleDuringCollection,
 }
 
 impl WorkerState for RntiMatcherState {
@@ -272,12 +279,34 @@ impl WorkerState for DownloaderState {
 pub enum MessageDci {
     CellDci(Box<NgScopeCellDci>),
     CellConfig(Box<NgScopeCellConfig>),
+    /// Per-UE DCI, only sent by ng-scope in decode_single_ue mode
+    UeDci(Box<NgScopeUeDci>),
+    /// All carriers ng-scope decodes, sent along with every CellConfig
+    CellCarriers(Vec<NgScopeCarrier>),
+    /// No DCI arrived for the silence timeout, contains the timestamp (us) of the last one
+    DciStale(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct NgScopeCarrier {
+    /// ng-scope cell index (rf_config<n> and NgScopeCellDci.cell_id)
+    pub cell_idx: u8,
+    /// Cell ID as reported by the cell API, if known
+    pub cell_id: Option<u64>,
+    /// Number of PRB per slot
+    pub nof_prb: u16,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Debug)]
-pub struct MessageCellInfo {
-    cell_info: CellInfo,
+pub enum MessageCellInfo {
+    /// Cells currently reported by the cell source
+    Cells(CellInfo),
+    /// The cell source delivered no data for the stale timeout
+    CellUnknown,
+    /// Handover or carrier change, sent before the new cells
+    CellChanged(Box<CellChange>),
+    /// Neighbour cells changed while the connected cells did not
+    Neighbours(CellInfo),
 }
 
 #[allow(dead_code)]
@@ -1,8 +1,8 @@
 use crate::logger::log_metric;
-use crate::ngscope::types::{NgScopeCellDci, NgScopeRntiDci};
+use crate::ngscope::types::{NgScopeCellDci, NgScopeRntiDci, NgScopeUeDci};
 use crate::parse::{Arguments, DynamicValue, FlattenedModelArgs, Scenario};
 use crate::util::{print_debug, print_info};
-use std::collections::{HashSet, HashMap};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::mpsc::{SyncSender, TryRecvError};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
@@ -11,6 +11,7 @@ use anyhow::{anyhow, Result};
 use bus::{Bus, BusReader};
 use serde_derive::{Deserialize, Serialize};
 
+use super::dci_quality::{dci_quality_live, DciQualityLive};
 use super::{MessageDownloadConfig, MetricA, MetricTypes};
 use crate::logic::{
     check_not_stopped, wait_until_running, MainState, MessageDci, MessageMetric,
@@ -27,6 +28,9 @@ pub const PHYSICAL_TO_TRANSPORT_FACTOR: f64 = 1.0 - PHYSICAL_TO_TRANSPORT_OVERHE
 pub const STANDARD_NOF_PRB_SLOT_TO_SUBFRAME: u64 = 2;
 pub const STANDARD_BIT_PER_PRB: u64 = 500; /* Chosen from historical data */
 
+/* ng-scope cell index of the primary carrier (rf_config0) */
+pub const PRIMARY_CELL_IDX: u8 = 0;
+
 pub const RNTI_SHARE_TYPE_ALL: u8 = 0;
 pub const RNTI_SHARE_TYPE_DL_OCCURENCES: u8 = 1;
 pub const RNTI_SHARE_TYPE_GREEDY: u8 = 2;
@@ -83,6 +87,40 @@ impl DciRingBuffer {
         self.dci_next = 0;
         &self.dci_array[delta_index..old_dci_next]
     }
+
+    fn clear(&mut self) {
+        self.dci_next = 0;
+    }
+}
+
+/*
+ * Keeps the latest UE DCIs (only sent by ng-scope in decode_single_ue mode)
+ * */
+struct UeDciBuffer {
+    ue_dci_deque: VecDeque<NgScopeUeDci>,
+}
+
+impl UeDciBuffer {
+    fn new() -> UeDciBuffer {
+        UeDciBuffer {
+            ue_dci_deque: VecDeque::with_capacity(MAX_DCI_ARRAY_SIZE),
+        }
+    }
+
+    fn push(&mut self, item: NgScopeUeDci) {
+        if self.ue_dci_deque.len() >= MAX_DCI_ARRAY_SIZE {
+            self.ue_dci_deque.pop_front();
+        }
+        self.ue_dci_deque.push_back(item);
+    }
+
+    fn as_slice(&mut self) -> &[NgScopeUeDci] {
+        self.ue_dci_deque.make_contiguous()
+    }
+
+    fn clear(&mut self) {
+        self.ue_dci_deque.clear();
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
@@ -99,6 +137,9 @@ pub struct MetricResult {
     /// If 0, super fair
     pub fair_share_type: u8,
     pub no_tbs_prb_ratio: f64,
+    /// Share of the target-RNTI DL TBS that were re-transmissions (from UE DCIs).
+    /// The transport capacity is reduced by this share, as re-transmissions carry no new data.
+    pub dl_re_tx_ratio: f64,
 }
 
 #[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
@@ -117,6 +158,11 @@ pub struct MetricBasis {
     pub p_alloc_rnti: u64,
     pub p_alloc_no_tbs_rnti: u64,
     pub p_alloc_rnti_suggested: u64,
+    pub nof_ue_dci: u64,
+    pub nof_ue_dci_dl_re_tx: u64,
+    pub nof_ue_dci_ul_re_tx: u64,
+    pub tbs_ue_dci_bit: u64,
+    pub tbs_ue_dci_dl_re_tx_bit: u64,
 }
 
 #[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
@@ -124,6 +170,9 @@ pub struct LogMetric {
     timestamp_us: u64,
     result: MetricResult,
     basis: MetricBasis,
+    /// Cumulative DCI loss counters at the time of the calculation
+    #[serde(default)]
+    dci_quality: DciQualityLive,
 }
 
 pub struct ModelHandlerArgs {
@@ -149,6 +198,7 @@ struct RunArgs {
 struct RunParameters<'a> {
     tx_metric: &'a mut Bus<MessageMetric>,
     dci_buffer: &'a mut DciRingBuffer,
+    ue_dci_buffer: &'a mut UeDciBuffer,
     rnti: u16,
     cell_capacity_prb_per_slot: u16,
     is_log_metric: &'a bool,
@@ -214,6 +264,7 @@ fn run(run_args: &mut RunArgs) -> Result<()> {
     let is_log_metric: bool = model_args.model_log_metric;
     let mut last_metric_timestamp_us: u64 = chrono::Local::now().timestamp_micros() as u64;
     let mut dci_buffer = DciRingBuffer::new();
+    let mut ue_dci_buffer = UeDciBuffer::new();
     let mut last_rnti: Option<u16> = None;
     let mut last_cell_capacity: Option<u16> = None;
     let mut last_rnti_share_type: u8 = RNTI_SHARE_TYPE_ALL;
@@ -227,7 +278,12 @@ fn run(run_args: &mut RunArgs) -> Result<()> {
         if check_not_stopped(rx_app_state).is_err() {
             break;
         }
-        unpack_all_dci_messages(rx_dci, &mut dci_buffer, &mut last_cell_capacity)?;
+        unpack_all_dci_messages(
+            rx_dci,
+            &mut dci_buffer,
+            &mut ue_dci_buffer,
+            &mut last_cell_capacity,
+        )?;
         match rx_rnti.try_recv() {
             Ok(rnti_msg) => {
                 if let Some(rnti) = rnti_msg.cell_rnti.values().copied().next() {
@@ -263,6 +319,7 @@ fn run(run_args: &mut RunArgs) -> Result<()> {
                 let mut run_params = RunParameters {
                     tx_metric,
                     dci_buffer: &mut dci_buffer,
+                    ue_dci_buffer: &mut ue_dci_buffer,
                     rnti,
                     cell_capacity_prb_per_slot,
                     is_log_metric: &is_log_metric,
@@ -299,6 +356,7 @@ fn finish(run_args: RunArgs) {
 fn unpack_all_dci_messages(
     rx_dci: &mut BusReader<MessageDci>,
     dci_buffer: &mut DciRingBuffer,
+    ue_dci_buffer: &mut UeDciBuffer,
     last_cell_capacity: &mut Option<u16>,
 ) -> Result<()> {
 
@@ -307,13 +365,31 @@ fn unpack_all_dci_messages(
             Ok(dci) => {
                     match dci {
                         MessageDci::CellDci(ngscope_dci) => {
-                            dci_buffer.push(*ngscope_dci)
+                            /* the model only considers the primary carrier */
+                            if ngscope_dci.cell_id == PRIMARY_CELL_IDX {
+                                dci_buffer.push(*ngscope_dci)
+                            }
                         }
                         MessageDci::CellConfig(ngscope_cell_config) => {
-                            if ngscope_cell_config.nof_cell == 1 {
-                                *last_cell_capacity = Some(ngscope_cell_config.cell_prb[0])
+                            if ngscope_cell_config.nof_cell >= 1 {
+                                *last_cell_capacity = Some(ngscope_cell_config.cell_prb[PRIMARY_CELL_IDX as usize])
                             }
                         }
+                        MessageDci::CellCarriers(carriers) => {
+                            print_debug(&format!("DEBUG [model] carriers: {:?}", carriers));
+                        }
+                        MessageDci::UeDci(ngscope_ue_dci) => {
+                            ue_dci_buffer.push(*ngscope_ue_dci)
+                        }
+                        MessageDci::DciStale(last_dci_timestamp_us) => {
+                            /* do not estimate the capacity from outdated DCIs */
+                            print_info(&format!(
+                                "[model] DCIs are stale (last DCI at {} us), clearing buffers",
+                                last_dci_timestamp_us
+                            ));
+                            dci_buffer.clear();
+                            ue_dci_buffer.clear();
+                        }
                     }
             },
             Err(TryRecvError::Empty) => break,
@@ -331,6 +407,7 @@ fn handle_calculate_metric(
     let RunParameters {
         tx_metric,
         dci_buffer,
+        ue_dci_buffer,
         rnti,
         cell_capacity_prb_per_slot,
         is_log_metric,
@@ -352,6 +429,7 @@ fn handle_calculate_metric(
             *rnti,
             *cell_capacity_prb_per_slot,
             buffer_slice,
+            ue_dci_buffer.as_slice(),
             is_log_metric,
             rnti_share_type,
         ) {
@@ -383,11 +461,17 @@ fn calculate_capacity(
     target_rnti: u16,
     cell_capacity_prb_per_slot: u16,
     dci_list: &[NgScopeCellDci],
+    ue_dci_list: &[NgScopeUeDci],
     is_log_metric: &bool,
     rnti_share_type: &u8,
 ) -> Result<LogMetric> {
-    let metric_wrapper =
-        calculate_pbe_cc_capacity(target_rnti, cell_capacity_prb_per_slot, dci_list, rnti_share_type)?;
+    let metric_wrapper = calculate_pbe_cc_capacity(
+        target_rnti,
+        cell_capacity_prb_per_slot,
+        dci_list,
+        ue_dci_list,
+        rnti_share_type,
+    )?;
     if *is_log_metric {
         let _ = log_metric(metric_wrapper.clone());
     }
@@ -397,7 +481,8 @@ fn calculate_capacity(
                                  c_p:      \t{:6?} bit/ms | {:3.3?} Mbit/s
                                  phy rate: \t{:6?} bit/PRB
                                  phy flag: \t{:?}
-                                 no_tbs %: \t{:?}",
+                                 no_tbs %: \t{:?}
+                                 re_tx %:  \t{:?}",
         metric_wrapper
             .result
             .transport_fair_share_capacity_bit_per_ms,
@@ -417,6 +502,7 @@ fn calculate_capacity(
         metric_wrapper.result.physical_rate_bit_per_prb,
         metric_wrapper.result.physical_rate_mode,
         metric_wrapper.result.no_tbs_prb_ratio,
+        metric_wrapper.result.dl_re_tx_ratio,
     ));
     Ok(metric_wrapper)
 }
@@ -426,11 +512,15 @@ fn calculate_capacity(
  * (capacity, bit/PRB ratio, re-transmissions)
  *
  * According to PBE-CC: https://dl.acm.org/doi/abs/10.1145/3387514.3405880
+ *
+ * The UE DCIs are optional (decode_single_ue) and only used to determine
+ * the re-transmission share of the target RNTI.
  * */
 fn calculate_pbe_cc_capacity(
     target_rnti: u16,
     cell_capacity_prb_per_slot: u16,
     dci_list: &[NgScopeCellDci],
+    ue_dci_list: &[NgScopeUeDci],
     rnti_share_type: &u8,
 ) -> Result<LogMetric> {
     let nof_dci: u64 = dci_list.len() as u64;
@@ -499,6 +589,49 @@ fn calculate_pbe_cc_capacity(
         .map(|target_rnti_dci| target_rnti_dci.dl_no_tbs_prb as u64)
         .sum::<u64>();
 
+    // The UE DCIs of our RNTI within the timeframe of the given DCIs
+    let oldest_dci_timestamp_us: u64 = dci_list.iter().map(|dci| dci.time_stamp).min().unwrap();
+    let latest_dci_timestamp_us: u64 = dci_list.iter().map(|dci| dci.time_stamp).max().unwrap();
+    let target_ue_dci_list: Vec<&NgScopeUeDci> = ue_dci_list
+        .iter()
+        .filter(|ue_dci| ue_dci.rnti == target_rnti)
+        .filter(|ue_dci| {
+            (oldest_dci_timestamp_us..=latest_dci_timestamp_us).contains(&ue_dci.time_stamp)
+        })
+        .collect::<Vec<&NgScopeUeDci>>();
+    let target_ue_dci_dl_list: Vec<&&NgScopeUeDci> = target_ue_dci_list
+        .iter()
+        .filter(|ue_dci| ue_dci.dl_tbs > 0)
+        .collect::<Vec<&&NgScopeUeDci>>();
+
+    let nof_ue_dci: u64 = target_ue_dci_list.len() as u64;
+    let nof_ue_dci_dl_re_tx: u64 = target_ue_dci_dl_list
+        .iter()
+        .filter(|ue_dci| ue_dci.is_dl_re_tx())
+        .count() as u64;
+    let nof_ue_dci_ul_re_tx: u64 = target_ue_dci_list
+        .iter()
+        .filter(|ue_dci| ue_dci.ul_tbs > 0 && ue_dci.is_ul_re_tx())
+        .count() as u64;
+
+    // The DL traffic of our RNTI in bit according to the UE DCIs, and the re-transmitted part
+    let tbs_ue_dci_bit: u64 = target_ue_dci_dl_list
+        .iter()
+        .map(|ue_dci| ue_dci.dl_tbs as u64)
+        .sum::<u64>();
+    let tbs_ue_dci_dl_re_tx_bit: u64 = target_ue_dci_dl_list
+        .iter()
+        .filter(|ue_dci| ue_dci.is_dl_re_tx())
+        .map(|ue_dci| ue_dci.dl_tbs as u64)
+        .sum::<u64>();
+
+    // Share of our traffic that occupies PRBs without carrying new data
+    let dl_re_tx_ratio: f64 = if tbs_ue_dci_bit > 0 {
+        tbs_ue_dci_dl_re_tx_bit as f64 / tbs_ue_dci_bit as f64
+    } else {
+        0.0
+    };
+
     // Total number of allocated PRBs in the given DCIs
     let p_alloc_total = p_alloc + p_alloc_no_tbs;
 
@@ -578,7 +711,7 @@ fn calculate_pbe_cc_capacity(
      * */
     let p_alloc_rnti_suggested: u64 = p_alloc_rnti + ((p_idle + nof_rnti_shared - 1) / nof_rnti_shared);
     let c_p: u64 = (((r_w * p_alloc_rnti_suggested) as f64) / (nof_dci as f64)) as u64;
-    let c_t = translate_physcial_to_transport_simple(c_p);
+    let c_t = translate_physcial_to_transport_simple((c_p as f64 * (1.0 - dl_re_tx_ratio)) as u64);
 
     let mut no_tbs_prb_ratio = 0.0;
     if p_alloc_no_tbs > 0 {
@@ -625,6 +758,7 @@ fn calculate_pbe_cc_capacity(
             physical_rate_bit_per_prb: r_w,
             physical_rate_mode: r_w_mode,
             no_tbs_prb_ratio,
+            dl_re_tx_ratio,
         },
         basis: MetricBasis {
             nof_dci,
@@ -641,7 +775,13 @@ fn calculate_pbe_cc_capacity(
             p_alloc_rnti,
             p_alloc_rnti_suggested,
             p_alloc_no_tbs_rnti,
+            nof_ue_dci,
+            nof_ue_dci_dl_re_tx,
+            nof_ue_dci_ul_re_tx,
+            tbs_ue_dci_bit,
+            tbs_ue_dci_dl_re_tx_bit,
         },
+        dci_quality: dci_quality_live(),
     })
 }
 
@@ -719,6 +859,7 @@ mod tests {
             dummy_rnti,
             dummy_cell_capacity_prb,
             &dummy_dci_slice(),
+            &[],
             &false,
             &RNTI_SHARE_TYPE_ALL,
         )?;
@@ -729,6 +870,56 @@ mod tests {
         assert_eq!(metric_params.result.physical_rate_bit_per_prb, 512);
         assert_eq!(metric_params.result.physical_rate_mode, 2);
         assert_eq!(metric_params.result.no_tbs_prb_ratio, 0.0);
+        assert_eq!(metric_params.result.dl_re_tx_ratio, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_capacity_re_tx() -> Result<()> {
+        let dummy_rnti: u16 = 123;
+        let dummy_cell_capacity_prb: u16 = 100;
+        let new_tx = NgScopeUeDci {
+            rnti: dummy_rnti,
+            dl_tbs: 3072,
+            ..Default::default()
+        };
+        let re_tx = NgScopeUeDci {
+            dl_tbs: 1024,
+            dl_re_tx: 1,
+            ..new_tx
+        };
+        let rv_only = NgScopeUeDci {
+            dl_tbs: 1024,
+            dl_rv_flag: true,
+            ..new_tx
+        };
+        let other_rnti = NgScopeUeDci { rnti: 124, ..re_tx };
+        let out_of_timeframe = NgScopeUeDci {
+            time_stamp: 1,
+            ..re_tx
+        };
+        let metric_params = calculate_capacity(
+            dummy_rnti,
+            dummy_cell_capacity_prb,
+            &dummy_dci_slice(),
+            &[new_tx, re_tx, rv_only, other_rnti, out_of_timeframe],
+            &false,
+            &RNTI_SHARE_TYPE_ALL,
+        )?;
+        assert_eq!(metric_params.basis.nof_ue_dci, 3);
+        assert_eq!(metric_params.basis.nof_ue_dci_dl_re_tx, 2);
+        assert_eq!(metric_params.basis.tbs_ue_dci_dl_re_tx_bit, 2048);
+        assert_eq!(metric_params.result.dl_re_tx_ratio, 0.4);
+        assert_eq!(
+            metric_params.result.physical_fair_share_capacity_bit_per_ms,
+            33280
+        );
+        assert_eq!(
+            metric_params
+                .result
+                .transport_fair_share_capacity_bit_per_ms,
+            translate_physcial_to_transport_simple((33280.0 * 0.6) as u64)
+        );
         Ok(())
     }
 }
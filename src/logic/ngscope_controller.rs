@@ -1,22 +1,32 @@
 use anyhow::{anyhow, Result};
 use bus::{Bus, BusReader};
+use std::collections::VecDeque;
 use std::fs::{self, File};
 use std::net::UdpSocket;
 use std::path::Path;
-use std::process::{Child, Stdio};
+use std::process::{Child, ExitStatus, Stdio};
 use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::cell_info::CellInfo;
-use crate::logger::log_dci;
+use crate::cell_info::{CellInfo, SingleCell};
+use crate::logger::{log_dci, log_dci_quality, log_ue_dci};
+use crate::logic::dci_jitter_buffer::DciJitterBuffer;
+use crate::logic::dci_quality::{DciQualityReport, DciQualityTracker};
 use crate::logic::{
     check_not_stopped, wait_until_running, MainState, MessageCellInfo, MessageDci, NgControlState,
-    CHANNEL_SYNC_SIZE, DEFAULT_WORKER_SLEEP_MS, DEFAULT_WORKER_SLEEP_US,
+    NgScopeCarrier, CHANNEL_SYNC_SIZE, DEFAULT_WORKER_SLEEP_MS, DEFAULT_WORKER_SLEEP_US,
 };
 use crate::ngscope;
-use crate::ngscope::config::NgScopeConfig;
-use crate::ngscope::types::{Message, NgScopeCellDci};
+use crate::ngscope::config::{
+    read_config, rf_args_with_serial, NgScopeConfig, NgScopeConfigRfDev, NGSCOPE_MAX_NOF_RF_DEV,
+};
+use crate::ngscope::events::{deploy_ngscope_output_reader, NgScopeEvent};
+use crate::ngscope::types::{
+    Message, NgScopeCellConfig, NgScopeCellDci, NgScopeUeDci, NGSCOPE_MAX_NOF_CELL,
+};
 use crate::ngscope::{
     ngscope_validate_server_check, ngscope_validate_server_send_initial, start_ngscope,
     stop_ngscope,
@@ -25,14 +35,34 @@ use crate::parse::{Arguments, FlattenedNgScopeArgs};
 use crate::util::{determine_process_id, is_debug, print_debug, print_info};
 
 const WAIT_FOR_TRIGGER_NGSCOPE_RESPONE_MS: u64 = 500;
+const DCI_QUALITY_REPORT_INTERVAL_US: u64 = 10_000_000;
+const DCI_QUALITY_LONG_GAP_TTI: u64 = 1000;
+const NGSCOPE_RESTART_BACKOFF_INITIAL_MS: u64 = 1000;
+const NGSCOPE_RESTART_BACKOFF_MAX_MS: u64 = 60_000;
+/* An ng-scope process that ran this long before exiting resets the backoff */
+const NGSCOPE_STABLE_RUNTIME_MS: u64 = 60_000;
+const NGSCOPE_SYNC_LOSS_WINDOW_MS: u64 = 60_000;
+const NGSCOPE_EVENT_CHANNEL_SIZE: usize = 100;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 enum LocalDciState {
     Stop,
     SendInitial,
     WaitForServerAuth(u8),
     SuccessfulAuth,
     ListenForDci,
+    /// Cell IDs of the carriers in ng-scope cell index order, does not change the state
+    UpdateCarriers(Vec<u64>),
+    /// Primary cell PRB from the ng-scope output, used until the first Config arrives
+    PrbHint(u16),
+    /// Tell the workers that no DCI arrived for the silence timeout
+    NotifyDciStale,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DciSilenceAction {
+    ReHandshake,
+    RestartNgScope,
 }
 
 pub struct NgControlArgs {
@@ -50,13 +80,233 @@ struct RunArgs {
     rx_cell_info: BusReader<MessageCellInfo>,
     tx_dci_thread_handle: Option<SyncSender<LocalDciState>>,
     dci_thread_handle: Option<JoinHandle<()>>,
-    ng_process_handle: Option<Child>,
+    ng_supervisor: NgScopeSupervisor,
 }
 
 struct RunArgsMovables {
     tx_dci: Bus<MessageDci>,
 }
 
+/*
+ * Keeps track of the ng-scope process started by the controller.
+ *
+ * If the process exits without being stopped by the controller, the exit
+ * status is recorded and a restart with the last config is scheduled. The
+ * delay doubles with every crash and is reset once a process ran stable.
+ * */
+struct NgScopeSupervisor {
+    process: Option<Child>,
+    config: Option<NgScopeConfig>,
+    started_at: Instant,
+    restart_at: Option<Instant>,
+    backoff_ms: u64,
+    exit_statuses: Vec<ExitStatus>,
+    sync_losses: VecDeque<Instant>,
+}
+
+impl NgScopeSupervisor {
+    fn new() -> NgScopeSupervisor {
+        NgScopeSupervisor {
+            process: None,
+            config: None,
+            started_at: Instant::now(),
+            restart_at: None,
+            backoff_ms: NGSCOPE_RESTART_BACKOFF_INITIAL_MS,
+            exit_statuses: vec![],
+            sync_losses: VecDeque::new(),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.process.is_some()
+    }
+
+    /// Supervise a newly started process (None if ng-scope is not started by us)
+    fn started(&mut self, process: Option<Child>, config: NgScopeConfig) {
+        self.process = process;
+        self.config = Some(config);
+        self.started_at = Instant::now();
+        self.restart_at = None;
+        self.sync_losses.clear();
+    }
+
+    /// Returns true if the sync losses within the window reached `restart_count`
+    fn record_sync_loss(&mut self, restart_count: u32) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_millis(NGSCOPE_SYNC_LOSS_WINDOW_MS);
+        self.sync_losses.push_back(now);
+        while let Some(&oldest) = self.sync_losses.front() {
+            if now.duration_since(oldest) <= window {
+                break;
+            }
+            self.sync_losses.pop_front();
+        }
+        if restart_count == 0 || self.sync_losses.len() < restart_count as usize {
+            return false;
+        }
+        self.sync_losses.clear();
+        self.is_running()
+    }
+
+    /// Intentionally stop the process, no restart is scheduled
+    fn stop(&mut self) -> Result<()> {
+        self.restart_at = None;
+        self.config = None;
+        if let Some(mut process) = self.process.take() {
+            let exit_status = stop_ngscope(&mut process)?;
+            print_info(&format!("[ngcontrol] ng-scope stopped: {}", exit_status));
+        }
+        Ok(())
+    }
+
+    /// Poll the process, returns the exit status and schedules a restart if it exited
+    fn check_process(&mut self) -> Result<Option<ExitStatus>> {
+        let exit_status = match self.process.as_mut() {
+            Some(process) => match process.try_wait()? {
+                Some(exit_status) => exit_status,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        self.process = None;
+        self.exit_statuses.push(exit_status);
+
+        if self.started_at.elapsed() >= Duration::from_millis(NGSCOPE_STABLE_RUNTIME_MS) {
+            self.backoff_ms = NGSCOPE_RESTART_BACKOFF_INITIAL_MS;
+        }
+        if self.config.is_some() {
+            self.restart_at = Some(Instant::now() + Duration::from_millis(self.backoff_ms));
+        }
+        print_info(&format!(
+            "ERROR [ngcontrol] ng-scope exited unexpectedly ({}) after {:?}, restarting in {} ms (exit #{})",
+            exit_status,
+            self.started_at.elapsed(),
+            self.backoff_ms,
+            self.exit_statuses.len()
+        ));
+        self.backoff_ms = (2 * self.backoff_ms).min(NGSCOPE_RESTART_BACKOFF_MAX_MS);
+        Ok(Some(exit_status))
+    }
+
+    /// The config to restart with, once the backoff has passed
+    fn take_due_restart(&mut self) -> Option<NgScopeConfig> {
+        match self.restart_at {
+            Some(restart_at) if Instant::now() >= restart_at => {
+                self.restart_at = None;
+                self.config.clone()
+            }
+            _ => None,
+        }
+    }
+}
+
+/*
+ * Detects when ng-scope stops sending DCIs after a successful handshake.
+ *
+ * The fetcher stores the timestamp of every received DCI. The first silence is
+ * answered with a new handshake, if ng-scope stays silent the process is
+ * restarted. Any DCI in between starts the escalation over.
+ * */
+struct DciWatchdog {
+    timeout_us: u64,
+    last_dci_us: Arc<AtomicU64>,
+    armed_at_us: Option<u64>,
+    nof_recoveries: u32,
+}
+
+impl DciWatchdog {
+    fn new(timeout_ms: u64, last_dci_us: Arc<AtomicU64>) -> DciWatchdog {
+        DciWatchdog {
+            timeout_us: timeout_ms * 1000,
+            last_dci_us,
+            armed_at_us: None,
+            nof_recoveries: 0,
+        }
+    }
+
+    /// Start expecting DCIs, does nothing if the watchdog is disabled (timeout 0)
+    fn arm(&mut self, now_us: u64) {
+        if self.timeout_us > 0 {
+            self.armed_at_us = Some(now_us);
+        }
+    }
+
+    /// Stop expecting DCIs, e.g. while ng-scope is (re)started
+    fn reset(&mut self) {
+        self.armed_at_us = None;
+        self.nof_recoveries = 0;
+    }
+
+    fn is_recovering(&self) -> bool {
+        self.nof_recoveries > 0
+    }
+
+    fn last_dci_us(&self) -> u64 {
+        self.last_dci_us.load(Ordering::Relaxed)
+    }
+
+    /// Returns the recovery action once no DCI arrived for the timeout, disarms itself
+    fn check(&mut self, now_us: u64) -> Option<DciSilenceAction> {
+        let armed_at_us = self.armed_at_us?;
+        let last_dci_us = self.last_dci_us();
+        if last_dci_us > armed_at_us {
+            self.nof_recoveries = 0;
+        }
+        if now_us.saturating_sub(last_dci_us.max(armed_at_us)) < self.timeout_us {
+            return None;
+        }
+        self.armed_at_us = None;
+        self.nof_recoveries += 1;
+        match self.nof_recoveries {
+            1 => Some(DciSilenceAction::ReHandshake),
+            _ => Some(DciSilenceAction::RestartNgScope),
+        }
+    }
+}
+
+/*
+ * Compares the PCI ng-scope locks to with the PCIs the cell API reports for
+ * the decoded carriers. A mismatch usually means that a neighbour cell on the
+ * same EARFCN is decoded. Every mismatching PCI is reported once in a row.
+ * */
+#[derive(Default)]
+struct PciCheck {
+    expected_pcis: Vec<u16>,
+    last_mismatch_pci: Option<u16>,
+}
+
+impl PciCheck {
+    fn set_expected(&mut self, expected_pcis: Vec<u16>) {
+        self.expected_pcis = expected_pcis;
+        self.last_mismatch_pci = None;
+    }
+
+    /// Returns true if the detected PCI is a new mismatch
+    fn is_new_mismatch(&mut self, detected_pci: u16) -> bool {
+        if self.expected_pcis.is_empty() || self.expected_pcis.contains(&detected_pci) {
+            self.last_mismatch_pci = None;
+            return false;
+        }
+        if self.last_mismatch_pci == Some(detected_pci) {
+            return false;
+        }
+        self.last_mismatch_pci = Some(detected_pci);
+        true
+    }
+}
+
+struct DciFetcherArgs {
+    tx_main_thread: SyncSender<LocalDciState>,
+    rx_main_thread: Receiver<LocalDciState>,
+    tx_dci: Bus<MessageDci>,
+    local_socket_addr: String,
+    ng_server_addr: String,
+    is_log_dci: bool,
+    log_dci_batch_size: u64,
+    is_log_dci_quality: bool,
+    last_dci_us: Arc<AtomicU64>,
+}
+
 pub fn deploy_ngscope_controller(args: NgControlArgs) -> Result<JoinHandle<()>> {
     let mut run_args: RunArgs = RunArgs {
         rx_app_state: args.rx_app_state,
@@ -65,7 +315,7 @@ pub fn deploy_ngscope_controller(args: NgControlArgs) -> Result<JoinHandle<()>>
         rx_cell_info: args.rx_cell_info,
         tx_dci_thread_handle: None,
         dci_thread_handle: None,
-        ng_process_handle: None,
+        ng_supervisor: NgScopeSupervisor::new(),
     };
     let run_args_mov: RunArgsMovables = RunArgsMovables {
         tx_dci: args.tx_dci,
@@ -83,6 +333,7 @@ fn run(run_args: &mut RunArgs, run_args_mov: RunArgsMovables) -> Result<()> {
     let tx_ngcontrol_state = &mut run_args.tx_ngcontrol_state;
     let app_args = &run_args.app_args;
     let rx_cell_info = &mut run_args.rx_cell_info;
+    let ng_supervisor = &mut run_args.ng_supervisor;
     let tx_dci = run_args_mov.tx_dci;
 
     tx_ngcontrol_state.send(NgControlState::Running)?;
@@ -93,25 +344,29 @@ fn run(run_args: &mut RunArgs, run_args_mov: RunArgsMovables) -> Result<()> {
     ));
 
     let ng_args = FlattenedNgScopeArgs::from_unflattened(app_args.clone().ngscope.unwrap())?;
-    let mut ng_process_option: Option<Child> = None;
-    let ngscope_config = NgScopeConfig {
-        ..Default::default()
-    };
+    let ngscope_config = determine_base_config(&ng_args)?;
 
+    let (tx_ng_event, rx_ng_event) = sync_channel::<NgScopeEvent>(NGSCOPE_EVENT_CHANNEL_SIZE);
     let (tx_dci_thread, rx_main_thread) = sync_channel::<LocalDciState>(CHANNEL_SYNC_SIZE);
     let (tx_main_thread, rx_dci_thread) = sync_channel::<LocalDciState>(CHANNEL_SYNC_SIZE);
-    run_args.dci_thread_handle = Some(deploy_dci_fetcher_thread(
+    let last_dci_us = Arc::new(AtomicU64::new(0));
+    let mut dci_watchdog =
+        DciWatchdog::new(ng_args.ng_dci_silence_timeout_ms, last_dci_us.clone());
+    run_args.dci_thread_handle = Some(deploy_dci_fetcher_thread(DciFetcherArgs {
         tx_main_thread,
         rx_main_thread,
         tx_dci,
-        ng_args.ng_local_addr.to_string(),
-        ng_args.ng_server_addr.to_string(),
-        ng_args.ng_log_dci,
-        ng_args.ng_log_dci_batch_size,
-    )?);
+        local_socket_addr: ng_args.ng_local_addr.to_string(),
+        ng_server_addr: ng_args.ng_server_addr.to_string(),
+        is_log_dci: ng_args.ng_log_dci,
+        log_dci_batch_size: ng_args.ng_log_dci_batch_size,
+        is_log_dci_quality: ng_args.ng_log_dci_quality,
+        last_dci_us,
+    })?);
     run_args.tx_dci_thread_handle = Some(tx_dci_thread.clone());
 
     let mut ngcontrol_state: NgControlState = NgControlState::CheckingCellInfo;
+    let mut pci_check = PciCheck::default();
 
     loop {
         /* <precheck> */
@@ -119,13 +374,47 @@ fn run(run_args: &mut RunArgs, run_args_mov: RunArgsMovables) -> Result<()> {
         if check_not_stopped(rx_app_state).is_err() {
             break;
         }
+        if ng_supervisor.check_process()?.is_some() {
+            tx_ngcontrol_state.send(NgControlState::RestartingNgScopeProcess)?;
+        }
+        if let Some(config) = ng_supervisor.take_due_restart() {
+            ngcontrol_state = NgControlState::StartNgScope(Box::new(config));
+        }
+        if let Some(config) = handle_ngscope_events(
+            &rx_ng_event,
+            ng_supervisor,
+            &ng_args,
+            &tx_dci_thread,
+            &mut pci_check,
+        )? {
+            tx_ngcontrol_state.send(NgControlState::RestartingNgScopeProcess)?;
+            ngcontrol_state = NgControlState::StartNgScope(Box::new(config));
+        }
+        if let Some(state) = handle_dci_silence(
+            &mut dci_watchdog,
+            ng_supervisor,
+            &tx_dci_thread,
+            tx_ngcontrol_state,
+        )? {
+            ngcontrol_state = state;
+        }
         /* </precheck> */
 
         match ngcontrol_state {
             NgControlState::CheckingCellInfo => {
-                ngcontrol_state = handle_cell_update(rx_cell_info, &ngscope_config)?;
+                ngcontrol_state = handle_cell_update(
+                    rx_cell_info,
+                    &ngscope_config,
+                    &ng_args,
+                    &tx_dci_thread,
+                    &mut pci_check,
+                )?;
             }
             NgControlState::TriggerListenDci => {
+                /* a re-handshake that stays unanswered escalates to a restart */
+                if dci_watchdog.is_recovering() {
+                    dci_watchdog.arm(chrono::Local::now().timestamp_micros() as u64);
+                }
                 tx_dci_thread.send(LocalDciState::SendInitial)?;
                 ngcontrol_state = NgControlState::WaitForTriggerResponse;
             }
@@ -144,6 +433,7 @@ fn run(run_args: &mut RunArgs, run_args_mov: RunArgsMovables) -> Result<()> {
             }
             NgControlState::SuccessfulTriggerResponse => {
                 tx_ngcontrol_state.send(NgControlState::SuccessfulTriggerResponse)?;
+                dci_watchdog.arm(chrono::Local::now().timestamp_micros() as u64);
                 ngcontrol_state = NgControlState::CheckingCellInfo
             }
             NgControlState::SleepMs(time_ms, next_state) => {
@@ -151,13 +441,12 @@ fn run(run_args: &mut RunArgs, run_args_mov: RunArgsMovables) -> Result<()> {
                 ngcontrol_state = *next_state;
             }
             NgControlState::StartNgScope(config) => {
-                if let Some(ref mut process) = ng_process_option {
-                    stop_ngscope(process)?;
-                }
-                match handle_start_ngscope(&config, &ng_args) {
+                dci_watchdog.reset();
+                ng_supervisor.stop()?;
+                match handle_start_ngscope(&config, &ng_args, &tx_ng_event) {
                     Ok((state, proc)) => {
                         ngcontrol_state = state;
-                        ng_process_option = proc;
+                        ng_supervisor.started(proc, *config);
                     }
                     Err(err) => {
                         print_info(&format!(
@@ -173,9 +462,8 @@ fn run(run_args: &mut RunArgs, run_args_mov: RunArgsMovables) -> Result<()> {
                 }
             }
             NgControlState::StopNgScope => {
-                if let Some(ref mut process) = ng_process_option {
-                    stop_ngscope(process)?;
-                }
+                dci_watchdog.reset();
+                ng_supervisor.stop()?;
                 ngcontrol_state = NgControlState::CheckingCellInfo;
             }
             _ => todo!(),
@@ -197,11 +485,18 @@ fn finish(mut run_args: RunArgs) {
     if let Some(dci_thread) = run_args.dci_thread_handle {
         let _ = dci_thread.join();
     }
-    if let Some(ref mut process) = run_args.ng_process_handle {
+    if run_args.ng_supervisor.is_running() {
         let _ = run_args
             .tx_ngcontrol_state
             .send(NgControlState::StoppingNgScopeProcess);
-        let _ = stop_ngscope(process);
+    }
+    let _ = run_args.ng_supervisor.stop();
+    if !run_args.ng_supervisor.exit_statuses.is_empty() {
+        print_info(&format!(
+            "[ngcontrol] ng-scope exited unexpectedly {} times: {:?}",
+            run_args.ng_supervisor.exit_statuses.len(),
+            run_args.ng_supervisor.exit_statuses
+        ));
     }
     let _ = send_final_state(&run_args.tx_ngcontrol_state);
 }
@@ -209,20 +504,39 @@ fn finish(mut run_args: RunArgs) {
 fn handle_start_ngscope(
     ng_conf: &NgScopeConfig,
     ng_args: &FlattenedNgScopeArgs,
+    tx_ng_event: &SyncSender<NgScopeEvent>,
 ) -> Result<(NgControlState, Option<Child>)> {
-    let (std_out, std_err) = match &ng_args.ng_log_file {
-        Some(path) => {
-            if Path::new(path).exists() {
-                fs::remove_file(path).unwrap();
+    let new_ng_process = match ng_args.ng_start_process {
+        true => {
+            let log_file = match &ng_args.ng_log_file {
+                Some(path) => {
+                    if Path::new(path).exists() {
+                        fs::remove_file(path).unwrap();
+                    }
+                    Some(Arc::new(Mutex::new(File::create(path)?)))
+                }
+                None => None,
+            };
+            let mut process =
+                start_ngscope(&ng_args.ng_path, ng_conf, Stdio::piped(), Stdio::piped())?;
+            if let Some(std_out) = process.stdout.take() {
+                deploy_ngscope_output_reader(
+                    std_out,
+                    log_file.clone(),
+                    tx_ng_event.clone(),
+                    ng_args.ng_log_events,
+                )?;
+            }
+            if let Some(std_err) = process.stderr.take() {
+                deploy_ngscope_output_reader(
+                    std_err,
+                    log_file,
+                    tx_ng_event.clone(),
+                    ng_args.ng_log_events,
+                )?;
             }
-            let file_out = File::create(path)?;
-            let file_err = file_out.try_clone()?;
-            (Stdio::from(file_out), Stdio::from(file_err))
+            Some(process)
         }
-        None => (Stdio::null(), Stdio::null()),
-    };
-    let new_ng_process = match ng_args.ng_start_process {
-        true => Some(start_ngscope(&ng_args.ng_path, ng_conf, std_out, std_err)?),
         false => None,
     };
     Ok((
@@ -231,58 +545,285 @@ fn handle_start_ngscope(
     ))
 }
 
+/*
+ * React to the events recognised in the ng-scope output.
+ * Returns the config to restart ng-scope with after repeated sync loss.
+ * */
+fn handle_ngscope_events(
+    rx_ng_event: &Receiver<NgScopeEvent>,
+    ng_supervisor: &mut NgScopeSupervisor,
+    ng_args: &FlattenedNgScopeArgs,
+    tx_dci_thread: &SyncSender<LocalDciState>,
+    pci_check: &mut PciCheck,
+) -> Result<Option<NgScopeConfig>> {
+    for event in rx_ng_event.try_iter() {
+        match event {
+            NgScopeEvent::CellFound { pci, nof_prb } => {
+                check_detected_pci(pci_check, pci);
+                let _ = tx_dci_thread.try_send(LocalDciState::PrbHint(nof_prb));
+            }
+            NgScopeEvent::PrbDetected { nof_prb } => {
+                let _ = tx_dci_thread.try_send(LocalDciState::PrbHint(nof_prb));
+            }
+            NgScopeEvent::SyncLost => {
+                if ng_supervisor.record_sync_loss(ng_args.ng_sync_loss_restart_count) {
+                    print_info(&format!(
+                        "ERROR [ngcontrol] ng-scope lost sync {} times within {} s, restarting",
+                        ng_args.ng_sync_loss_restart_count,
+                        NGSCOPE_SYNC_LOSS_WINDOW_MS / 1000
+                    ));
+                    return Ok(ng_supervisor.config.clone());
+                }
+            }
+            NgScopeEvent::UsrpError { message } => {
+                print_info(&format!(
+                    "ERROR [ngcontrol] ng-scope SDR error: {}",
+                    message
+                ));
+            }
+            NgScopeEvent::PciDetected { pci } => {
+                print_debug(&format!("DEBUG [ngcontrol] ng-scope detected PCI {}", pci));
+                check_detected_pci(pci_check, pci);
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn check_detected_pci(pci_check: &mut PciCheck, detected_pci: u16) {
+    if pci_check.is_new_mismatch(detected_pci) {
+        print_info(&format!(
+            "ERROR [ngcontrol] ng-scope locked to PCI {}, but the cell API reports PCI {:?} (neighbour cell on the same EARFCN?)",
+            detected_pci, pci_check.expected_pcis
+        ));
+    }
+}
+
+/*
+ * Recover from DCI silence: first a new handshake, then a process restart.
+ * Returns the next state if the watchdog fired.
+ * */
+fn handle_dci_silence(
+    dci_watchdog: &mut DciWatchdog,
+    ng_supervisor: &NgScopeSupervisor,
+    tx_dci_thread: &SyncSender<LocalDciState>,
+    tx_ngcontrol_state: &SyncSender<NgControlState>,
+) -> Result<Option<NgControlState>> {
+    let action = match dci_watchdog.check(chrono::Local::now().timestamp_micros() as u64) {
+        Some(action) => action,
+        None => return Ok(None),
+    };
+    tx_dci_thread.send(LocalDciState::NotifyDciStale)?;
+    match (action, ng_supervisor.config.clone()) {
+        (DciSilenceAction::RestartNgScope, Some(config)) => {
+            print_info("ERROR [ngcontrol] no DCI after re-handshake, restarting ng-scope");
+            tx_ngcontrol_state.send(NgControlState::RestartingNgScopeProcess)?;
+            Ok(Some(NgControlState::StartNgScope(Box::new(config))))
+        }
+        _ => {
+            print_info(&format!(
+                "ERROR [ngcontrol] no DCI for {} ms, re-sending the ng-scope handshake",
+                dci_watchdog.timeout_us / 1000
+            ));
+            Ok(Some(NgControlState::TriggerListenDci))
+        }
+    }
+}
+
+/*
+ * The config all cell specific configs are derived from: the template (or the
+ * defaults) with the ng-scope settings of the tracker config applied on top.
+ * */
+fn determine_base_config(ng_args: &FlattenedNgScopeArgs) -> Result<NgScopeConfig> {
+    let mut config = match &ng_args.ng_config_template {
+        Some(template_path) => {
+            print_info(&format!(
+                "[ngcontrol] using ng-scope config template {}",
+                template_path
+            ));
+            read_config(template_path)?
+        }
+        None => NgScopeConfig::default(),
+    };
+    apply_config_args(&mut config, ng_args);
+    Ok(config)
+}
+
+fn apply_config_args(config: &mut NgScopeConfig, ng_args: &FlattenedNgScopeArgs) {
+    let mut rf_template = config.rf_config0.clone().unwrap_or_default();
+    rf_template.N_id_2 = ng_args.ng_n_id_2.unwrap_or(rf_template.N_id_2);
+    rf_template.nof_thread = ng_args.ng_nof_thread.unwrap_or(rf_template.nof_thread);
+    rf_template.disable_plot = ng_args.ng_disable_plot.or(rf_template.disable_plot);
+    rf_template.log_dl = ng_args.ng_rf_log_dl.or(rf_template.log_dl);
+    rf_template.log_ul = ng_args.ng_rf_log_ul.or(rf_template.log_ul);
+    rf_template.log_phich = ng_args.ng_rf_log_phich.or(rf_template.log_phich);
+    config.rf_config0 = Some(rf_template);
+
+    config.rnti = ng_args.ng_rnti.unwrap_or(config.rnti);
+    config.remote_enable = ng_args.ng_remote_enable.or(config.remote_enable);
+    config.decode_single_ue = ng_args.ng_decode_single_ue.or(config.decode_single_ue);
+    config.decode_sib = ng_args.ng_decode_sib.or(config.decode_sib);
+    config.dci_logs_path = ng_args
+        .ng_dci_logs_path
+        .clone()
+        .or(config.dci_logs_path.take());
+    config.sib_logs_path = ng_args
+        .ng_sib_logs_path
+        .clone()
+        .or(config.sib_logs_path.take());
+
+    let mut dci_log_config = config.dci_log_config.clone().unwrap_or_default();
+    dci_log_config.log_ul = ng_args.ng_dci_log_ul.unwrap_or(dci_log_config.log_ul);
+    dci_log_config.log_dl = ng_args.ng_dci_log_dl.unwrap_or(dci_log_config.log_dl);
+    dci_log_config.log_interval = ng_args
+        .ng_dci_log_interval
+        .unwrap_or(dci_log_config.log_interval);
+    config.dci_log_config = Some(dci_log_config);
+}
+
 fn handle_cell_update(
     rx_cell_info: &mut BusReader<MessageCellInfo>,
     ng_conf: &NgScopeConfig,
+    ng_args: &FlattenedNgScopeArgs,
+    tx_dci_thread: &SyncSender<LocalDciState>,
+    pci_check: &mut PciCheck,
 ) -> Result<NgControlState> {
     match check_cell_update(rx_cell_info)? {
-        Some(cell_info) => {
+        Some(MessageCellInfo::CellUnknown) => {
+            print_info(
+                "[ngcontrol] cell unknown, stopping ng-scope until the cell source recovers",
+            );
+            Ok(NgControlState::StopNgScope)
+        }
+        Some(MessageCellInfo::CellChanged(_)) => Ok(NgControlState::CheckingCellInfo),
+        Some(MessageCellInfo::Neighbours(cell_info)) => {
+            /* the connected cells are unchanged, keep ng-scope running */
+            print_handover_target(&cell_info);
+            Ok(NgControlState::CheckingCellInfo)
+        }
+        Some(MessageCellInfo::Cells(cell_info)) => {
             print_info(&format!("[ngcontrol] cell_info: {:#?}", cell_info));
             if cell_info.cells.is_empty() {
                 return Ok(NgControlState::StopNgScope);
             }
-            // TODO: Handle multi cell
-            let mut new_conf = ng_conf.clone();
-            new_conf.rf_config0.as_mut().unwrap().rf_freq =
-                cell_info.cells.first().unwrap().frequency as i64;
+            print_handover_target(&cell_info);
+            let (new_conf, carrier_cell_ids) = determine_multi_cell_config(
+                ng_conf,
+                &cell_info.cells,
+                &ng_args.ng_rf_args,
+                &ng_args.ng_rf_serial,
+            );
+            if carrier_cell_ids.len() < cell_info.cells.len() {
+                print_info(&format!(
+                    "[ngcontrol] decoding {} of {} connected cells (configure ng_rf_args or ng_rf_serial per carrier)",
+                    carrier_cell_ids.len(),
+                    cell_info.cells.len()
+                ));
+            }
+            pci_check.set_expected(
+                cell_info
+                    .cells
+                    .iter()
+                    .take(carrier_cell_ids.len())
+                    .filter_map(|cell| cell.pci)
+                    .collect(),
+            );
+            tx_dci_thread.send(LocalDciState::UpdateCarriers(carrier_cell_ids))?;
             Ok(NgControlState::StartNgScope(Box::new(new_conf)))
         }
         _ => Ok(NgControlState::CheckingCellInfo),
     }
 }
 
-fn deploy_dci_fetcher_thread(
-    tx_main_thread: SyncSender<LocalDciState>,
-    rx_main_thread: Receiver<LocalDciState>,
-    tx_dci: Bus<MessageDci>,
-    local_socket_addr: String,
-    ng_server_addr: String,
-    is_log_dci: bool,
-    log_dci_batch_size: u64,
-) -> Result<JoinHandle<()>> {
+/* candidate for a second SDR, ng-scope only decodes connected cells for now */
+fn print_handover_target(cell_info: &CellInfo) {
+    if let Some(target) = cell_info.strongest_neighbour() {
+        print_info(&format!(
+            "[ngcontrol] likely handover target: {:?} {} Hz PCI {:?} ({} dBm, {} neighbours)",
+            target.cell_type,
+            target.frequency,
+            target.pci,
+            target.rsrp,
+            cell_info.neighbours.len()
+        ));
+    }
+}
+
+/*
+ * One RF device per connected cell, the first cell is the primary carrier.
+ *
+ * rf_config0 serves as template for all devices. Every additional device needs
+ * its own SDR, so without rf_args or a serial per carrier only the primary cell
+ * is decoded. A serial replaces the serial in the carrier's rf_args.
+ *
+ * If the cell API reports the PCI, N_id_2 = PCI % 3 spares ng-scope the blind
+ * search, unless the config sets an explicit N_id_2.
+ * */
+fn determine_multi_cell_config(
+    ng_conf: &NgScopeConfig,
+    cells: &[SingleCell],
+    rf_args: &[String],
+    rf_serials: &[String],
+) -> (NgScopeConfig, Vec<u64>) {
+    let template = ng_conf.rf_config0.clone().unwrap_or_default();
+    let nof_carrier = cells
+        .len()
+        .min(rf_args.len().max(rf_serials.len()).max(1))
+        .min(NGSCOPE_MAX_NOF_CELL)
+        .min(NGSCOPE_MAX_NOF_RF_DEV);
+    let rf_devices: Vec<NgScopeConfigRfDev> = cells
+        .iter()
+        .take(nof_carrier)
+        .enumerate()
+        .map(|(index, cell)| {
+            let device_args = rf_args
+                .get(index)
+                .cloned()
+                .unwrap_or_else(|| template.rf_args.clone());
+            NgScopeConfigRfDev {
+                rf_freq: cell.frequency as i64,
+                N_id_2: match cell.pci {
+                    Some(pci) if template.N_id_2 < 0 => (pci % 3) as i16,
+                    _ => template.N_id_2,
+                },
+                rf_args: match rf_serials.get(index) {
+                    Some(serial) => rf_args_with_serial(&device_args, serial),
+                    None => device_args,
+                },
+                ..template.clone()
+            }
+        })
+        .collect();
+
+    let mut new_conf = ng_conf.clone();
+    new_conf.set_rf_devices(rf_devices);
+    let carrier_cell_ids = cells
+        .iter()
+        .take(nof_carrier)
+        .map(|cell| cell.cell_id)
+        .collect();
+    (new_conf, carrier_cell_ids)
+}
+
+fn deploy_dci_fetcher_thread(fetcher_args: DciFetcherArgs) -> Result<JoinHandle<()>> {
     let thread = thread::spawn(move || {
-        let _ = run_dci_fetcher(
-            tx_main_thread,
-            rx_main_thread,
-            tx_dci,
-            local_socket_addr,
-            ng_server_addr,
-            is_log_dci,
-            log_dci_batch_size,
-        );
+        let _ = run_dci_fetcher(fetcher_args);
     });
     Ok(thread)
 }
 
-fn run_dci_fetcher(
-    tx_main_thread: SyncSender<LocalDciState>,
-    rx_main_thread: Receiver<LocalDciState>,
-    mut tx_dci: Bus<MessageDci>,
-    local_socket_addr: String,
-    ng_server_addr: String,
-    is_log_dci: bool,
-    log_dci_batch_size: u64,
-) -> Result<()> {
+fn run_dci_fetcher(fetcher_args: DciFetcherArgs) -> Result<()> {
+    let DciFetcherArgs {
+        tx_main_thread,
+        rx_main_thread,
+        mut tx_dci,
+        local_socket_addr,
+        ng_server_addr,
+        is_log_dci,
+        log_dci_batch_size,
+        is_log_dci_quality,
+        last_dci_us,
+    } = fetcher_args;
     let socket = init_dci_server(&local_socket_addr)?;
     let mut dci_state: LocalDciState = LocalDciState::ListenForDci;
     print_info(&format!(
@@ -292,17 +833,51 @@ fn run_dci_fetcher(
 
     let mut log_dci_buffer: Vec<NgScopeCellDci> =
         Vec::with_capacity(2 * log_dci_batch_size as usize);
+    let mut log_ue_dci_buffer: Vec<NgScopeUeDci> =
+        Vec::with_capacity(2 * log_dci_batch_size as usize);
     let sleep_duration = Duration::from_micros(DEFAULT_WORKER_SLEEP_US);
-    let mut last_dci_timestamp_us: u64 = 0;
+    let mut jitter_buffer = DciJitterBuffer::new();
+    let mut quality_tracker =
+        DciQualityTracker::new(chrono::Local::now().timestamp_micros() as u64);
+    let mut carrier_cell_ids: Vec<u64> = vec![];
+    let mut is_cell_config_received: bool = false;
+    let mut last_prb_hint: Option<u16> = None;
 
     loop {
         thread::sleep(sleep_duration);
         if let Some(new_state) = check_rx_state(&rx_main_thread)? {
-            dci_state = new_state;
+            match new_state {
+                LocalDciState::UpdateCarriers(cell_ids) => carrier_cell_ids = cell_ids,
+                LocalDciState::PrbHint(nof_prb) => {
+                    if !is_cell_config_received && last_prb_hint != Some(nof_prb) {
+                        broadcast_prb_hint(&mut tx_dci, nof_prb, &carrier_cell_ids);
+                        last_prb_hint = Some(nof_prb);
+                    }
+                }
+                LocalDciState::SendInitial => {
+                    /* a new handshake, ng-scope might have been restarted */
+                    is_cell_config_received = false;
+                    last_prb_hint = None;
+                    dci_state = new_state;
+                }
+                LocalDciState::NotifyDciStale => {
+                    let last_dci_timestamp_us = last_dci_us.load(Ordering::Relaxed);
+                    broadcast_message_dci(&mut tx_dci, MessageDci::DciStale(last_dci_timestamp_us));
+                }
+                _ => dci_state = new_state,
+            }
         }
 
         match dci_state {
             LocalDciState::Stop => {
+                let run_report = quality_tracker.run_report(
+                    chrono::Local::now().timestamp_micros() as u64,
+                    jitter_buffer.stats(),
+                );
+                print_dci_quality_report(&run_report);
+                if is_log_dci_quality {
+                    let _ = log_dci_quality(run_report);
+                }
                 break;
             }
             LocalDciState::SendInitial => {
@@ -319,8 +894,12 @@ fn run_dci_fetcher(
                 dci_state = match ngscope_validate_server_check(&socket)? {
                     Some(msg) => {
                         if let Message::Config(ngscope_cell_config) = msg {
-                            print_info(&format!("[ngcontrol] {:?}", ngscope_cell_config));
-                            broadcast_message_dci(&mut tx_dci, MessageDci::CellConfig(Box::new(ngscope_cell_config)));
+                            is_cell_config_received = true;
+                            broadcast_cell_config(
+                                &mut tx_dci,
+                                ngscope_cell_config,
+                                &carrier_cell_ids,
+                            );
                         }
                         if successful_auths >= 1 {
                             LocalDciState::SuccessfulAuth
@@ -331,15 +910,35 @@ fn run_dci_fetcher(
                     None => LocalDciState::WaitForServerAuth(successful_auths),
                 };
             }
+            LocalDciState::UpdateCarriers(_)
+            | LocalDciState::PrbHint(_)
+            | LocalDciState::NotifyDciStale => {}
             LocalDciState::ListenForDci => {
-                check_ngscope_message(
+                let is_dci_received = check_ngscope_message(
                     &socket,
                     &mut tx_dci,
-                    &mut last_dci_timestamp_us,
+                    &mut jitter_buffer,
+                    &is_log_dci,
+                    &mut log_ue_dci_buffer,
+                    &carrier_cell_ids,
+                    &mut is_cell_config_received,
+                );
+                if is_dci_received {
+                    last_dci_us.store(
+                        chrono::Local::now().timestamp_micros() as u64,
+                        Ordering::Relaxed,
+                    );
+                }
+                release_ordered_dci(
+                    &mut tx_dci,
+                    &mut jitter_buffer,
+                    &mut quality_tracker,
                     &is_log_dci,
                     &mut log_dci_buffer,
                 );
                 check_log_dci(&is_log_dci, &mut log_dci_buffer, &log_dci_batch_size);
+                check_log_ue_dci(&is_log_dci, &mut log_ue_dci_buffer, &log_dci_batch_size);
+                check_dci_quality_report(&mut quality_tracker, &jitter_buffer, &is_log_dci_quality);
             }
         }
     }
@@ -359,64 +958,159 @@ fn broadcast_message_dci(
     }
 }
 
+/// Returns true if a (cell or UE) DCI was received
 fn check_ngscope_message(
     socket: &UdpSocket,
     tx_dci: &mut Bus<MessageDci>,
-    last_dci_timestamp_us: &mut u64,
+    jitter_buffer: &mut DciJitterBuffer,
     is_log_dci: &bool,
-    log_dci_buffer: &mut Vec<NgScopeCellDci>,
-) {
+    log_ue_dci_buffer: &mut Vec<NgScopeUeDci>,
+    carrier_cell_ids: &[u64],
+    is_cell_config_received: &mut bool,
+) -> bool {
     match ngscope::ngscope_recv_single_message(socket) {
         Ok(msg) => {
             match msg {
                 Message::CellDci(cell_dci) => {
-                    if is_debug() {
-                        if *last_dci_timestamp_us != 0
-                            && cell_dci.time_stamp > *last_dci_timestamp_us
-                        {
-                            let timestamp_delta: i64 =
-                                cell_dci.time_stamp as i64 - *last_dci_timestamp_us as i64;
-                            if timestamp_delta > 1000000 {
-                                let now_delta = chrono::Local::now().timestamp_micros() as u64
-                                    - cell_dci.time_stamp;
-                                print_debug(&format!(
-                                    "DEBUG [ngcontrol.fetcher] 1s DCI gap:\n\
-                                                      \tdiff to previous DCI: {:>10} us\n\
-                                                      \tdiff to now:          {:>10} us",
-                                    timestamp_delta, now_delta
-                                ));
-                            }
-                        }
-                        *last_dci_timestamp_us = cell_dci.time_stamp;
-                    }
-                    /* check bus size */
-                    if *is_log_dci {
-                        log_dci_buffer.push(*cell_dci.clone());
-                    }
-                    let message_dci = MessageDci::CellDci(Box::new(*cell_dci));
-                    broadcast_message_dci(tx_dci, message_dci)
+                    jitter_buffer.push(*cell_dci, chrono::Local::now().timestamp_micros());
+                    true
                 }
                 Message::Dci(ue_dci) => {
-                    // TODO: Evaluate how to handle this
-                    print_info(&format!("[ngcontrol] {:?}", ue_dci));
+                    if *is_log_dci {
+                        log_ue_dci_buffer.push(ue_dci);
+                    }
+                    broadcast_message_dci(tx_dci, MessageDci::UeDci(Box::new(ue_dci)));
+                    true
                 }
                 Message::Config(cell_config) => {
-                    // TODO: Evaluate how to handle this
-                    print_info(&format!("[ngcontrol] {:?}", cell_config));
-                    let message_dci = MessageDci::CellConfig(Box::new(cell_config));
-                    broadcast_message_dci(tx_dci, message_dci)
+                    *is_cell_config_received = true;
+                    broadcast_cell_config(tx_dci, cell_config, carrier_cell_ids);
+                    false
                 }
                 // TODO: Evaluate how to handle Start andExit
-                Message::Start => {}
-                Message::Exit => {}
+                Message::Start => false,
+                Message::Exit => false,
             }
         }
         _ => {
             // TODO: print error properly? it also goes here when there just hasn't been a message
+            false
         }
     }
 }
 
+/*
+ * Publish the CellConfig and the carriers it describes. ng-scope cell index n
+ * belongs to rf_config<n>, which was configured with carrier_cell_ids[n].
+ * */
+fn broadcast_cell_config(
+    tx_dci: &mut Bus<MessageDci>,
+    cell_config: NgScopeCellConfig,
+    carrier_cell_ids: &[u64],
+) {
+    print_info(&format!("[ngcontrol] {:?}", cell_config));
+    let carriers: Vec<NgScopeCarrier> = cell_config
+        .cell_prb
+        .iter()
+        .take(cell_config.nof_cell as usize)
+        .enumerate()
+        .map(|(cell_idx, &nof_prb)| NgScopeCarrier {
+            cell_idx: cell_idx as u8,
+            cell_id: carrier_cell_ids.get(cell_idx).copied(),
+            nof_prb,
+        })
+        .collect();
+    print_info(&format!("[ngcontrol] carriers: {:?}", carriers));
+    broadcast_message_dci(tx_dci, MessageDci::CellConfig(Box::new(cell_config)));
+    broadcast_message_dci(tx_dci, MessageDci::CellCarriers(carriers));
+}
+
+/*
+ * Provisional single cell config from the PRB ng-scope printed, so that the
+ * workers know the bandwidth before the first Config message arrives.
+ * */
+fn broadcast_prb_hint(tx_dci: &mut Bus<MessageDci>, nof_prb: u16, carrier_cell_ids: &[u64]) {
+    let mut cell_prb = [0u16; NGSCOPE_MAX_NOF_CELL];
+    cell_prb[0] = nof_prb;
+    let cell_config = NgScopeCellConfig {
+        nof_cell: 1,
+        cell_prb,
+        rnti: 0,
+    };
+    broadcast_cell_config(tx_dci, cell_config, carrier_cell_ids);
+}
+
+fn release_ordered_dci(
+    tx_dci: &mut Bus<MessageDci>,
+    jitter_buffer: &mut DciJitterBuffer,
+    quality_tracker: &mut DciQualityTracker,
+    is_log_dci: &bool,
+    log_dci_buffer: &mut Vec<NgScopeCellDci>,
+) {
+    for cell_dci in jitter_buffer.pop_ready(chrono::Local::now().timestamp_micros()) {
+        let missing_tti = quality_tracker.update(&cell_dci);
+        if is_debug() && missing_tti >= DCI_QUALITY_LONG_GAP_TTI {
+            /* the DCI timestamp can be ahead of the local clock */
+            let now_delta = (chrono::Local::now().timestamp_micros() as u64)
+                .saturating_sub(cell_dci.time_stamp);
+            print_debug(&format!(
+                "DEBUG [ngcontrol.fetcher] DCI gap on cell {}:\n\
+                                  \tmissing TTIs before DCI: {:>10}\n\
+                                  \tdiff to now:             {:>10} us",
+                cell_dci.cell_id, missing_tti, now_delta
+            ));
+        }
+        /* check bus size */
+        if *is_log_dci {
+            log_dci_buffer.push(cell_dci);
+        }
+        let message_dci = MessageDci::CellDci(Box::new(cell_dci));
+        broadcast_message_dci(tx_dci, message_dci)
+    }
+}
+
+fn check_dci_quality_report(
+    quality_tracker: &mut DciQualityTracker,
+    jitter_buffer: &DciJitterBuffer,
+    is_log_dci_quality: &bool,
+) {
+    let now_us = chrono::Local::now().timestamp_micros() as u64;
+    if now_us.saturating_sub(quality_tracker.interval_start_us())
+        < DCI_QUALITY_REPORT_INTERVAL_US
+    {
+        return;
+    }
+    let report = quality_tracker.take_interval_report(now_us, jitter_buffer.stats());
+    if is_debug() {
+        print_dci_quality_report(&report);
+    }
+    if *is_log_dci_quality {
+        let _ = log_dci_quality(report);
+    }
+}
+
+fn print_dci_quality_report(report: &DciQualityReport) {
+    for cell in report.cells.iter() {
+        let jitter = report
+            .jitter
+            .get(&cell.cell_id)
+            .cloned()
+            .unwrap_or_default();
+        print_info(&format!(
+            "[ngcontrol.fetcher] {:?} DCI quality cell {}: {} DCI | {} missing TTI ({:.2}%) | {} gaps (longest {}) | {} late | {} duplicate",
+            report.report_type,
+            cell.cell_id,
+            cell.nof_dci,
+            cell.nof_missing_tti,
+            100.0 * cell.missing_ratio(),
+            cell.nof_gaps,
+            cell.longest_gap_tti,
+            jitter.nof_late,
+            jitter.nof_duplicate,
+        ));
+    }
+}
+
 fn check_log_dci(
     is_log_dci: &bool,
     log_dci_buffer: &mut Vec<NgScopeCellDci>,
@@ -428,6 +1122,17 @@ fn check_log_dci(
     }
 }
 
+fn check_log_ue_dci(
+    is_log_dci: &bool,
+    log_ue_dci_buffer: &mut Vec<NgScopeUeDci>,
+    log_dci_batch_size: &u64,
+) {
+    if *is_log_dci && log_ue_dci_buffer.len() >= *log_dci_batch_size as usize {
+        let _ = log_ue_dci(log_ue_dci_buffer.clone());
+        log_ue_dci_buffer.clear()
+    }
+}
+
 /*  --------------  */
 /*      Helpers     */
 /*  --------------  */
@@ -466,9 +1171,11 @@ fn wait_for_running(
     }
 }
 
-fn check_cell_update(rx_cell_info: &mut BusReader<MessageCellInfo>) -> Result<Option<CellInfo>> {
+fn check_cell_update(
+    rx_cell_info: &mut BusReader<MessageCellInfo>,
+) -> Result<Option<MessageCellInfo>> {
     match rx_cell_info.try_recv() {
-        Ok(msg) => Ok(Some(msg.cell_info)),
+        Ok(msg) => Ok(Some(msg)),
         Err(TryRecvError::Disconnected) => {
             // TODO: print error properly
             Err(anyhow!("[ngcontrol] err: rx_cell_info disconnected!"))
@@ -476,3 +1183,279 @@ fn check_cell_update(rx_cell_info: &mut BusReader<MessageCellInfo>) -> Result<Op
         Err(TryRecvError::Empty) => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dummy_cells(nof_cells: u64) -> Vec<SingleCell> {
+        (0..nof_cells)
+            .map(|index| SingleCell {
+                cell_id: 100 + index,
+                frequency: 796_000_000 + index * 10_000_000,
+                pci: Some(301 + index as u16),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_multi_cell_config() {
+        let rf_args: Vec<String> = vec!["serial=A".to_string(), "serial=B".to_string()];
+        let (config, carrier_cell_ids) =
+            determine_multi_cell_config(&NgScopeConfig::default(), &dummy_cells(3), &rf_args, &[]);
+
+        assert_eq!(carrier_cell_ids, vec![100, 101]);
+        assert_eq!(config.nof_rf_dev, 2);
+        assert_eq!(config.dci_log_config.as_ref().unwrap().nof_cell, 2);
+        let rf_devices = config.rf_devices();
+        assert_eq!(rf_devices[0].rf_freq, 796_000_000);
+        assert_eq!(rf_devices[0].rf_args, "serial=A");
+        assert_eq!(rf_devices[1].rf_freq, 806_000_000);
+        assert_eq!(rf_devices[1].rf_args, "serial=B");
+        assert_eq!(rf_devices[0].N_id_2, 1);
+        assert_eq!(rf_devices[1].N_id_2, 2);
+    }
+
+    #[test]
+    fn test_multi_cell_config_n_id_2() {
+        let mut cells = dummy_cells(1);
+        cells[0].pci = None;
+        let (config, _) = determine_multi_cell_config(&NgScopeConfig::default(), &cells, &[], &[]);
+        assert_eq!(config.rf_devices()[0].N_id_2, -1);
+
+        /* an explicit N_id_2 is kept */
+        let mut explicit_config = NgScopeConfig::default();
+        explicit_config.rf_config0.as_mut().unwrap().N_id_2 = 0;
+        let (config, _) = determine_multi_cell_config(&explicit_config, &dummy_cells(1), &[], &[]);
+        assert_eq!(config.rf_devices()[0].N_id_2, 0);
+    }
+
+    #[test]
+    fn test_cell_update_pci_change() -> Result<()> {
+        let mut tx_cell_info = Bus::<MessageCellInfo>::new(10);
+        let mut rx_cell_info = tx_cell_info.add_rx();
+        let (tx_dci_thread, rx_dci_thread) = sync_channel::<LocalDciState>(10);
+        let ng_args =
+            FlattenedNgScopeArgs::from_unflattened(Arguments::default().ngscope.unwrap())?;
+        let mut pci_check = PciCheck::default();
+
+        let mut cell_info = CellInfo {
+            cells: dummy_cells(1),
+            neighbours: vec![],
+        };
+        let mut n_id_2_and_expected_pcis = vec![];
+        for pci in [301, 302] {
+            /* same frequency, new PCI */
+            cell_info.cells[0].pci = Some(pci);
+            tx_cell_info.broadcast(MessageCellInfo::Cells(cell_info.clone()));
+            let state = handle_cell_update(
+                &mut rx_cell_info,
+                &NgScopeConfig::default(),
+                &ng_args,
+                &tx_dci_thread,
+                &mut pci_check,
+            )?;
+            let config = match state {
+                NgControlState::StartNgScope(config) => config,
+                _ => panic!("expected StartNgScope, got {:?}", state),
+            };
+            assert_eq!(config.rf_devices()[0].rf_freq, 796_000_000);
+            n_id_2_and_expected_pcis.push((
+                config.rf_devices()[0].N_id_2,
+                pci_check.expected_pcis.clone(),
+            ));
+            assert!(matches!(
+                rx_dci_thread.try_recv(),
+                Ok(LocalDciState::UpdateCarriers(_))
+            ));
+        }
+        assert_eq!(
+            n_id_2_and_expected_pcis,
+            vec![(1, vec![301]), (2, vec![302])]
+        );
+        assert!(pci_check.is_new_mismatch(301));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pci_check() {
+        let mut pci_check = PciCheck::default();
+        assert!(!pci_check.is_new_mismatch(140));
+
+        pci_check.set_expected(vec![301, 302]);
+        assert!(!pci_check.is_new_mismatch(302));
+        assert!(pci_check.is_new_mismatch(140));
+        assert!(!pci_check.is_new_mismatch(140));
+        assert!(pci_check.is_new_mismatch(141));
+        assert!(!pci_check.is_new_mismatch(301));
+        assert!(pci_check.is_new_mismatch(141));
+    }
+
+    #[test]
+    fn test_multi_cell_config_with_serials() {
+        let rf_args: Vec<String> = vec!["type=b200,serial=A".to_string()];
+        let rf_serials: Vec<String> = vec!["X".to_string(), "Y".to_string()];
+        let (config, carrier_cell_ids) = determine_multi_cell_config(
+            &NgScopeConfig::default(),
+            &dummy_cells(3),
+            &rf_args,
+            &rf_serials,
+        );
+
+        assert_eq!(carrier_cell_ids, vec![100, 101]);
+        let rf_devices = config.rf_devices();
+        assert_eq!(rf_devices[0].rf_args, "serial=X,type=b200");
+        assert_eq!(rf_devices[1].rf_args, "serial=Y");
+    }
+
+    #[test]
+    fn test_apply_config_args() {
+        let mut ng_args =
+            FlattenedNgScopeArgs::from_unflattened(Arguments::default().ngscope.unwrap()).unwrap();
+        let mut config = NgScopeConfig::default();
+        apply_config_args(&mut config, &ng_args);
+        assert_eq!(config, NgScopeConfig::default());
+
+        ng_args.ng_nof_thread = Some(2);
+        ng_args.ng_n_id_2 = Some(1);
+        ng_args.ng_rnti = Some(0xFFFF);
+        ng_args.ng_decode_single_ue = Some(true);
+        ng_args.ng_dci_logs_path = Some("./dci_logs/".to_string());
+        ng_args.ng_dci_log_interval = Some(10);
+        apply_config_args(&mut config, &ng_args);
+        let rf_config0 = config.rf_config0.as_ref().unwrap();
+        assert_eq!(rf_config0.nof_thread, 2);
+        assert_eq!(rf_config0.N_id_2, 1);
+        assert_eq!(rf_config0.rf_args, "serial=3295B62");
+        assert_eq!(config.rnti, 0xFFFF);
+        assert_eq!(config.decode_single_ue, Some(true));
+        assert_eq!(config.decode_sib, Some(true));
+        assert_eq!(config.dci_logs_path, Some("./dci_logs/".to_string()));
+        assert_eq!(config.dci_log_config.as_ref().unwrap().log_interval, 10);
+    }
+
+    fn spawn_shell(script: &str) -> Child {
+        std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_supervisor_restart_backoff() -> Result<()> {
+        let mut supervisor = NgScopeSupervisor::new();
+        for expected_backoff_ms in [1000, 2000, 4000] {
+            supervisor.started(Some(spawn_shell("exit 3")), NgScopeConfig::default());
+            let exit_status = loop {
+                if let Some(exit_status) = supervisor.check_process()? {
+                    break exit_status;
+                }
+                thread::sleep(Duration::from_millis(5));
+            };
+            assert_eq!(exit_status.code(), Some(3));
+            assert!(!supervisor.is_running());
+            let restart_in = supervisor.restart_at.unwrap() - Instant::now();
+            assert!(restart_in <= Duration::from_millis(expected_backoff_ms));
+            assert!(restart_in > Duration::from_millis(expected_backoff_ms - 500));
+            assert!(supervisor.take_due_restart().is_none());
+        }
+        assert_eq!(supervisor.exit_statuses.len(), 3);
+
+        supervisor.restart_at = Some(Instant::now());
+        assert_eq!(
+            supervisor.take_due_restart(),
+            Some(NgScopeConfig::default())
+        );
+        assert!(supervisor.take_due_restart().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_supervisor_sync_loss() {
+        let mut supervisor = NgScopeSupervisor::new();
+        supervisor.started(Some(spawn_shell("sleep 30")), NgScopeConfig::default());
+        assert!(!supervisor.record_sync_loss(3));
+        assert!(!supervisor.record_sync_loss(3));
+        assert!(supervisor.record_sync_loss(3));
+        /* the counter starts over after a triggered restart */
+        assert!(!supervisor.record_sync_loss(3));
+        assert!(!supervisor.record_sync_loss(0));
+        supervisor.stop().unwrap();
+        assert!(!supervisor.record_sync_loss(1));
+    }
+
+    #[test]
+    fn test_supervisor_stop() -> Result<()> {
+        let mut supervisor = NgScopeSupervisor::new();
+        supervisor.started(Some(spawn_shell("sleep 30")), NgScopeConfig::default());
+        assert!(supervisor.check_process()?.is_none());
+
+        let stop_start = Instant::now();
+        supervisor.stop()?;
+        assert!(stop_start.elapsed() < Duration::from_secs(3));
+        assert!(!supervisor.is_running());
+        assert!(supervisor.check_process()?.is_none());
+        assert!(supervisor.take_due_restart().is_none());
+        assert!(supervisor.exit_statuses.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dci_watchdog_escalation() {
+        let last_dci_us = Arc::new(AtomicU64::new(0));
+        let mut watchdog = DciWatchdog::new(5, last_dci_us.clone());
+        assert!(watchdog.check(1_000_000).is_none());
+
+        watchdog.arm(1_000_000);
+        last_dci_us.store(1_002_000, Ordering::Relaxed);
+        assert!(watchdog.check(1_006_000).is_none());
+        assert_eq!(
+            watchdog.check(1_007_000),
+            Some(DciSilenceAction::ReHandshake)
+        );
+        /* disarmed until the handshake is triggered again */
+        assert!(watchdog.check(1_020_000).is_none());
+        assert!(watchdog.is_recovering());
+
+        watchdog.arm(1_020_000);
+        assert_eq!(
+            watchdog.check(1_025_000),
+            Some(DciSilenceAction::RestartNgScope)
+        );
+
+        watchdog.reset();
+        assert!(!watchdog.is_recovering());
+        watchdog.arm(1_030_000);
+        assert_eq!(
+            watchdog.check(1_035_000),
+            Some(DciSilenceAction::ReHandshake)
+        );
+        /* a DCI after the re-handshake starts the escalation over */
+        watchdog.arm(1_036_000);
+        last_dci_us.store(1_037_000, Ordering::Relaxed);
+        assert_eq!(
+            watchdog.check(1_042_000),
+            Some(DciSilenceAction::ReHandshake)
+        );
+
+        let mut disabled_watchdog = DciWatchdog::new(0, last_dci_us);
+        disabled_watchdog.arm(1_000_000);
+        assert!(disabled_watchdog.check(2_000_000).is_none());
+    }
+
+    #[test]
+    fn test_multi_cell_config_without_rf_args() {
+        let default_config = NgScopeConfig::default();
+        let (config, carrier_cell_ids) =
+            determine_multi_cell_config(&default_config, &dummy_cells(3), &[], &[]);
+
+        assert_eq!(carrier_cell_ids, vec![100]);
+        assert_eq!(config.nof_rf_dev, 1);
+        assert_eq!(
+            config.rf_config0.unwrap().rf_args,
+            default_config.rf_config0.unwrap().rf_args
+        );
+    }
+}
@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::mem;
 use std::net::UdpSocket;
 use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
@@ -13,7 +14,9 @@ use nalgebra::{DMatrix, DVector};
 use serde_derive::{Deserialize, Serialize};
 
 use crate::logger::log_traffic_collection;
-use crate::logic::traffic_patterns::{TrafficPattern, TrafficPatternFeatures};
+use crate::logic::traffic_patterns::{
+    RntiMatchingTrafficPatternType, TrafficPattern, TrafficPatternFeatures,
+};
 use crate::logic::{
     check_not_stopped, wait_until_running, MainState, MessageDci, MessageRnti, RntiMatcherState,
     RntiMatchingErrorType, CHANNEL_SYNC_SIZE, DEFAULT_WORKER_SLEEP_MS,
@@ -31,19 +34,19 @@ use crate::math_util::{
 use super::{MessageMetric, MetricTypes};
 
 pub const MATCHING_INTERVAL_MS: u64 = 1000;
-pub const MATCHING_TRAFFIC_PATTERN_TIME_OVERLAP_FACTOR: f64 = 1.1;
 pub const MATCHING_MAX_DCI_TIMESTAMP_DELTA_MS: u64 = 100;
 pub const MATCHING_UL_BYTES_LOWER_BOUND_FACTOR: f64 = 0.5;
 pub const MATCHING_UL_BYTES_UPPER_BOUND_FACTOR: f64 = 4.0;
 pub const TIME_MS_TO_US_FACTOR: u64 = 1000;
 pub const COLLECT_DCI_MAX_TIMESTAMP_DELTA_US: u64 = 50000;
 
-pub const BASIC_FILTER_MAX_TOTAL_UL_FACTOR: f64 = 200.0;
-pub const BASIC_FILTER_MIN_TOTAL_UL_FACTOR: f64 = 0.005;
-pub const BASIC_FILTER_MAX_UL_PER_DCI: u64 = 5_000_000;
-pub const BASIC_FILTER_MIN_OCCURENCES_FACTOR: f64 = 0.01;
-
-pub const RNTI_RING_BUFFER_SIZE: usize = 10;
+/* Defaults of the configurable matching parameters (RntiMatchingArgs) */
+pub const DEFAULT_MATCHING_TRAFFIC_PATTERN_TIME_OVERLAP_FACTOR: f64 = 1.1;
+pub const DEFAULT_BASIC_FILTER_MAX_TOTAL_UL_FACTOR: f64 = 200.0;
+pub const DEFAULT_BASIC_FILTER_MIN_TOTAL_UL_FACTOR: f64 = 0.005;
+pub const DEFAULT_BASIC_FILTER_MAX_UL_PER_DCI: u64 = 5_000_000;
+pub const DEFAULT_BASIC_FILTER_MIN_OCCURENCES_FACTOR: f64 = 0.01;
+pub const DEFAULT_RNTI_RING_BUFFER_SIZE: usize = 10;
 
 pub const METRIC_HEADER_LENGTH: usize = 5;
 pub const METRIC_INITIAL_INDEX_START: usize = 0;
@@ -65,30 +68,9 @@ pub const METRIC_PAYLOAD_INDEX: usize = 5;
  * DCI timestamp delta mean
  * DCI timestamp delta variance
  * */
-// pub const MATCHING_WEIGHTINGS: [f64; 8] = [
-//     0.5,    /* DCI count (occurences) */
-//     0.1,    /* Total UL bytes */
-//     0.15,    /* UL bytes median */
-//     0.025,  /* UL bytes mean */
-//     0.025,  /* UL bytes variance */
-//     0.15,    /* DCI time delta median */
-//     0.025,  /* DCI time delta mean */
-//     0.025,  /* DCI time delta variance */
-// ];
-
-/* on D, not so nice */
-// pub const MATCHING_WEIGHTINGS: [f64; 8] = [
-//     0.3,    /* DCI count (occurences) */
-//     0.3,    /* Total UL bytes */
-//     0.1,    /* UL bytes median */
-//     0.2,  /* UL bytes mean */
-//     0.025,  /* UL bytes variance */
-//     0.025,    /* DCI time delta median */
-//     0.025,  /* DCI time delta mean */
-//     0.025,  /* DCI time delta variance */
-// ];
-
-pub const MATCHING_WEIGHTINGS: [f64; 8] = [
+pub const NOF_MATCHING_FEATURES: usize = 8;
+
+pub const DEFAULT_MATCHING_WEIGHTINGS: [f64; NOF_MATCHING_FEATURES] = [
     0.5,   /* DCI count (occurences) */
     0.3,   /* Total UL bytes */
     0.1,   /* UL bytes median */
@@ -99,6 +81,98 @@ pub const MATCHING_WEIGHTINGS: [f64; 8] = [
     0.020, /* DCI time delta variance */
 ];
 
+/// Parameters of a single matching run, can differ per traffic pattern
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchingParameters {
+    /// One weighting per feature, in feature vector order
+    pub weightings: Vec<f64>,
+    /// DCIs are collected for the pattern's total time * this factor
+    pub time_overlap_factor: f64,
+    /// Factors are relative to the pattern's total UL bytes or number of packets
+    pub basic_filter_max_total_ul_factor: f64,
+    pub basic_filter_min_total_ul_factor: f64,
+    pub basic_filter_max_ul_per_dci: u64,
+    pub basic_filter_min_occurences_factor: f64,
+}
+
+impl Default for MatchingParameters {
+    fn default() -> Self {
+        MatchingParameters {
+            weightings: DEFAULT_MATCHING_WEIGHTINGS.to_vec(),
+            time_overlap_factor: DEFAULT_MATCHING_TRAFFIC_PATTERN_TIME_OVERLAP_FACTOR,
+            basic_filter_max_total_ul_factor: DEFAULT_BASIC_FILTER_MAX_TOTAL_UL_FACTOR,
+            basic_filter_min_total_ul_factor: DEFAULT_BASIC_FILTER_MIN_TOTAL_UL_FACTOR,
+            basic_filter_max_ul_per_dci: DEFAULT_BASIC_FILTER_MAX_UL_PER_DCI,
+            basic_filter_min_occurences_factor: DEFAULT_BASIC_FILTER_MIN_OCCURENCES_FACTOR,
+        }
+    }
+}
+
+impl MatchingParameters {
+    pub fn validate(&self) -> Result<()> {
+        if self.weightings.len() != NOF_MATCHING_FEATURES {
+            return Err(anyhow!(
+                "matching weightings need {} values, got {}",
+                NOF_MATCHING_FEATURES,
+                self.weightings.len()
+            ));
+        }
+        if self
+            .weightings
+            .iter()
+            .any(|weighting| !weighting.is_finite() || *weighting < 0.0)
+        {
+            return Err(anyhow!(
+                "matching weightings must be finite and >= 0: {:?}",
+                self.weightings
+            ));
+        }
+        if self.time_overlap_factor < 1.0 {
+            return Err(anyhow!(
+                "matching time overlap factor must be >= 1.0, got {}",
+                self.time_overlap_factor
+            ));
+        }
+        if self.basic_filter_min_total_ul_factor > self.basic_filter_max_total_ul_factor {
+            return Err(anyhow!(
+                "basic filter min total UL factor ({}) exceeds the max total UL factor ({})",
+                self.basic_filter_min_total_ul_factor,
+                self.basic_filter_max_total_ul_factor
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Weightings written by the calibrate-matching subcommand
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchingWeightsProfile {
+    /// None applies to all traffic patterns
+    pub pattern: Option<RntiMatchingTrafficPatternType>,
+    pub weightings: Vec<f64>,
+    /// Calibration result, informational only
+    #[serde(default)]
+    pub accuracy: f64,
+    #[serde(default)]
+    pub mean_margin: Option<f64>,
+    #[serde(default)]
+    pub nof_samples: usize,
+}
+
+impl MatchingWeightsProfile {
+    pub fn load(path: &str) -> Result<MatchingWeightsProfile> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| anyhow!("could not read weights profile {}: {}", path, err))?;
+        serde_yaml::from_str(&content)
+            .map_err(|err| anyhow!("invalid weights profile {}: {}", path, err))
+    }
+
+    pub fn store(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 enum LocalGeneratorState {
     Stop,
@@ -137,6 +211,9 @@ pub struct TrafficCollection {
     pub start_timestamp_ms: u64,
     pub finish_timestamp_ms: u64,
     pub traffic_pattern_features: TrafficPatternFeatures,
+    /// Missing in logs of versions without configurable matching parameters
+    #[serde(default)]
+    pub matching_parameters: MatchingParameters,
     pub basic_filter_statistics: Option<BasicFilterStatistics>,
     pub feature_distance_statistics: Option<FeatureDistanceStatistics>,
 }
@@ -207,12 +284,17 @@ fn run(run_args: &mut RunArgs, run_args_mov: RunArgsMovables) -> Result<()> {
     let scenario = app_args.scenario.unwrap();
 
     let mut cell_rnti_ring_buffer: CellRntiRingBuffer =
-        CellRntiRingBuffer::new(RNTI_RING_BUFFER_SIZE);
-    let traffic_destination = matching_args.matching_traffic_destination;
-    let traffic_pattern_list: Vec<TrafficPattern> = matching_args
+        CellRntiRingBuffer::new(matching_args.matching_rnti_ring_buffer_size);
+    let traffic_destination = matching_args.matching_traffic_destination.clone();
+    let traffic_pattern_list: Vec<(TrafficPattern, MatchingParameters)> = matching_args
         .matching_traffic_pattern
         .iter()
-        .map(|pattern_type| pattern_type.generate_pattern())
+        .map(|pattern_type| {
+            (
+                pattern_type.generate_pattern(),
+                matching_args.parameters_for(*pattern_type),
+            )
+        })
         .collect();
     let log_matching: bool = matching_args.matching_log_traffic;
     let mut traffic_pattern_index = 0;
@@ -221,7 +303,7 @@ fn run(run_args: &mut RunArgs, run_args_mov: RunArgsMovables) -> Result<()> {
     let (tx_gen_thread, rx_gen_thread) = sync_channel::<LocalGeneratorState>(CHANNEL_SYNC_SIZE);
     run_args.gen_thread_handle = Some(deploy_traffic_generator_thread(
         rx_gen_thread,
-        matching_args.matching_local_addr,
+        matching_args.matching_local_addr.clone(),
         traffic_destination.clone(),
         rx_metric,
     )?);
@@ -297,16 +379,17 @@ fn collect_dcis(rx_dci: &mut BusReader<MessageDci>) -> Vec<MessageDci> {
 
 fn handle_start_matching(
     tx_gen_thread: &SyncSender<LocalGeneratorState>,
-    traffic_pattern_list: &[TrafficPattern],
+    traffic_pattern_list: &[(TrafficPattern, MatchingParameters)],
     traffic_pattern_index: &mut usize,
 ) -> RntiMatcherState {
-    let traffic_pattern = traffic_pattern_list[*traffic_pattern_index].clone();
+    let (traffic_pattern, matching_parameters) =
+        traffic_pattern_list[*traffic_pattern_index].clone();
     *traffic_pattern_index = (*traffic_pattern_index + 1) % traffic_pattern_list.len();
 
     let pattern_total_ms = traffic_pattern.total_time_ms();
     let start_timestamp_ms = chrono::Local::now().timestamp_millis() as u64;
     let finish_timestamp_ms = start_timestamp_ms
-        + (MATCHING_TRAFFIC_PATTERN_TIME_OVERLAP_FACTOR * pattern_total_ms as f64) as u64;
+        + (matching_parameters.time_overlap_factor * pattern_total_ms as f64) as u64;
     let traffic_pattern_features =
         match TrafficPatternFeatures::from_traffic_pattern(&traffic_pattern) {
             Ok(features) => features,
@@ -322,6 +405,7 @@ fn handle_start_matching(
         start_timestamp_ms,
         finish_timestamp_ms,
         traffic_pattern_features,
+        matching_parameters,
         basic_filter_statistics: None,
         feature_distance_statistics: None,
     };
@@ -343,10 +427,19 @@ fn handle_collect_dci(
 
     let start_timestamp_ms_bound = traffic_collection.start_timestamp_ms * TIME_MS_TO_US_FACTOR;
     for dci in dci_list.iter() {
-        if let MessageDci::CellDci(ngscope_dci) = dci {
-            if ngscope_dci.time_stamp >= start_timestamp_ms_bound {
+        match dci {
+            MessageDci::CellDci(ngscope_dci)
+                if ngscope_dci.time_stamp >= start_timestamp_ms_bound =>
+            {
                 traffic_collection.update_from_cell_dci(ngscope_dci);
             }
+            /* the pattern is incomplete without the DCIs of the silent period */
+            MessageDci::DciStale(_) => {
+                return RntiMatcherState::MatchingError(
+                    RntiMatchingErrorType::DciStaleDuringCollection,
+                );
+            }
+            _ => {}
         }
     }
     RntiMatcherState::MatchingCollectDci(Box::new(traffic_collection))
@@ -359,6 +452,11 @@ fn handle_process_dci(
 ) -> RntiMatcherState {
     // Check number of packets plausability: expected ms -> expected dcis
     let mut message_rnti: MessageRnti = MessageRnti::default();
+    /* the log keeps all RNTIs, so the matching can be replayed with other filters */
+    let mut logged_collection = match log_traffic {
+        true => Some(traffic_collection.clone()),
+        false => None,
+    };
 
     /* First processing step: Reduce RNTIs */
     traffic_collection.apply_basic_filter();
@@ -375,8 +473,11 @@ fn handle_process_dci(
             );
         }
     };
-    if log_traffic {
-        let _ = log_traffic_collection(traffic_collection.clone());
+    if let Some(mut logged_collection) = logged_collection.take() {
+        logged_collection.basic_filter_statistics = traffic_collection.basic_filter_statistics;
+        logged_collection.feature_distance_statistics =
+            traffic_collection.feature_distance_statistics;
+        let _ = log_traffic_collection(logged_collection);
     }
     cell_rnti_ring_buffer.update(&best_matches);
     print_debug(&format!(
@@ -393,6 +494,10 @@ fn handle_matching_error(
 ) -> RntiMatcherState {
     match error_type {
         RntiMatchingErrorType::ExceededDciTimestampDelta => {}
+        RntiMatchingErrorType::DciStaleDuringCollection => {
+            print_info("[rntimatcher] DCIs went stale during collection, restarting the pattern");
+            let _ = tx_gen_thread.send(LocalGeneratorState::Idle);
+        }
         RntiMatchingErrorType::ErrorGeneratingTrafficPatternFeatures
         | RntiMatchingErrorType::ErrorFindingBestMatchingRnti => {
             print_info(&format!(
@@ -691,17 +796,19 @@ impl TrafficCollection {
      *   MEDIAN UL
      *
      * */
-    fn apply_basic_filter(&mut self) {
+    pub fn apply_basic_filter(&mut self) {
         let mut stats: BasicFilterStatistics = Default::default();
-        let max_total_ul = (BASIC_FILTER_MAX_TOTAL_UL_FACTOR
+        let params = &self.matching_parameters;
+        let max_total_ul = (params.basic_filter_max_total_ul_factor
             * self.traffic_pattern_features.total_ul_bytes as f64)
             .round() as u64;
-        let min_total_ul = (BASIC_FILTER_MIN_TOTAL_UL_FACTOR
+        let min_total_ul = (params.basic_filter_min_total_ul_factor
             * self.traffic_pattern_features.total_ul_bytes as f64)
             .round() as u64;
-        let min_occurences = (BASIC_FILTER_MIN_OCCURENCES_FACTOR
+        let min_occurences = (params.basic_filter_min_occurences_factor
             * self.traffic_pattern_features.nof_packets as f64)
             .round() as u64;
+        let max_ul_per_dci = params.basic_filter_max_ul_per_dci;
 
         // Determine which RNTIs to remove
         let to_keep: HashMap<u64, HashSet<u16>> = self
@@ -742,7 +849,7 @@ impl TrafficCollection {
                     .filter(|(_, ue_traffic)| {
                         let mut filtered = true;
                         for (_, tx_data) in ue_traffic.traffic.iter() {
-                            if tx_data.ul_bytes > BASIC_FILTER_MAX_UL_PER_DCI {
+                            if tx_data.ul_bytes > max_ul_per_dci {
                                 stats.max_ul_per_dci += 1;
                                 filtered = false;
                                 break;
@@ -790,6 +897,20 @@ impl TrafficCollection {
     pub fn find_best_matching_rnti(&mut self) -> Result<HashMap<u64, u16>> {
         /* Change this to use the functional approach */
         // feature_distance_functional(&self.cell_traffic, pattern_std_vec, pattern_feature_vec);
+        Ok(self
+            .feature_distance_matrices()?
+            .into_iter()
+            .filter_map(|(cell_id, rnti_and_distance)| {
+                rnti_and_distance.first().map(|&(rnti, _)| (cell_id, rnti))
+            })
+            .collect())
+    }
+
+    /*
+     * cell_id -> [ (rnti, distance) ], sorted by distance
+     *
+     * */
+    pub fn rank_rntis(&mut self) -> Result<HashMap<u64, Vec<(u16, f64)>>> {
         self.feature_distance_matrices()
     }
 
@@ -808,7 +929,7 @@ impl TrafficCollection {
                         let distance = calculate_weighted_euclidean_distance(
                             pattern_feature_vec,
                             &std_feature_vec,
-                            &MATCHING_WEIGHTINGS,
+                            &self.matching_parameters.weightings,
                         );
                         Ok((rnti, distance))
                     })
@@ -820,11 +941,12 @@ impl TrafficCollection {
             .collect::<Result<HashMap<u64, u16>>>()
     }
 
-    fn feature_distance_matrices(&mut self) -> Result<HashMap<u64, u16>> {
+    fn feature_distance_matrices(&mut self) -> Result<HashMap<u64, Vec<(u16, f64)>>> {
         let pattern_std_vec = &self.traffic_pattern_features.std_vec;
         let pattern_feature_vec = &self.traffic_pattern_features.std_feature_vec;
         let num_features = pattern_std_vec.len();
-        let weightings_vector = DVector::from_row_slice(&MATCHING_WEIGHTINGS);
+        let weightings = self.matching_parameters.weightings.clone();
+        let weightings_vector = DVector::from_row_slice(&weightings);
 
         self.cell_traffic
             .iter()
@@ -875,7 +997,7 @@ impl TrafficCollection {
                 rnti_and_distance.sort_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap());
 
                 self.feature_distance_statistics = Some(FeatureDistanceStatistics {
-                    weightings: MATCHING_WEIGHTINGS.to_vec(),
+                    weightings: weightings.clone(),
                     pattern_standardization: pattern_std_vec.clone(),
                     pattern_features: pattern_feature_vec.clone(),
                     rntis: cell_traffic.traffic.keys().cloned().collect(),
@@ -883,9 +1005,9 @@ impl TrafficCollection {
                     rnti_distances: euclidean_distances.column(0).iter().cloned().collect(),
                 });
 
-                Ok((cell_id, rnti_and_distance.first().unwrap().0))
+                Ok((cell_id, rnti_and_distance))
             })
-            .collect::<Result<HashMap<u64, u16>>>()
+            .collect::<Result<HashMap<u64, Vec<(u16, f64)>>>>()
     }
 }
 
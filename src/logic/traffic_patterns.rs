@@ -203,7 +203,7 @@ fn generate_incremental_pattern(
 /* WARNING: The total time of a traffic pattern must be > 0
  *
  * After sending the pattern, the DCI messages are collected
- * for the total time * MATCHING_TRAFFIC_PATTERN_TIME_OVERLAP_FACTOR
+ * for the total time * time_overlap_factor (MatchingParameters)
  * */
 
 fn pattern_a() -> TrafficPattern {
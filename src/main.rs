@@ -2,6 +2,8 @@ use anyhow::{anyhow, Result};
 use bus::{Bus, BusReader};
 use logger::{deploy_logger, LoggerArgs, LoggerState};
 use logic::downloader::{deploy_downloader, DownloaderArgs};
+use logic::matching_calibration::run_calibrate_matching;
+use logic::matching_replay::run_replay_matching;
 use std::collections::HashSet;
 use std::error::Error;
 use std::sync::atomic::AtomicBool;
@@ -10,10 +12,15 @@ use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
+mod band_table;
+mod cell_file;
 mod cell_info;
+mod devicepublisher;
 mod logger;
 mod logic;
 mod math_util;
+mod milesight;
+mod modem;
 mod ngscope;
 mod parse;
 mod util;
@@ -29,7 +36,8 @@ use logic::{
     WORKER_SLEEP_LONG_MS,
 };
 use logic::{MessageMetric, WorkerChannel, BUS_SIZE_METRIC};
-use parse::Arguments;
+use ngscope::fake_server::run_fake_ngscope;
+use parse::{AppCommand, Arguments, FlattenedNgScopeArgs, FlattenedRntiMatchingArgs};
 use util::{determine_process_id, is_notifier, prepare_sigint_notifier, print_info, set_debug};
 
 struct CombinedReceivers {
@@ -221,6 +229,10 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let sigint_notifier = prepare_sigint_notifier()?;
 
+    if let Some(command) = args.command.clone() {
+        return Ok(run_command(command, &args, &sigint_notifier)?);
+    }
+
     let mut tx_app_state = Bus::<MainState>::new(BUS_SIZE_APP_STATE);
     let (model_tx, model_rx) = sync_channel::<ModelState>(CHANNEL_SYNC_SIZE);
     let (source_tx, source_rx) = sync_channel::<SourceState>(CHANNEL_SYNC_SIZE);
@@ -280,6 +292,47 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Run a standalone tool with only the logger deployed
+fn run_command(
+    command: AppCommand,
+    args: &Arguments,
+    sigint_notifier: &Arc<AtomicBool>,
+) -> Result<()> {
+    let mut tx_app_state = Bus::<MainState>::new(BUS_SIZE_APP_STATE);
+    let (logger_tx, logger_rx) = sync_channel::<LoggerState>(CHANNEL_SYNC_SIZE);
+    let logger_task = deploy_logger(LoggerArgs {
+        app_args: args.clone(),
+        rx_app_state: tx_app_state.add_rx(),
+        tx_logger_state: logger_tx,
+    })?;
+    logger_rx.worker_recv_general_state()?;
+    tx_app_state.broadcast(MainState::Running);
+
+    let result = match command {
+        AppCommand::FakeNgScope(fake_args) => {
+            let ng_args = FlattenedNgScopeArgs::from_unflattened(args.clone().ngscope.unwrap())?;
+            run_fake_ngscope(&ng_args, &fake_args, sigint_notifier)
+        }
+        AppCommand::ReplayMatching(replay_args) => {
+            let matching_args =
+                FlattenedRntiMatchingArgs::from_unflattened(args.clone().rntimatching.unwrap())?;
+            run_replay_matching(&matching_args, &replay_args)
+        }
+        AppCommand::CalibrateMatching(calibrate_args) => {
+            let matching_args =
+                FlattenedRntiMatchingArgs::from_unflattened(args.clone().rntimatching.unwrap())?;
+            run_calibrate_matching(&matching_args, &calibrate_args)
+        }
+    };
+    if let Err(ref err) = result {
+        print_info(&format!("[main] command failed: {:?}", err));
+    }
+
+    tx_app_state.broadcast(MainState::Stopped);
+    let _ = logger_task.join();
+    result
+}
+
 fn handle_running(
     tx_app_state: &mut Bus<MainState>,
     rx_states: &CombinedReceivers,
@@ -0,0 +1,342 @@
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use reqwest::header::{HeaderMap, HeaderName};
+use reqwest::StatusCode;
+use tokio::runtime::Runtime;
+
+use crate::cell_info::CellInfo;
+use crate::util::print_debug;
+
+/*
+ * Milesight router /cgi client that keeps its login session
+ *
+ * The session cookie of the login is reused for every request. The client
+ * logs in again only if the router rejects the session.
+ * */
+
+/// Error code the /cgi API answers with if the session is not valid anymore
+const MILESIGHT_SESSION_EXPIRED_CODE: i64 = -32000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MilesightError {
+    /// The login was rejected or did not return a session cookie
+    AuthFailed(String),
+    /// The router did not accept the session cookie anymore
+    SessionExpired,
+}
+
+impl fmt::Display for MilesightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MilesightError::AuthFailed(reason) => {
+                write!(f, "Milesight authentication failed: {}", reason)
+            }
+            MilesightError::SessionExpired => write!(f, "Milesight session expired"),
+        }
+    }
+}
+
+impl std::error::Error for MilesightError {}
+
+struct MilesightSession {
+    url: String,
+    user: String,
+    auth: String,
+    client: reqwest::Client,
+    cookie: Option<HeaderMap>,
+    #[cfg(test)]
+    nof_logins: u64,
+}
+
+pub struct MilesightClient {
+    runtime: Arc<Runtime>,
+    session: MilesightSession,
+}
+
+impl MilesightClient {
+    /// The runtime and the (connection pooled) client are shared with the caller
+    pub fn new(
+        base_addr: &str,
+        user: &str,
+        auth: &str,
+        runtime: Arc<Runtime>,
+        client: reqwest::Client,
+    ) -> Result<MilesightClient> {
+        Ok(MilesightClient {
+            runtime,
+            session: MilesightSession {
+                url: format!("http://{}/cgi", base_addr),
+                user: user.to_string(),
+                auth: auth.to_string(),
+                client,
+                cookie: None,
+                #[cfg(test)]
+                nof_logins: 0,
+            },
+        })
+    }
+
+    pub fn retrieve_cell_info(&mut self) -> Result<CellInfo> {
+        let response_json = self.runtime.block_on(self.session.get_cell())?;
+        CellInfo::from_cgi_response(&response_json)
+    }
+
+    #[cfg(test)]
+    pub fn nof_logins(&self) -> u64 {
+        self.session.nof_logins
+    }
+}
+
+impl MilesightSession {
+    async fn post(&self, payload: String) -> Result<reqwest::Response> {
+        let mut request = self
+            .client
+            .get(&self.url)
+            .header("Accept", "application/json, text/javascript, */*; q=0.01")
+            .header("Content-Type", "application/json")
+            .body(payload);
+        if let Some(cookie) = &self.cookie {
+            request = request.headers(cookie.clone());
+        }
+        Ok(request.send().await?)
+    }
+
+    async fn login(&mut self) -> Result<()> {
+        self.cookie = None;
+        #[cfg(test)]
+        {
+            self.nof_logins += 1;
+        }
+        let payload = format!(
+            "{{
+            \"id\":\"1\",\
+            \"execute\":1,\
+            \"core\":\"user\",\
+            \"function\":\"login\",\
+            \"values\":[{{\"username\":\"{}\",\"password\":\"{}\"}}]
+          }}",
+            self.user, self.auth
+        );
+        let resp = self.post(payload).await?;
+        if is_auth_status(resp.status()) {
+            return Err(MilesightError::AuthFailed(format!("status {}", resp.status())).into());
+        }
+        /* the router expects the session as it was handed out */
+        let set_cookie = HeaderName::from_static("set-cookie");
+        let mut header_map = HeaderMap::new();
+        for content in resp.headers().get_all(&set_cookie).iter() {
+            header_map.append(set_cookie.clone(), content.clone());
+        }
+        if header_map.is_empty() {
+            return Err(MilesightError::AuthFailed(format!(
+                "no session cookie in response: {resp:#?}"
+            ))
+            .into());
+        }
+        self.cookie = Some(header_map);
+        Ok(())
+    }
+
+    async fn request_cell(&self) -> Result<serde_json::Value> {
+        let payload = "{\"id\":\"1\",\
+                            \"execute\":1,\
+                            \"core\":\"user\",\
+                            \"function\":\"get\",\
+                            \"values\":[{\"base\":\"yruo_celluar\"}]}"
+            .to_string();
+        let resp = self.post(payload).await?;
+        if is_auth_status(resp.status()) {
+            return Err(MilesightError::SessionExpired.into());
+        }
+        let body = resp.text().await?;
+        let response_value: serde_json::Value = serde_json::from_str(&body)?;
+        /* an expired session is answered with an error object instead of a result */
+        if let Some(error) = response_value.get("error") {
+            if error.get("code").and_then(|code| code.as_i64())
+                == Some(MILESIGHT_SESSION_EXPIRED_CODE)
+            {
+                return Err(MilesightError::SessionExpired.into());
+            }
+            return Err(anyhow!("[milesight] router returned an error: {}", error));
+        }
+        Ok(response_value)
+    }
+
+    /// Log in if necessary and repeat the request once if the session expired
+    async fn get_cell(&mut self) -> Result<serde_json::Value> {
+        if self.cookie.is_none() {
+            self.login().await?;
+        }
+        match self.request_cell().await {
+            Err(err) if err.downcast_ref() == Some(&MilesightError::SessionExpired) => {
+                print_debug("DEBUG [milesight] session expired, logging in again");
+                self.login().await?;
+                match self.request_cell().await {
+                    Err(err) if err.downcast_ref() == Some(&MilesightError::SessionExpired) => {
+                        Err(MilesightError::AuthFailed(
+                            "session rejected right after login".to_string(),
+                        )
+                        .into())
+                    }
+                    result => result,
+                }
+            }
+            result => result,
+        }
+    }
+}
+
+fn is_auth_status(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn dummy_client(addr: &str, auth: &str) -> Result<MilesightClient> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        MilesightClient::new(
+            addr,
+            "root",
+            auth,
+            Arc::new(runtime),
+            reqwest::Client::new(),
+        )
+    }
+
+    const CELL_RESPONSE: &str = r#"{"result":[{"get":[{"value":{
+        "modem":{"signal":"31asu (-51dBm)","net_type":"LTE","cellid":"1C17302"},
+        "more":{"rsrp":"-77dBm","rsrq":"-8dB","earfcn":"1300","pcid":"140"}}}]}]}"#;
+
+    fn read_request(stream: &mut TcpStream) -> (String, String) {
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+            head.push_str(&line);
+        }
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).unwrap();
+        (head, String::from_utf8(body).unwrap())
+    }
+
+    fn respond(stream: &mut TcpStream, status: &str, headers: &str, body: &str) {
+        let response = format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    }
+
+    /// Router that hands out the sessions "s1", "s2", .. and expires the first one
+    /// after a single request. A password other than "secret" is rejected.
+    fn run_fake_router(listener: TcpListener, nof_requests: usize) {
+        let mut nof_sessions = 0;
+        let mut nof_session_requests = 0;
+        for stream in listener.incoming().take(nof_requests) {
+            let mut stream = stream.unwrap();
+            let (head, body) = read_request(&mut stream);
+            if body.contains("\"login\"") {
+                if !body.contains("\"secret\"") {
+                    respond(&mut stream, "401 Unauthorized", "", "");
+                    continue;
+                }
+                nof_sessions += 1;
+                let cookie = format!("Set-Cookie: session=s{}\r\n", nof_sessions);
+                respond(&mut stream, "200 OK", &cookie, "{\"result\":[]}");
+            } else if head.contains(&format!("session=s{}", nof_sessions)) {
+                nof_session_requests += 1;
+                if nof_sessions == 1 && nof_session_requests > 1 {
+                    let body = format!(
+                        "{{\"error\":{{\"code\":{}}}}}",
+                        MILESIGHT_SESSION_EXPIRED_CODE
+                    );
+                    respond(&mut stream, "200 OK", "", &body);
+                } else {
+                    respond(&mut stream, "200 OK", "", CELL_RESPONSE);
+                }
+            } else {
+                respond(&mut stream, "401 Unauthorized", "", "");
+            }
+        }
+    }
+
+    #[test]
+    fn test_session_reuse_and_relogin() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        /* login, get, get (expired), login, get, get */
+        let router = thread::spawn(move || run_fake_router(listener, 6));
+
+        let mut client = dummy_client(&addr, "secret")?;
+        assert_eq!(client.retrieve_cell_info()?.cells[0].pci, Some(140));
+        assert_eq!(client.nof_logins(), 1);
+        assert_eq!(client.retrieve_cell_info()?.cells[0].pci, Some(140));
+        assert_eq!(client.nof_logins(), 2);
+        assert_eq!(client.retrieve_cell_info()?.cells[0].pci, Some(140));
+        assert_eq!(client.nof_logins(), 2);
+        router.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_auth_failed() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        let router = thread::spawn(move || run_fake_router(listener, 1));
+
+        let mut client = dummy_client(&addr, "wrong")?;
+        let err = client.retrieve_cell_info().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<MilesightError>(),
+            Some(MilesightError::AuthFailed(_))
+        ));
+        router.join().unwrap();
+        Ok(())
+    }
+    #[test]
+    fn test_other_error_passed_through() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        /* login, get (error), no second login */
+        let router = thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let (_, body) = read_request(&mut stream);
+                if body.contains("\"login\"") {
+                    respond(&mut stream, "200 OK", "Set-Cookie: session=s1\r\n", "{}");
+                } else {
+                    let error = "{\"error\":{\"code\":-32602,\"message\":\"Invalid params\"}}";
+                    respond(&mut stream, "200 OK", "", error);
+                }
+            }
+        });
+
+        let mut client = dummy_client(&addr, "secret")?;
+        let err = client.retrieve_cell_info().unwrap_err();
+        assert!(err.downcast_ref::<MilesightError>().is_none());
+        assert!(err.to_string().contains("-32602"));
+        assert_eq!(client.nof_logins(), 1);
+        router.join().unwrap();
+        Ok(())
+    }
+}
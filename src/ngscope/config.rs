@@ -2,7 +2,13 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::option::Option;
 
+use crate::ngscope::libconfig;
+
+/* Number of rf_config<n> entries supported by the ng-scope config */
+pub const NGSCOPE_MAX_NOF_RF_DEV: usize = 5;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
 #[allow(non_snake_case)]
 pub struct NgScopeConfigRfDev {
     pub rf_freq: i64,
@@ -20,6 +26,7 @@ pub struct NgScopeConfigRfDev {
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
 pub struct NgScopeConfigDciLog {
     pub nof_cell: u16,
     pub log_ul: bool,
@@ -28,6 +35,7 @@ pub struct NgScopeConfigDciLog {
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
 pub struct NgScopeConfig {
     pub nof_rf_dev: u16,
     pub rnti: u16,
@@ -35,7 +43,11 @@ pub struct NgScopeConfig {
     pub remote_enable: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub decode_single_ue: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "decode_SIB",
+        alias = "decode_sib",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub decode_sib: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub dci_logs_path: Option<String>,
@@ -103,24 +115,63 @@ impl Default for NgScopeConfig {
     }
 }
 
+impl NgScopeConfig {
+    /// RF devices in rf_config<n> order, unset entries are skipped
+    pub fn rf_devices(&self) -> Vec<&NgScopeConfigRfDev> {
+        [
+            &self.rf_config0,
+            &self.rf_config1,
+            &self.rf_config2,
+            &self.rf_config3,
+            &self.rf_config4,
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Replace all RF devices and set nof_rf_dev and dci_log_config.nof_cell accordingly.
+    /// Devices beyond NGSCOPE_MAX_NOF_RF_DEV are dropped.
+    pub fn set_rf_devices(&mut self, rf_devices: Vec<NgScopeConfigRfDev>) {
+        let mut rf_devices = rf_devices.into_iter().take(NGSCOPE_MAX_NOF_RF_DEV);
+        let nof_rf_dev = rf_devices.len() as u16;
+        self.rf_config0 = rf_devices.next();
+        self.rf_config1 = rf_devices.next();
+        self.rf_config2 = rf_devices.next();
+        self.rf_config3 = rf_devices.next();
+        self.rf_config4 = rf_devices.next();
+        self.nof_rf_dev = nof_rf_dev;
+        self.dci_log_config
+            .get_or_insert_with(Default::default)
+            .nof_cell = nof_rf_dev;
+    }
+}
+
+/// Replace (or add) the `serial=` entry of comma separated UHD device args
+pub fn rf_args_with_serial(rf_args: &str, serial: &str) -> String {
+    let mut args: Vec<String> = rf_args
+        .split(',')
+        .map(|arg| arg.trim())
+        .filter(|arg| !arg.is_empty() && !arg.starts_with("serial="))
+        .map(|arg| arg.to_string())
+        .collect();
+    args.insert(0, format!("serial={}", serial));
+    args.join(",")
+}
+
 pub fn read_config(file_path: &str) -> Result<NgScopeConfig> {
-    serde_libconfig::from_file::<NgScopeConfig>(file_path)
+    libconfig::from_file::<NgScopeConfig>(file_path)
 }
 
 pub fn write_config(config: &NgScopeConfig, file_path: &str) -> Result<()> {
     serde_libconfig::to_file::<NgScopeConfig>(config, file_path)
 }
 
-// TODO: serialize and deserialize config:
-// https://github.com/JoNil/libconfig-rs/blob/master/src/lib.rs#L131
-// https://crates.io/crates/libconfig-rs
-
 #[cfg(test)]
 
 mod tests {
     use super::*;
 
-    #[allow(dead_code)]
     const COMPLEX_CONFIG_STR: &str = r#"nof_rf_dev = 1;
 // rnti=21095;
 // rnti=4885; // = 0x1315
@@ -152,7 +203,6 @@ dci_log_config = {
     log_ul  = true;
 }"#;
 
-    #[allow(dead_code)]
     const EASY_CONFIG_STR: &str = r#"nof_rf_dev = 1;
 rnti             = 19015;
 decode_SIB       = true;
@@ -179,7 +229,7 @@ dci_log_config = {
 rnti = 1;
 remote_enable = true;
 decode_single_ue = false;
-decode_sib = true;
+decode_SIB = true;
 dci_log_config = {
     nof_cell = 1;
     log_ul = false;
@@ -204,38 +254,106 @@ rf_config0 = {
         assert_eq!(config_str, DEFAULT_CONFIG_STR)
     }
 
-    // #[test]
-    // fn test_config_de() {
-    //     let config = serde_libconfig::from_string::<NgScopeConfig>(DUMMY_CONFIG_STR);
-    //     // HERE: Debug errors (implement serde_libconfig properly)
-    //     assert!(config.is_ok())
-    // }
-
-    // #[test]
-    // fn test_config_de_ignores_comments() {
-    //     let config = serde_libconfig::from_string::<NgScopeConfig>(DUMMY_CONFIG_STR).unwrap();
-    //     assert_eq!(config.nof_rf_dev, 1)
-    // }
-
-    // #[test]
-    // fn test_easy_config_de() {
-    //     let dummy_config = NgScopeConfig::default();
-    //     let config = serde_libconfig::from_string::<NgScopeConfig>(EASY_CONFIG_STR);
-    //     assert!(config.is_ok())
-    // }
-
-    // #[test]
-    // fn test_complex_config_de() {
-    //     let _dummy_config = NgScopeConfig::default();
-    //     let config = serde_libconfig::from_string::<NgScopeConfig>(COMPLEX_CONFIG_STR);
-    //     assert!(config.is_ok())
-    // }
-
-    // #[test]
-    // fn test_config_ser_de() {
-    //     let dummy_config = NgScopeConfig::default();
-    //     let config_str = serde_libconfig::to_string(&dummy_config);
-    //     let config = serde_libconfig::from_string::<NgScopeConfig>(&config_str.unwrap()).unwrap();
-    //     assert_eq!(config.nof_rf_dev, dummy_config.nof_rf_dev)
-    // }
+    #[test]
+    fn test_set_rf_devices() {
+        let mut config = NgScopeConfig::default();
+        let rf_devices: Vec<NgScopeConfigRfDev> = (0..7)
+            .map(|index| NgScopeConfigRfDev {
+                rf_freq: 1_000_000 * index,
+                ..Default::default()
+            })
+            .collect();
+        config.set_rf_devices(rf_devices);
+        assert_eq!(config.nof_rf_dev, 5);
+        assert_eq!(config.dci_log_config.as_ref().unwrap().nof_cell, 5);
+        assert_eq!(config.rf_config4.as_ref().unwrap().rf_freq, 4_000_000);
+        assert_eq!(config.rf_devices().len(), 5);
+
+        config.set_rf_devices(vec![NgScopeConfigRfDev::default()]);
+        assert_eq!(config.nof_rf_dev, 1);
+        assert_eq!(config.dci_log_config.as_ref().unwrap().nof_cell, 1);
+        assert!(config.rf_config1.is_none());
+        assert_eq!(config.rf_devices().len(), 1);
+    }
+
+    #[test]
+    fn test_rf_args_with_serial() {
+        assert_eq!(rf_args_with_serial("serial=3295B62", "ABC"), "serial=ABC");
+        assert_eq!(
+            rf_args_with_serial("type=b200, serial=3295B62,master_clock_rate=23.04e6", "ABC"),
+            "serial=ABC,type=b200,master_clock_rate=23.04e6"
+        );
+        assert_eq!(rf_args_with_serial("", "ABC"), "serial=ABC");
+    }
+
+    #[test]
+    fn test_config_de() {
+        let config = libconfig::from_str::<NgScopeConfig>(DEFAULT_CONFIG_STR);
+        assert!(config.is_ok());
+        assert_eq!(config.unwrap(), NgScopeConfig::default())
+    }
+
+    #[test]
+    fn test_config_de_ignores_comments() {
+        let config = libconfig::from_str::<NgScopeConfig>(COMPLEX_CONFIG_STR).unwrap();
+        assert_eq!(config.nof_rf_dev, 1);
+        assert_eq!(config.rnti, 19015);
+        assert_eq!(config.rf_config0.unwrap().rf_freq, 796000000)
+    }
+
+    #[test]
+    fn test_easy_config_de() {
+        let config = libconfig::from_str::<NgScopeConfig>(EASY_CONFIG_STR);
+        assert!(config.is_ok());
+        let config = config.unwrap();
+        assert_eq!(config.decode_sib, Some(true));
+        assert_eq!(config.decode_single_ue, Some(false));
+        let rf_config0 = config.rf_config0.unwrap();
+        assert_eq!(rf_config0.N_id_2, -1);
+        assert_eq!(rf_config0.rf_args, "serial=3295B62");
+        assert_eq!(rf_config0.log_dl, Some(true));
+        assert_eq!(rf_config0.log_phich, Some(false));
+    }
+
+    #[test]
+    fn test_complex_config_de() {
+        let config = libconfig::from_str::<NgScopeConfig>(COMPLEX_CONFIG_STR);
+        assert!(config.is_ok());
+        let config = config.unwrap();
+        assert_eq!(config.nof_rf_dev, 1);
+        assert_eq!(config.rnti, 19015);
+        assert_eq!(config.remote_enable, Some(true));
+        assert_eq!(config.decode_sib, Some(true));
+        assert_eq!(config.decode_single_ue, Some(false));
+        let rf_config0 = config.rf_config0.unwrap();
+        assert_eq!(rf_config0.rf_freq, 796000000);
+        assert_eq!(rf_config0.N_id_2, -1);
+        assert_eq!(rf_config0.rf_args, "serial=3295B62");
+        assert_eq!(rf_config0.nof_thread, 4);
+        assert_eq!(rf_config0.disable_plot, Some(true));
+        assert_eq!(rf_config0.log_dl, Some(true));
+        assert_eq!(rf_config0.log_ul, Some(true));
+        assert!(config.rf_config1.is_none());
+        let dci_log_config = config.dci_log_config.unwrap();
+        assert!(dci_log_config.log_dl);
+        assert!(dci_log_config.log_ul);
+    }
+
+    #[test]
+    fn test_config_ser_de() {
+        let mut dummy_config = NgScopeConfig::default();
+        dummy_config.set_rf_devices(vec![
+            NgScopeConfigRfDev::default(),
+            NgScopeConfigRfDev {
+                rf_freq: 2_680_000_000,
+                N_id_2: 2,
+                rf_args: "serial=ABC".to_string(),
+                ..Default::default()
+            },
+        ]);
+        let config_str = serde_libconfig::to_string(&dummy_config);
+        let config = libconfig::from_str::<NgScopeConfig>(&config_str.unwrap()).unwrap();
+        assert_eq!(config.nof_rf_dev, dummy_config.nof_rf_dev);
+        assert_eq!(config, dummy_config)
+    }
 }
@@ -0,0 +1,183 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::SyncSender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::logger::log_ngscope_event;
+
+/*
+ * Recognises the key lines of the ng-scope (srsRAN based) stdout/stderr output
+ * */
+
+lazy_static! {
+    /* srsran cell search: "Found CELL ID: 123. 50 PRB, 2 ports" */
+    static ref RE_CELL_FOUND: Regex =
+        Regex::new(r"(?i)found cell(?: id)?[:\s]+(\d+)\D+?(\d+)\s*prb").unwrap();
+    static ref RE_SYNC_LOST: Regex =
+        Regex::new(r"(?i)(lost sync|sync(?:hronization)? lost|out of sync)").unwrap();
+    static ref RE_PCI: Regex =
+        Regex::new(r"(?i)\b(?:pci|cell[_ ]id|n_id)\s*[:=]\s*(\d+)").unwrap();
+    static ref RE_PRB: Regex =
+        Regex::new(r"(?i)\b(?:nof_prb|n_prb|prb)\s*[:=]\s*(\d+)").unwrap();
+    static ref RE_USRP_ERROR: Regex = Regex::new(
+        r"(?i)(\[error\].*(uhd|usrp|\brf\b)|uhd.*error|error opening rf|no (uhd )?devices found|rf device not found)"
+    )
+    .unwrap();
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum NgScopeEvent {
+    CellFound { pci: u16, nof_prb: u16 },
+    SyncLost,
+    PciDetected { pci: u16 },
+    PrbDetected { nof_prb: u16 },
+    UsrpError { message: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NgScopeEventRecord {
+    pub timestamp_us: u64,
+    pub event: NgScopeEvent,
+    pub line: String,
+}
+
+pub fn parse_ngscope_line(line: &str) -> Option<NgScopeEvent> {
+    if let Some(captures) = RE_CELL_FOUND.captures(line) {
+        return Some(NgScopeEvent::CellFound {
+            pci: captures[1].parse().ok()?,
+            nof_prb: captures[2].parse().ok()?,
+        });
+    }
+    if RE_SYNC_LOST.is_match(line) {
+        return Some(NgScopeEvent::SyncLost);
+    }
+    if RE_USRP_ERROR.is_match(line) {
+        return Some(NgScopeEvent::UsrpError {
+            message: line.trim().to_string(),
+        });
+    }
+    if let Some(captures) = RE_PCI.captures(line) {
+        return Some(NgScopeEvent::PciDetected {
+            pci: captures[1].parse().ok()?,
+        });
+    }
+    if let Some(captures) = RE_PRB.captures(line) {
+        return Some(NgScopeEvent::PrbDetected {
+            nof_prb: captures[1].parse().ok()?,
+        });
+    }
+    None
+}
+
+/// Read the lines of an ng-scope pipe until it closes.
+///
+/// Every line is appended to `log_file` (if given), recognised events are
+/// logged and passed to `tx_event` without blocking the reader.
+pub fn deploy_ngscope_output_reader<R: Read + Send + 'static>(
+    pipe: R,
+    log_file: Option<Arc<Mutex<File>>>,
+    tx_event: SyncSender<NgScopeEvent>,
+    is_log_events: bool,
+) -> Result<JoinHandle<()>> {
+    let builder = thread::Builder::new().name("[ngscope.output]".to_string());
+    let thread = builder.spawn(move || {
+        for line in BufReader::new(pipe).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if let Some(ref log_file) = log_file {
+                let _ = writeln!(log_file.lock().unwrap(), "{}", line);
+            }
+            if let Some(event) = parse_ngscope_line(&line) {
+                if is_log_events {
+                    let _ = log_ngscope_event(NgScopeEventRecord {
+                        timestamp_us: chrono::Local::now().timestamp_micros() as u64,
+                        event: event.clone(),
+                        line,
+                    });
+                }
+                let _ = tx_event.try_send(event);
+            }
+        }
+    })?;
+    Ok(thread)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ngscope_line() {
+        assert_eq!(
+            parse_ngscope_line("Found CELL ID: 123. 50 PRB, 2 ports"),
+            Some(NgScopeEvent::CellFound {
+                pci: 123,
+                nof_prb: 50
+            })
+        );
+        assert_eq!(
+            parse_ngscope_line("Lost synchronization, going back to cell search"),
+            Some(NgScopeEvent::SyncLost)
+        );
+        assert_eq!(
+            parse_ngscope_line("SYNC:  Lost sync in subframe 3"),
+            Some(NgScopeEvent::SyncLost)
+        );
+        assert_eq!(
+            parse_ngscope_line("[ERROR] [UHD] Device discovery error"),
+            Some(NgScopeEvent::UsrpError {
+                message: "[ERROR] [UHD] Device discovery error".to_string()
+            })
+        );
+        assert_eq!(
+            parse_ngscope_line("[ERROR] RF gain could not be set"),
+            Some(NgScopeEvent::UsrpError {
+                message: "[ERROR] RF gain could not be set".to_string()
+            })
+        );
+        /* "rf" only as a word, not in "interface" or "perf" */
+        assert_eq!(parse_ngscope_line("[ERROR] could not bind interface"), None);
+        assert_eq!(
+            parse_ngscope_line("[ERROR] perf counters unavailable"),
+            None
+        );
+        assert_eq!(
+            parse_ngscope_line("Cell configured: PCI: 301, ports: 2"),
+            Some(NgScopeEvent::PciDetected { pci: 301 })
+        );
+        assert_eq!(
+            parse_ngscope_line("MIB decoded nof_prb=100 phich=normal"),
+            Some(NgScopeEvent::PrbDetected { nof_prb: 100 })
+        );
+        assert_eq!(parse_ngscope_line("Decoding PDCCH ..."), None);
+    }
+
+    #[test]
+    fn test_output_reader() -> Result<()> {
+        let (tx_event, rx_event) = std::sync::mpsc::sync_channel::<NgScopeEvent>(10);
+        let output: &[u8] = b"starting\nFound CELL ID: 7. 25 PRB, 1 ports\nout of sync\n";
+        let reader = deploy_ngscope_output_reader(output, None, tx_event, false)?;
+        reader.join().unwrap();
+
+        let events: Vec<NgScopeEvent> = rx_event.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                NgScopeEvent::CellFound {
+                    pci: 7,
+                    nof_prb: 25
+                },
+                NgScopeEvent::SyncLost
+            ]
+        );
+        Ok(())
+    }
+}
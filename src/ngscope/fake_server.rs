@@ -0,0 +1,393 @@
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::logger::read_arrow_ipc;
+use crate::ngscope::types::{
+    Message, MessageType, NgScopeCellConfig, NgScopeCellDci, NgScopeRntiDci, NGSCOPE_MAX_NOF_CELL,
+    NGSCOPE_MAX_NOF_RNTI, NGSCOPE_MESSAGE_TYPE_SIZE, NGSCOPE_REMOTE_BUFFER_SIZE,
+};
+use crate::parse::{FakeNgScopeArgs, FlattenedNgScopeArgs};
+use crate::util::{is_notifier, print_info};
+
+const FAKE_SUBFRAME_US: u64 = 1000;
+const FAKE_NOF_TTI: u64 = 10240;
+/* Recorded gaps above this are shortened, a wrap-around in loop mode counts as one subframe */
+const FAKE_MAX_GAP_US: u64 = 5_000_000;
+const FAKE_MAX_SLEEP_MS: u64 = 100;
+const FAKE_SYNTHETIC_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+const FAKE_SYNTHETIC_RNTI_POOL_FACTOR: usize = 4;
+const FAKE_SYNTHETIC_MIN_BITS_PER_PRB: u64 = 100;
+const FAKE_SYNTHETIC_MAX_BITS_PER_PRB: u64 = 700;
+/* C-RNTI range according to 36.321 */
+const FAKE_SYNTHETIC_RNTI_MIN: u64 = 0x003D;
+const FAKE_SYNTHETIC_RNTI_MAX: u64 = 0xFFF3;
+
+enum DciSource {
+    Recorded {
+        dcis: Vec<NgScopeCellDci>,
+        index: usize,
+        is_loop: bool,
+    },
+    Synthetic(SyntheticDciGenerator),
+}
+
+struct SyntheticDciGenerator {
+    state: u64,
+    time_stamp: u64,
+    nof_prb: u16,
+    nof_rnti: u8,
+    rnti_pool: Vec<u16>,
+}
+
+struct PendingDci {
+    cell_dci: NgScopeCellDci,
+    send_at: Instant,
+}
+
+/// Act as the NG-Scope remote interface on `ng_server_addr`.
+///
+/// Waits for the `Start` handshake, answers with a `Config` and then streams
+/// `CellDci` packets to the sender of the handshake, paced like the source.
+pub fn run_fake_ngscope(
+    ng_args: &FlattenedNgScopeArgs,
+    fake_args: &FakeNgScopeArgs,
+    sigint_notifier: &Arc<AtomicBool>,
+) -> Result<()> {
+    if fake_args.fake_speed <= 0.0 {
+        return Err(anyhow!("--fake-speed must be greater than 0"));
+    }
+    let mut source = DciSource::from_args(fake_args)?;
+    let cell_config = NgScopeCellConfig {
+        nof_cell: 1,
+        cell_prb: {
+            let mut cell_prb = [0u16; NGSCOPE_MAX_NOF_CELL];
+            cell_prb[0] = fake_args.fake_nof_prb;
+            cell_prb
+        },
+        rnti: 0,
+    };
+
+    let socket = UdpSocket::bind(&ng_args.ng_server_addr)?;
+    socket.set_nonblocking(true)?;
+    print_info(&format!(
+        "[fake-ngscope] listening on {} for the Start handshake",
+        ng_args.ng_server_addr
+    ));
+
+    let mut client_addr: Option<SocketAddr> = None;
+    let mut pending_option: Option<PendingDci> = None;
+    let mut last_source_time_stamp: Option<u64> = None;
+    let mut last_send_at = Instant::now();
+    let mut nof_sent: u64 = 0;
+
+    loop {
+        if is_notifier(sigint_notifier) {
+            break;
+        }
+
+        if let Some(addr) = check_start(&socket)? {
+            print_info(&format!(
+                "[fake-ngscope] Start from {}, sending Config",
+                addr
+            ));
+            socket.send_to(&Message::Config(cell_config.clone()).to_bytes(), addr)?;
+            client_addr = Some(addr);
+            last_send_at = Instant::now();
+            last_source_time_stamp = None;
+            pending_option = None;
+        }
+
+        let addr = match client_addr {
+            Some(addr) => addr,
+            None => {
+                thread::sleep(Duration::from_millis(FAKE_MAX_SLEEP_MS));
+                continue;
+            }
+        };
+
+        if pending_option.is_none() {
+            pending_option = match source.next_dci() {
+                Some(cell_dci) => {
+                    let delta_us = match last_source_time_stamp {
+                        Some(last) if cell_dci.time_stamp > last => {
+                            (cell_dci.time_stamp - last).min(FAKE_MAX_GAP_US)
+                        }
+                        _ => FAKE_SUBFRAME_US,
+                    };
+                    last_source_time_stamp = Some(cell_dci.time_stamp);
+                    let delay_us = (delta_us as f64 / fake_args.fake_speed) as u64;
+                    Some(PendingDci {
+                        cell_dci,
+                        send_at: last_send_at + Duration::from_micros(delay_us),
+                    })
+                }
+                None => {
+                    print_info(&format!(
+                        "[fake-ngscope] source exhausted after {} CellDci, sending Exit",
+                        nof_sent
+                    ));
+                    let _ = socket.send_to(&Message::Exit.to_bytes(), addr);
+                    break;
+                }
+            };
+        }
+
+        let pending = pending_option.as_ref().unwrap();
+        let now = Instant::now();
+        if now < pending.send_at {
+            let max_sleep = Duration::from_millis(FAKE_MAX_SLEEP_MS);
+            thread::sleep((pending.send_at - now).min(max_sleep));
+            continue;
+        }
+
+        let PendingDci {
+            mut cell_dci,
+            send_at,
+        } = pending_option.take().unwrap();
+        cell_dci.time_stamp = chrono::Local::now().timestamp_micros() as u64;
+        cell_dci.tti = ((cell_dci.time_stamp / FAKE_SUBFRAME_US) % FAKE_NOF_TTI) as u16;
+        /* keep the schedule to avoid drift, unless we fell behind noticeably */
+        last_send_at = if now.duration_since(send_at) > Duration::from_millis(FAKE_MAX_SLEEP_MS) {
+            now
+        } else {
+            send_at
+        };
+        match socket.send_to(&Message::CellDci(Box::new(cell_dci)).to_bytes(), addr) {
+            Ok(_) => nof_sent += 1,
+            Err(err) => {
+                print_info(&format!(
+                    "[fake-ngscope] could not send to {}, waiting for a new Start: {:?}",
+                    addr, err
+                ));
+                client_addr = None;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_start(socket: &UdpSocket) -> Result<Option<SocketAddr>> {
+    let mut buf = [0u8; NGSCOPE_REMOTE_BUFFER_SIZE];
+    let mut start_addr: Option<SocketAddr> = None;
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((nof_recv, addr)) => {
+                if nof_recv >= NGSCOPE_MESSAGE_TYPE_SIZE
+                    && buf[..NGSCOPE_MESSAGE_TYPE_SIZE] == MessageType::Start.to_bytes()
+                {
+                    start_addr = Some(addr);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(start_addr),
+            /* ICMP port unreachable of a previous client shows up here */
+            Err(err) if err.kind() == ErrorKind::ConnectionRefused => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+impl DciSource {
+    fn from_args(fake_args: &FakeNgScopeArgs) -> Result<DciSource> {
+        match &fake_args.fake_dci_source {
+            Some(path) => {
+                let dcis = load_recorded_dci(Path::new(path))?;
+                print_info(&format!(
+                    "[fake-ngscope] loaded {} CellDci from {}",
+                    dcis.len(),
+                    path
+                ));
+                Ok(DciSource::Recorded {
+                    dcis,
+                    index: 0,
+                    is_loop: fake_args.fake_loop,
+                })
+            }
+            None => {
+                if fake_args.fake_nof_rnti as usize > NGSCOPE_MAX_NOF_RNTI {
+                    return Err(anyhow!(
+                        "--fake-nof-rnti must not exceed {}",
+                        NGSCOPE_MAX_NOF_RNTI
+                    ));
+                }
+                Ok(DciSource::Synthetic(SyntheticDciGenerator::new(
+                    FAKE_SYNTHETIC_SEED,
+                    fake_args.fake_nof_prb,
+                    fake_args.fake_nof_rnti,
+                )))
+            }
+        }
+    }
+
+    fn next_dci(&mut self) -> Option<NgScopeCellDci> {
+        match self {
+            DciSource::Recorded {
+                dcis,
+                index,
+                is_loop,
+            } => {
+                if *index >= dcis.len() {
+                    if !*is_loop {
+                        return None;
+                    }
+                    *index = 0;
+                }
+                *index += 1;
+                Some(dcis[*index - 1])
+            }
+            DciSource::Synthetic(generator) => Some(generator.next_dci()),
+        }
+    }
+}
+
+fn load_recorded_dci(path: &Path) -> Result<Vec<NgScopeCellDci>> {
+    let file_paths: Vec<PathBuf> = if path.is_dir() {
+        let mut file_paths: Vec<PathBuf> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file_path| file_path.extension().is_some_and(|ext| ext == "arrow"))
+            .collect();
+        file_paths.sort();
+        file_paths
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut dcis: Vec<NgScopeCellDci> = vec![];
+    for file_path in file_paths.iter() {
+        dcis.extend(read_arrow_ipc(File::open(file_path)?)?);
+    }
+    if dcis.is_empty() {
+        return Err(anyhow!("No DCI found in {}", path.display()));
+    }
+    dcis.sort_by_key(|cell_dci| cell_dci.time_stamp);
+    Ok(dcis)
+}
+
+impl SyntheticDciGenerator {
+    fn new(seed: u64, nof_prb: u16, nof_rnti: u8) -> SyntheticDciGenerator {
+        let mut generator = SyntheticDciGenerator {
+            state: seed.max(1),
+            time_stamp: 0,
+            nof_prb,
+            nof_rnti,
+            rnti_pool: vec![],
+        };
+        let pool_size = (nof_rnti as usize * FAKE_SYNTHETIC_RNTI_POOL_FACTOR).max(1);
+        let rnti_range = FAKE_SYNTHETIC_RNTI_MAX - FAKE_SYNTHETIC_RNTI_MIN;
+        generator.rnti_pool = (0..pool_size)
+            .map(|_| (FAKE_SYNTHETIC_RNTI_MIN + generator.next_random() % rnti_range) as u16)
+            .collect();
+        generator
+    }
+
+    /* xorshift64, good enough for traffic that only needs to look plausible */
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn next_dci(&mut self) -> NgScopeCellDci {
+        self.time_stamp += FAKE_SUBFRAME_US;
+        let mut cell_dci = NgScopeCellDci {
+            time_stamp: self.time_stamp,
+            ..Default::default()
+        };
+        if self.nof_rnti == 0 {
+            return cell_dci;
+        }
+
+        let prb_share = (self.nof_prb / self.nof_rnti as u16).max(1) as u64;
+        let pool_offset = self.next_random() as usize;
+        for index in 0..self.nof_rnti as usize {
+            let rnti = self.rnti_pool[(pool_offset + index) % self.rnti_pool.len()];
+            let dl_prb = 1 + self.next_random() % prb_share;
+            let ul_prb = self.next_random() % (prb_share / 2 + 1);
+            let dl_tbs_bit = dl_prb * self.next_bits_per_prb();
+            let ul_tbs_bit = ul_prb * self.next_bits_per_prb();
+
+            cell_dci.rnti_list[index] = NgScopeRntiDci {
+                rnti,
+                dl_tbs_bit: dl_tbs_bit as u32,
+                dl_prb: dl_prb as u8,
+                dl_no_tbs_prb: 0,
+                ul_tbs_bit: ul_tbs_bit as u32,
+                ul_prb: ul_prb as u8,
+                ul_no_tbs_prb: 0,
+            };
+            cell_dci.total_dl_tbs_bit += dl_tbs_bit;
+            cell_dci.total_dl_prb += dl_prb as u16;
+            cell_dci.total_ul_tbs_bit += ul_tbs_bit;
+            cell_dci.total_ul_prb += ul_prb as u16;
+            cell_dci.nof_rnti += 1;
+        }
+        cell_dci
+    }
+
+    fn next_bits_per_prb(&mut self) -> u64 {
+        FAKE_SYNTHETIC_MIN_BITS_PER_PRB
+            + self.next_random()
+                % (FAKE_SYNTHETIC_MAX_BITS_PER_PRB - FAKE_SYNTHETIC_MIN_BITS_PER_PRB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synthetic_dci() {
+        let mut generator = SyntheticDciGenerator::new(FAKE_SYNTHETIC_SEED, 50, 5);
+        let first = generator.next_dci();
+        let second = generator.next_dci();
+
+        assert_eq!(second.time_stamp - first.time_stamp, FAKE_SUBFRAME_US);
+        assert_eq!(first.nof_rnti, 5);
+        assert!(first.total_dl_prb <= 50);
+        let rnti_sum: u64 = first.rnti_list[..5]
+            .iter()
+            .map(|rnti_dci| rnti_dci.dl_tbs_bit as u64)
+            .sum();
+        assert_eq!(rnti_sum, first.total_dl_tbs_bit);
+        assert!(first.rnti_list[..5]
+            .iter()
+            .all(|rnti_dci| generator.rnti_pool.contains(&rnti_dci.rnti)));
+    }
+
+    #[test]
+    fn test_recorded_source_loop() {
+        let dcis: Vec<NgScopeCellDci> = (1..=2)
+            .map(|index| NgScopeCellDci {
+                time_stamp: index * FAKE_SUBFRAME_US,
+                ..Default::default()
+            })
+            .collect();
+        let mut source = DciSource::Recorded {
+            dcis: dcis.clone(),
+            index: 0,
+            is_loop: true,
+        };
+        let time_stamps: Vec<u64> = (0..3)
+            .map(|_| source.next_dci().unwrap().time_stamp)
+            .collect();
+        assert_eq!(time_stamps, vec![1000, 2000, 1000]);
+
+        let mut source = DciSource::Recorded {
+            dcis,
+            index: 0,
+            is_loop: false,
+        };
+        assert!(source.next_dci().is_some());
+        assert!(source.next_dci().is_some());
+        assert!(source.next_dci().is_none());
+    }
+}
@@ -0,0 +1,281 @@
+use std::fs;
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Number, Value};
+
+/*
+ * Parser for the libconfig format used by ng-scope .cfg files
+ *
+ * https://hyperrealm.github.io/libconfig/libconfig_manual.html#Configuration-Files
+ *
+ * The configuration is parsed into a serde_json::Value and deserialized from there:
+ *   - groups `{ ... }` become objects
+ *   - arrays `[ ... ]` and lists `( ... )` become arrays
+ *   - integers (decimal, hex, optional `L`/`LL` suffix), floats, booleans and strings
+ *   - comments: `#`, `//` and `/* */`
+ * */
+
+pub fn from_str<T: DeserializeOwned>(input: &str) -> Result<T> {
+    let value = parse(input)?;
+    serde_json::from_value::<T>(value).context("Error deserializing libconfig")
+}
+
+pub fn from_file<T: DeserializeOwned>(file_path: &str) -> Result<T> {
+    let input = fs::read_to_string(file_path)
+        .with_context(|| format!("Error reading libconfig file {}", file_path))?;
+    from_str::<T>(&input).with_context(|| format!("Error in libconfig file {}", file_path))
+}
+
+pub fn parse(input: &str) -> Result<Value> {
+    let mut parser = Parser { input, position: 0 };
+    let settings = parser.parse_settings(None)?;
+    parser.skip_whitespace_and_comments()?;
+    if parser.position < input.len() {
+        return Err(parser.error("unexpected character"));
+    }
+    Ok(Value::Object(settings))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        let line = self.input[..self.position].matches('\n').count() + 1;
+        let found: String = self.rest().chars().take(10).collect();
+        anyhow!("libconfig: {} in line {} at '{}'", message, line, found)
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<()> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+
+            if trimmed.starts_with('#') || trimmed.starts_with("//") {
+                self.position += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                match trimmed.find("*/") {
+                    Some(end) => self.position += end + 2,
+                    None => return Err(self.error("unterminated comment")),
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Parse settings until the closing character of a group or the end of input
+    fn parse_settings(&mut self, closing: Option<char>) -> Result<Map<String, Value>> {
+        let mut settings = Map::new();
+        loop {
+            self.skip_whitespace_and_comments()?;
+            match self.peek() {
+                None if closing.is_none() => return Ok(settings),
+                None => return Err(self.error("unexpected end of input")),
+                Some(next) if Some(next) == closing => return Ok(settings),
+                Some(_) => {}
+            }
+
+            let name = self.parse_name()?;
+            self.skip_whitespace_and_comments()?;
+            match self.peek() {
+                Some('=') | Some(':') => self.position += 1,
+                _ => return Err(self.error("expected '=' or ':' after setting name")),
+            }
+            let value = self.parse_value()?;
+            self.skip_whitespace_and_comments()?;
+            if let Some(';') | Some(',') = self.peek() {
+                self.position += 1;
+            }
+            settings.insert(name, value);
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String> {
+        let rest = self.rest();
+        let length = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '*'))
+            .unwrap_or(rest.len());
+        if length == 0 || !rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '*') {
+            return Err(self.error("invalid setting name"));
+        }
+        self.position += length;
+        Ok(rest[..length].to_string())
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        self.skip_whitespace_and_comments()?;
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                let settings = self.parse_settings(Some('}'))?;
+                self.position += 1;
+                Ok(Value::Object(settings))
+            }
+            Some('[') => self.parse_sequence(']'),
+            Some('(') => self.parse_sequence(')'),
+            Some('"') => self.parse_string(),
+            Some(_) => self.parse_scalar(),
+            None => Err(self.error("expected value")),
+        }
+    }
+
+    fn parse_sequence(&mut self, closing: char) -> Result<Value> {
+        self.position += 1;
+        let mut values: Vec<Value> = vec![];
+        loop {
+            self.skip_whitespace_and_comments()?;
+            if self.peek() == Some(closing) {
+                self.position += 1;
+                return Ok(Value::Array(values));
+            }
+            values.push(self.parse_value()?);
+            self.skip_whitespace_and_comments()?;
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(next) if next == closing => {}
+                _ => return Err(self.error("expected ',' or end of list")),
+            }
+        }
+    }
+
+    /// Adjacent string literals are concatenated, as in libconfig
+    fn parse_string(&mut self) -> Result<Value> {
+        let mut string = String::new();
+        while self.peek() == Some('"') {
+            self.position += 1;
+            let mut chars = self.rest().char_indices();
+            loop {
+                match chars.next() {
+                    Some((index, '"')) => {
+                        self.position += index + 1;
+                        break;
+                    }
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => string.push('\n'),
+                        Some((_, 't')) => string.push('\t'),
+                        Some((_, 'r')) => string.push('\r'),
+                        Some((_, 'f')) => string.push('\x0c'),
+                        Some((_, escaped)) => string.push(escaped),
+                        None => return Err(self.error("unterminated string")),
+                    },
+                    Some((_, c)) => string.push(c),
+                    None => return Err(self.error("unterminated string")),
+                }
+            }
+            self.skip_whitespace_and_comments()?;
+        }
+        Ok(Value::String(string))
+    }
+
+    fn parse_scalar(&mut self) -> Result<Value> {
+        let rest = self.rest();
+        let length = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '+' || c == '-'))
+            .unwrap_or(rest.len());
+        let token = &rest[..length];
+        let value = parse_scalar_token(token).ok_or_else(|| self.error("invalid value"))?;
+        self.position += length;
+        Ok(value)
+    }
+}
+
+fn parse_scalar_token(token: &str) -> Option<Value> {
+    if token.eq_ignore_ascii_case("true") {
+        return Some(Value::Bool(true));
+    }
+    if token.eq_ignore_ascii_case("false") {
+        return Some(Value::Bool(false));
+    }
+
+    let integer_token = token
+        .strip_suffix("LL")
+        .or_else(|| token.strip_suffix('L'))
+        .unwrap_or(token);
+    let (is_negative, unsigned_token) = match integer_token.strip_prefix('-') {
+        Some(unsigned_token) => (true, unsigned_token),
+        None => (false, integer_token.strip_prefix('+').unwrap_or(integer_token)),
+    };
+    let integer = match unsigned_token
+        .strip_prefix("0x")
+        .or_else(|| unsigned_token.strip_prefix("0X"))
+    {
+        Some(hex_token) => i64::from_str_radix(hex_token, 16).ok(),
+        None => unsigned_token.parse::<i64>().ok(),
+    };
+    if let Some(integer) = integer {
+        let signed = if is_negative { -integer } else { integer };
+        return Some(Value::Number(Number::from(signed)));
+    }
+
+    token
+        .parse::<f64>()
+        .ok()
+        .filter(|float| float.is_finite())
+        .and_then(Number::from_f64)
+        .map(Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_scalars() -> Result<()> {
+        let value = parse(
+            r#"a = 1; b = -2L; c = 0x1F; d = 1.5e3; e = TRUE; f = false; g = "x" "y";
+            h = 796000000LL"#,
+        )?;
+        assert_eq!(
+            value,
+            json!({"a": 1, "b": -2, "c": 31, "d": 1500.0, "e": true, "f": false, "g": "xy", "h": 796000000})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_groups_lists_comments() -> Result<()> {
+        let value = parse(
+            r#"# hash comment
+            group: {
+                // line comment
+                inner = "value with // no comment"; /* block
+                comment */
+                nested = { x = 1; };
+            };
+            array = [1, 2, 3];
+            list = ( "a", { y = 2; } );"#,
+        )?;
+        assert_eq!(
+            value,
+            json!({
+                "group": {"inner": "value with // no comment", "nested": {"x": 1}},
+                "array": [1, 2, 3],
+                "list": ["a", {"y": 2}],
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("a = ;").is_err());
+        assert!(parse("a = 1; b = { c = 2;").is_err());
+        assert!(parse("a = \"unterminated").is_err());
+        assert!(parse("a = 1; }").is_err());
+        assert!(parse("/* unterminated").is_err());
+    }
+}
@@ -1,12 +1,17 @@
 use anyhow::{anyhow, Result};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
 
 use std::net::UdpSocket;
-use std::process::{Child, Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[allow(dead_code)]
 pub mod config;
+pub mod events;
+pub mod fake_server;
+pub mod libconfig;
 #[allow(dead_code)]
 pub mod types;
 
@@ -16,6 +21,9 @@ use types::{Message, MessageType};
 use crate::util::print_info;
 
 const TMP_NGSCOPE_CONFIG_PATH: &str = "./.tmp_ngscope_conf.cfg";
+/* Time ng-scope gets to exit after SIGTERM before it is killed */
+const NGSCOPE_STOP_TIMEOUT_MS: u64 = 3000;
+const NGSCOPE_STOP_POLL_MS: u64 = 10;
 
 pub fn start_ngscope<T: Into<Stdio>>(
     exec_path: &str,
@@ -33,9 +41,27 @@ pub fn start_ngscope<T: Into<Stdio>>(
     Ok(child)
 }
 
-pub fn stop_ngscope(child: &mut Child) -> Result<()> {
+/// Stop ng-scope with SIGTERM and fall back to SIGKILL if it does not exit in time
+pub fn stop_ngscope(child: &mut Child) -> Result<ExitStatus> {
+    if let Some(exit_status) = child.try_wait()? {
+        return Ok(exit_status);
+    }
+    signal::kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM)?;
+
+    let deadline = Instant::now() + Duration::from_millis(NGSCOPE_STOP_TIMEOUT_MS);
+    while Instant::now() < deadline {
+        if let Some(exit_status) = child.try_wait()? {
+            return Ok(exit_status);
+        }
+        thread::sleep(Duration::from_millis(NGSCOPE_STOP_POLL_MS));
+    }
+
+    print_info(&format!(
+        "[ngscope] process {} did not exit after SIGTERM, sending SIGKILL",
+        child.id()
+    ));
     child.kill()?;
-    Ok(())
+    Ok(child.wait()?)
 }
 
 #[allow(dead_code)]
@@ -64,7 +90,7 @@ pub fn ngscope_recv_single_message_type(socket: &UdpSocket) -> Result<(MessageTy
 pub fn ngscope_recv_single_message(socket: &UdpSocket) -> Result<Message> {
     let mut buf = [0u8; types::NGSCOPE_REMOTE_BUFFER_SIZE];
     let (nof_recv, _) = socket.recv_from(&mut buf)?;
-    Message::from_bytes(&buf[..nof_recv])
+    Ok(Message::from_bytes(&buf[..nof_recv])?)
 }
 
 #[allow(dead_code)]
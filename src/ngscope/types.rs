@@ -1,3 +1,5 @@
+use std::fmt;
+
 use anyhow::{anyhow, Context, Result};
 
 pub const NOF_VALIDATE_RETRIES: usize = 50;
@@ -15,6 +17,46 @@ pub const NGSCOPE_MESSAGE_CONTENT_POSITION: usize = 5;
 pub const NGSCOPE_STRUCT_SIZE_DCI: usize = 40;
 pub const NGSCOPE_STRUCT_SIZE_CELL_DCI: usize = 856;
 pub const NGSCOPE_STRUCT_SIZE_CONFIG: usize = 12;
+pub const NGSCOPE_STRUCT_SIZE_RNTI_DCI: usize = 20;
+
+// Protocol version byte as sent by ng-scope in front of Dci and CellDci content
+pub const NGSCOPE_MESSAGE_VERSION_V0: u8 = 0x00;
+
+// Byte offsets of the little-endian, C-aligned structs in dci_sink_def.h
+const UE_DCI_OFFSET_CELL_IDX: usize = 0;
+const UE_DCI_OFFSET_TIME_STAMP: usize = 8;
+const UE_DCI_OFFSET_TTI: usize = 16;
+const UE_DCI_OFFSET_RNTI: usize = 18;
+const UE_DCI_OFFSET_DL_TBS: usize = 20;
+const UE_DCI_OFFSET_DL_RE_TX: usize = 24;
+const UE_DCI_OFFSET_DL_RV_FLAG: usize = 25;
+const UE_DCI_OFFSET_UL_TBS: usize = 28;
+const UE_DCI_OFFSET_UL_RE_TX: usize = 32;
+const UE_DCI_OFFSET_UL_RV_FLAG: usize = 33;
+
+const RNTI_DCI_OFFSET_RNTI: usize = 0;
+const RNTI_DCI_OFFSET_DL_TBS_BIT: usize = 4;
+const RNTI_DCI_OFFSET_DL_PRB: usize = 8;
+const RNTI_DCI_OFFSET_DL_NO_TBS_PRB: usize = 9;
+const RNTI_DCI_OFFSET_UL_TBS_BIT: usize = 12;
+const RNTI_DCI_OFFSET_UL_PRB: usize = 16;
+const RNTI_DCI_OFFSET_UL_NO_TBS_PRB: usize = 17;
+
+const CELL_DCI_OFFSET_CELL_ID: usize = 0;
+const CELL_DCI_OFFSET_TIME_STAMP: usize = 8;
+const CELL_DCI_OFFSET_TTI: usize = 16;
+const CELL_DCI_OFFSET_TOTAL_DL_TBS_BIT: usize = 24;
+const CELL_DCI_OFFSET_TOTAL_DL_PRB: usize = 32;
+const CELL_DCI_OFFSET_TOTAL_DL_NO_TBS_PRB: usize = 34;
+const CELL_DCI_OFFSET_TOTAL_UL_TBS_BIT: usize = 40;
+const CELL_DCI_OFFSET_TOTAL_UL_PRB: usize = 48;
+const CELL_DCI_OFFSET_TOTAL_UL_NO_TBS_PRB: usize = 50;
+const CELL_DCI_OFFSET_NOF_RNTI: usize = 52;
+const CELL_DCI_OFFSET_RNTI_LIST: usize = 56;
+
+const CONFIG_OFFSET_NOF_CELL: usize = 0;
+const CONFIG_OFFSET_CELL_PRB: usize = 2;
+const CONFIG_OFFSET_RNTI: usize = 10;
 
 // IMPORTANT:
 // - when receiving messages, check the timestamp - due to UDP, messages might arrive out of order
@@ -29,7 +71,12 @@ pub enum MessageType {
     Exit,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageVersion {
+    V0,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Message {
     Start,
     Dci(NgScopeUeDci),
@@ -38,46 +85,165 @@ pub enum Message {
     Exit,
 }
 
-fn check_size(size: usize, bound: usize) -> Result<()> {
+#[derive(Clone, Debug, PartialEq)]
+pub enum NgScopeDecodeError {
+    TooFewBytes {
+        expected: usize,
+        actual: usize,
+    },
+    UnknownMessageType([u8; NGSCOPE_MESSAGE_TYPE_SIZE]),
+    UnknownVersion {
+        message_type: MessageType,
+        version: u8,
+    },
+    TooManyRnti {
+        nof_rnti: u8,
+    },
+}
+
+impl fmt::Display for NgScopeDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NgScopeDecodeError::TooFewBytes { expected, actual } => write!(
+                f,
+                "Error converting bytes to NgScope message: bytes must be at least {}, got {}",
+                expected, actual
+            ),
+            NgScopeDecodeError::UnknownMessageType(preamble) => write!(
+                f,
+                "Error converting bytes to NgScope message: unknown message type {:02X?}",
+                preamble
+            ),
+            NgScopeDecodeError::UnknownVersion {
+                message_type,
+                version,
+            } => write!(
+                f,
+                "Error converting bytes to NgScope message: unknown protocol version {} for {:?}",
+                version, message_type
+            ),
+            NgScopeDecodeError::TooManyRnti { nof_rnti } => write!(
+                f,
+                "Error converting bytes to NgScope message: nof_rnti {} exceeds {}",
+                nof_rnti, NGSCOPE_MAX_NOF_RNTI
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NgScopeDecodeError {}
+
+fn check_size(size: usize, bound: usize) -> Result<(), NgScopeDecodeError> {
     if size < bound {
-        return Err(anyhow!(
-            "Error converting bytes to NgScope message: bytes must be at least {}",
-            bound
-        ));
+        return Err(NgScopeDecodeError::TooFewBytes {
+            expected: bound,
+            actual: size,
+        });
     }
     Ok(())
 }
 
+fn read_u16_le(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+}
+
+fn read_u32_le(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+}
+
+fn read_u64_le(bytes: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap())
+}
+
+fn write_u16_le(bytes: &mut [u8], pos: usize, value: u16) {
+    bytes[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32_le(bytes: &mut [u8], pos: usize, value: u32) {
+    bytes[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64_le(bytes: &mut [u8], pos: usize, value: u64) {
+    bytes[pos..pos + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+impl MessageVersion {
+    pub fn from_byte(
+        message_type: MessageType,
+        version: u8,
+    ) -> Result<MessageVersion, NgScopeDecodeError> {
+        match version {
+            NGSCOPE_MESSAGE_VERSION_V0 => Ok(MessageVersion::V0),
+            _ => Err(NgScopeDecodeError::UnknownVersion {
+                message_type,
+                version,
+            }),
+        }
+    }
+}
+
 impl Message {
-    pub fn from_bytes(bytes: &[u8]) -> Result<Message> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Message, NgScopeDecodeError> {
         check_size(bytes.len(), NGSCOPE_MESSAGE_TYPE_SIZE)?;
         let msg_type_bytes: [u8; NGSCOPE_MESSAGE_TYPE_SIZE] =
             bytes[..NGSCOPE_MESSAGE_TYPE_SIZE].try_into().unwrap();
-        let msg: Message = match MessageType::from_bytes(&msg_type_bytes).unwrap() {
+        let msg_type = MessageType::from_bytes(&msg_type_bytes)
+            .ok_or(NgScopeDecodeError::UnknownMessageType(msg_type_bytes))?;
+        let msg: Message = match msg_type {
             MessageType::Start => Message::Start,
             MessageType::Dci => {
-                check_size(bytes.len(), NGSCOPE_MESSAGE_VERSION_POSITION + 1)?;
-                let _version_byte: u8 = bytes[NGSCOPE_MESSAGE_VERSION_POSITION];
-                let content_bytes: &[u8] = &bytes[NGSCOPE_MESSAGE_CONTENT_POSITION..];
-                Message::Dci(NgScopeUeDci::from_bytes(content_bytes.try_into()?)?)
+                let (version, content_bytes) = split_version(bytes, msg_type)?;
+                Message::Dci(NgScopeUeDci::from_bytes(version, content_bytes)?)
             }
             MessageType::CellDci => {
-                check_size(bytes.len(), NGSCOPE_MESSAGE_VERSION_POSITION + 1)?;
-                let _version_byte: u8 = bytes[NGSCOPE_MESSAGE_VERSION_POSITION];
-                let content_bytes: &[u8] = &bytes[NGSCOPE_MESSAGE_CONTENT_POSITION..];
+                let (version, content_bytes) = split_version(bytes, msg_type)?;
                 Message::CellDci(Box::new(NgScopeCellDci::from_bytes(
-                    content_bytes.try_into()?,
+                    version,
+                    content_bytes,
                 )?))
             }
             MessageType::Config => {
+                /* Config carries no version byte, content starts right after the type */
                 let content_bytes: &[u8] = &bytes[NGSCOPE_MESSAGE_VERSION_POSITION..];
-                check_size(content_bytes.len(), NGSCOPE_STRUCT_SIZE_CONFIG)?;
-                Message::Config(NgScopeCellConfig::from_bytes(content_bytes.try_into()?)?)
+                Message::Config(NgScopeCellConfig::from_bytes(content_bytes)?)
             }
             MessageType::Exit => Message::Exit,
         };
         Ok(msg)
     }
+
+    /// Encode the message as ng-scope would send it (protocol version 0)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(NGSCOPE_REMOTE_BUFFER_SIZE);
+        match self {
+            Message::Start => bytes.extend_from_slice(&MessageType::Start.to_bytes()),
+            Message::Dci(ue_dci) => {
+                bytes.extend_from_slice(&MessageType::Dci.to_bytes());
+                bytes.push(NGSCOPE_MESSAGE_VERSION_V0);
+                bytes.extend_from_slice(&ue_dci.to_bytes_v0());
+            }
+            Message::CellDci(cell_dci) => {
+                bytes.extend_from_slice(&MessageType::CellDci.to_bytes());
+                bytes.push(NGSCOPE_MESSAGE_VERSION_V0);
+                bytes.extend_from_slice(&cell_dci.to_bytes_v0());
+            }
+            Message::Config(cell_config) => {
+                bytes.extend_from_slice(&MessageType::Config.to_bytes());
+                bytes.extend_from_slice(&cell_config.to_bytes());
+            }
+            Message::Exit => bytes.extend_from_slice(&MessageType::Exit.to_bytes()),
+        }
+        bytes
+    }
+}
+
+fn split_version(
+    bytes: &[u8],
+    message_type: MessageType,
+) -> Result<(MessageVersion, &[u8]), NgScopeDecodeError> {
+    check_size(bytes.len(), NGSCOPE_MESSAGE_CONTENT_POSITION)?;
+    let version = MessageVersion::from_byte(message_type, bytes[NGSCOPE_MESSAGE_VERSION_POSITION])?;
+    Ok((version, &bytes[NGSCOPE_MESSAGE_CONTENT_POSITION..]))
 }
 
 impl MessageType {
@@ -105,7 +271,7 @@ impl MessageType {
 
 // taken from: ngscope/hdr/dciLib/dci_sink_def.h
 #[repr(C)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct NgScopeUeDci {
     pub cell_idx: u8,
     pub time_stamp: u64,
@@ -122,14 +288,59 @@ pub struct NgScopeUeDci {
 }
 
 impl NgScopeUeDci {
-    pub fn from_bytes(bytes: [u8; NGSCOPE_STRUCT_SIZE_DCI]) -> Result<NgScopeUeDci> {
-        let ue_dci: &NgScopeUeDci = unsafe { &*bytes.as_ptr().cast() };
-        Ok(ue_dci.clone())
+    pub fn from_bytes(
+        version: MessageVersion,
+        bytes: &[u8],
+    ) -> Result<NgScopeUeDci, NgScopeDecodeError> {
+        match version {
+            MessageVersion::V0 => NgScopeUeDci::from_bytes_v0(bytes),
+        }
+    }
+
+    fn from_bytes_v0(bytes: &[u8]) -> Result<NgScopeUeDci, NgScopeDecodeError> {
+        check_size(bytes.len(), NGSCOPE_STRUCT_SIZE_DCI)?;
+        Ok(NgScopeUeDci {
+            cell_idx: bytes[UE_DCI_OFFSET_CELL_IDX],
+            time_stamp: read_u64_le(bytes, UE_DCI_OFFSET_TIME_STAMP),
+            tti: read_u16_le(bytes, UE_DCI_OFFSET_TTI),
+            rnti: read_u16_le(bytes, UE_DCI_OFFSET_RNTI),
+            dl_tbs: read_u32_le(bytes, UE_DCI_OFFSET_DL_TBS),
+            dl_re_tx: bytes[UE_DCI_OFFSET_DL_RE_TX],
+            dl_rv_flag: bytes[UE_DCI_OFFSET_DL_RV_FLAG] != 0,
+            ul_tbs: read_u32_le(bytes, UE_DCI_OFFSET_UL_TBS),
+            ul_re_tx: bytes[UE_DCI_OFFSET_UL_RE_TX],
+            ul_rv_flag: bytes[UE_DCI_OFFSET_UL_RV_FLAG] != 0,
+        })
+    }
+
+    fn to_bytes_v0(self) -> [u8; NGSCOPE_STRUCT_SIZE_DCI] {
+        let mut bytes = [0u8; NGSCOPE_STRUCT_SIZE_DCI];
+        bytes[UE_DCI_OFFSET_CELL_IDX] = self.cell_idx;
+        write_u64_le(&mut bytes, UE_DCI_OFFSET_TIME_STAMP, self.time_stamp);
+        write_u16_le(&mut bytes, UE_DCI_OFFSET_TTI, self.tti);
+        write_u16_le(&mut bytes, UE_DCI_OFFSET_RNTI, self.rnti);
+        write_u32_le(&mut bytes, UE_DCI_OFFSET_DL_TBS, self.dl_tbs);
+        bytes[UE_DCI_OFFSET_DL_RE_TX] = self.dl_re_tx;
+        bytes[UE_DCI_OFFSET_DL_RV_FLAG] = self.dl_rv_flag as u8;
+        write_u32_le(&mut bytes, UE_DCI_OFFSET_UL_TBS, self.ul_tbs);
+        bytes[UE_DCI_OFFSET_UL_RE_TX] = self.ul_re_tx;
+        bytes[UE_DCI_OFFSET_UL_RV_FLAG] = self.ul_rv_flag as u8;
+        bytes
+    }
+
+    /// The DL transport block is a HARQ re-transmission (re-tx counter or redundancy version set)
+    pub fn is_dl_re_tx(&self) -> bool {
+        self.dl_re_tx > 0 || self.dl_rv_flag
+    }
+
+    /// The UL transport block is a HARQ re-transmission (re-tx counter or redundancy version set)
+    pub fn is_ul_re_tx(&self) -> bool {
+        self.ul_re_tx > 0 || self.ul_rv_flag
     }
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
 #[allow(non_snake_case)]
 pub struct NgScopeRntiDci {
     pub rnti: u16,
@@ -142,8 +353,33 @@ pub struct NgScopeRntiDci {
     pub ul_no_tbs_prb: u8,
 }
 
+impl NgScopeRntiDci {
+    fn from_bytes_v0(bytes: &[u8]) -> Result<NgScopeRntiDci, NgScopeDecodeError> {
+        check_size(bytes.len(), NGSCOPE_STRUCT_SIZE_RNTI_DCI)?;
+        Ok(NgScopeRntiDci {
+            rnti: read_u16_le(bytes, RNTI_DCI_OFFSET_RNTI),
+            dl_tbs_bit: read_u32_le(bytes, RNTI_DCI_OFFSET_DL_TBS_BIT),
+            dl_prb: bytes[RNTI_DCI_OFFSET_DL_PRB],
+            dl_no_tbs_prb: bytes[RNTI_DCI_OFFSET_DL_NO_TBS_PRB],
+            ul_tbs_bit: read_u32_le(bytes, RNTI_DCI_OFFSET_UL_TBS_BIT),
+            ul_prb: bytes[RNTI_DCI_OFFSET_UL_PRB],
+            ul_no_tbs_prb: bytes[RNTI_DCI_OFFSET_UL_NO_TBS_PRB],
+        })
+    }
+
+    fn write_bytes_v0(&self, bytes: &mut [u8]) {
+        write_u16_le(bytes, RNTI_DCI_OFFSET_RNTI, self.rnti);
+        write_u32_le(bytes, RNTI_DCI_OFFSET_DL_TBS_BIT, self.dl_tbs_bit);
+        bytes[RNTI_DCI_OFFSET_DL_PRB] = self.dl_prb;
+        bytes[RNTI_DCI_OFFSET_DL_NO_TBS_PRB] = self.dl_no_tbs_prb;
+        write_u32_le(bytes, RNTI_DCI_OFFSET_UL_TBS_BIT, self.ul_tbs_bit);
+        bytes[RNTI_DCI_OFFSET_UL_PRB] = self.ul_prb;
+        bytes[RNTI_DCI_OFFSET_UL_NO_TBS_PRB] = self.ul_no_tbs_prb;
+    }
+}
+
 #[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[allow(non_snake_case)]
 pub struct NgScopeCellDci {
     pub cell_id: u8,
@@ -178,15 +414,84 @@ impl Default for NgScopeCellDci {
 }
 
 impl NgScopeCellDci {
-    pub fn from_bytes(bytes: [u8; NGSCOPE_STRUCT_SIZE_CELL_DCI]) -> Result<NgScopeCellDci> {
-        let cell_dci: &NgScopeCellDci = unsafe { &*bytes.as_ptr().cast() };
-        Ok(*cell_dci)
+    pub fn from_bytes(
+        version: MessageVersion,
+        bytes: &[u8],
+    ) -> Result<NgScopeCellDci, NgScopeDecodeError> {
+        match version {
+            MessageVersion::V0 => NgScopeCellDci::from_bytes_v0(bytes),
+        }
+    }
+
+    fn from_bytes_v0(bytes: &[u8]) -> Result<NgScopeCellDci, NgScopeDecodeError> {
+        check_size(bytes.len(), NGSCOPE_STRUCT_SIZE_CELL_DCI)?;
+        let nof_rnti: u8 = bytes[CELL_DCI_OFFSET_NOF_RNTI];
+        if nof_rnti as usize > NGSCOPE_MAX_NOF_RNTI {
+            return Err(NgScopeDecodeError::TooManyRnti { nof_rnti });
+        }
+
+        let mut rnti_list = [NgScopeRntiDci::default(); NGSCOPE_MAX_NOF_RNTI];
+        for (index, rnti_dci) in rnti_list.iter_mut().take(nof_rnti as usize).enumerate() {
+            let start = CELL_DCI_OFFSET_RNTI_LIST + index * NGSCOPE_STRUCT_SIZE_RNTI_DCI;
+            *rnti_dci =
+                NgScopeRntiDci::from_bytes_v0(&bytes[start..start + NGSCOPE_STRUCT_SIZE_RNTI_DCI])?;
+        }
+
+        Ok(NgScopeCellDci {
+            cell_id: bytes[CELL_DCI_OFFSET_CELL_ID],
+            time_stamp: read_u64_le(bytes, CELL_DCI_OFFSET_TIME_STAMP),
+            tti: read_u16_le(bytes, CELL_DCI_OFFSET_TTI),
+            total_dl_tbs_bit: read_u64_le(bytes, CELL_DCI_OFFSET_TOTAL_DL_TBS_BIT),
+            total_dl_prb: read_u16_le(bytes, CELL_DCI_OFFSET_TOTAL_DL_PRB),
+            total_dl_no_tbs_prb: read_u16_le(bytes, CELL_DCI_OFFSET_TOTAL_DL_NO_TBS_PRB),
+            total_ul_tbs_bit: read_u64_le(bytes, CELL_DCI_OFFSET_TOTAL_UL_TBS_BIT),
+            total_ul_prb: read_u16_le(bytes, CELL_DCI_OFFSET_TOTAL_UL_PRB),
+            total_ul_no_tbs_prb: read_u16_le(bytes, CELL_DCI_OFFSET_TOTAL_UL_NO_TBS_PRB),
+            nof_rnti,
+            rnti_list,
+        })
+    }
+
+    fn to_bytes_v0(self) -> [u8; NGSCOPE_STRUCT_SIZE_CELL_DCI] {
+        let mut bytes = [0u8; NGSCOPE_STRUCT_SIZE_CELL_DCI];
+        bytes[CELL_DCI_OFFSET_CELL_ID] = self.cell_id;
+        write_u64_le(&mut bytes, CELL_DCI_OFFSET_TIME_STAMP, self.time_stamp);
+        write_u16_le(&mut bytes, CELL_DCI_OFFSET_TTI, self.tti);
+        write_u64_le(
+            &mut bytes,
+            CELL_DCI_OFFSET_TOTAL_DL_TBS_BIT,
+            self.total_dl_tbs_bit,
+        );
+        write_u16_le(&mut bytes, CELL_DCI_OFFSET_TOTAL_DL_PRB, self.total_dl_prb);
+        write_u16_le(
+            &mut bytes,
+            CELL_DCI_OFFSET_TOTAL_DL_NO_TBS_PRB,
+            self.total_dl_no_tbs_prb,
+        );
+        write_u64_le(
+            &mut bytes,
+            CELL_DCI_OFFSET_TOTAL_UL_TBS_BIT,
+            self.total_ul_tbs_bit,
+        );
+        write_u16_le(&mut bytes, CELL_DCI_OFFSET_TOTAL_UL_PRB, self.total_ul_prb);
+        write_u16_le(
+            &mut bytes,
+            CELL_DCI_OFFSET_TOTAL_UL_NO_TBS_PRB,
+            self.total_ul_no_tbs_prb,
+        );
+        bytes[CELL_DCI_OFFSET_NOF_RNTI] = self.nof_rnti;
+        let nof_rnti = (self.nof_rnti as usize).min(NGSCOPE_MAX_NOF_RNTI);
+        for (index, rnti_dci) in self.rnti_list.iter().take(nof_rnti).enumerate() {
+            let start = CELL_DCI_OFFSET_RNTI_LIST + index * NGSCOPE_STRUCT_SIZE_RNTI_DCI;
+            rnti_dci.write_bytes_v0(&mut bytes[start..start + NGSCOPE_STRUCT_SIZE_RNTI_DCI]);
+        }
+        bytes
     }
 }
 
 // taken from: ngscope/hdr/dciLib/dci_sink_def.h
 #[repr(C)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct NgScopeCellConfig {
     pub nof_cell: u8,
     pub cell_prb: [u16; NGSCOPE_MAX_NOF_CELL],
@@ -194,9 +499,27 @@ pub struct NgScopeCellConfig {
 }
 
 impl NgScopeCellConfig {
-    pub fn from_bytes(bytes: [u8; NGSCOPE_STRUCT_SIZE_CONFIG]) -> Result<NgScopeCellConfig> {
-        let ue_dci: &NgScopeCellConfig = unsafe { &*bytes.as_ptr().cast() };
-        Ok(ue_dci.clone())
+    pub fn from_bytes(bytes: &[u8]) -> Result<NgScopeCellConfig, NgScopeDecodeError> {
+        check_size(bytes.len(), NGSCOPE_STRUCT_SIZE_CONFIG)?;
+        let mut cell_prb = [0u16; NGSCOPE_MAX_NOF_CELL];
+        for (index, prb) in cell_prb.iter_mut().enumerate() {
+            *prb = read_u16_le(bytes, CONFIG_OFFSET_CELL_PRB + index * 2);
+        }
+        Ok(NgScopeCellConfig {
+            nof_cell: bytes[CONFIG_OFFSET_NOF_CELL],
+            cell_prb,
+            rnti: read_u16_le(bytes, CONFIG_OFFSET_RNTI),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; NGSCOPE_STRUCT_SIZE_CONFIG] {
+        let mut bytes = [0u8; NGSCOPE_STRUCT_SIZE_CONFIG];
+        bytes[CONFIG_OFFSET_NOF_CELL] = self.nof_cell;
+        for (index, prb) in self.cell_prb.iter().enumerate() {
+            write_u16_le(&mut bytes, CONFIG_OFFSET_CELL_PRB + index * 2, *prb);
+        }
+        write_u16_le(&mut bytes, CONFIG_OFFSET_RNTI, self.rnti);
+        bytes
     }
 }
 
@@ -286,4 +609,146 @@ mod tests {
         let result = ngscope_extract_packet(&[255, 255, 123, 234, 123]);
         assert!(result.is_err());
     }
+
+    fn dummy_cell_dci_bytes(nof_rnti: u8) -> Vec<u8> {
+        let mut bytes = vec![0u8; NGSCOPE_MESSAGE_CONTENT_POSITION + NGSCOPE_STRUCT_SIZE_CELL_DCI];
+        bytes[..NGSCOPE_MESSAGE_TYPE_SIZE].copy_from_slice(&[171, 171, 171, 171]);
+        bytes[NGSCOPE_MESSAGE_VERSION_POSITION] = NGSCOPE_MESSAGE_VERSION_V0;
+        let content = &mut bytes[NGSCOPE_MESSAGE_CONTENT_POSITION..];
+        content[CELL_DCI_OFFSET_CELL_ID] = 1;
+        content[CELL_DCI_OFFSET_TIME_STAMP..CELL_DCI_OFFSET_TIME_STAMP + 8]
+            .copy_from_slice(&1_700_000_000_123_456u64.to_le_bytes());
+        content[CELL_DCI_OFFSET_TTI..CELL_DCI_OFFSET_TTI + 2]
+            .copy_from_slice(&8147u16.to_le_bytes());
+        content[CELL_DCI_OFFSET_TOTAL_DL_TBS_BIT..CELL_DCI_OFFSET_TOTAL_DL_TBS_BIT + 8]
+            .copy_from_slice(&12345u64.to_le_bytes());
+        content[CELL_DCI_OFFSET_TOTAL_DL_PRB..CELL_DCI_OFFSET_TOTAL_DL_PRB + 2]
+            .copy_from_slice(&42u16.to_le_bytes());
+        content[CELL_DCI_OFFSET_TOTAL_UL_PRB..CELL_DCI_OFFSET_TOTAL_UL_PRB + 2]
+            .copy_from_slice(&7u16.to_le_bytes());
+        content[CELL_DCI_OFFSET_NOF_RNTI] = nof_rnti;
+        let rnti_start = CELL_DCI_OFFSET_RNTI_LIST;
+        content[rnti_start..rnti_start + 2].copy_from_slice(&19015u16.to_le_bytes());
+        content
+            [rnti_start + RNTI_DCI_OFFSET_DL_TBS_BIT..rnti_start + RNTI_DCI_OFFSET_DL_TBS_BIT + 4]
+            .copy_from_slice(&12345u32.to_le_bytes());
+        content[rnti_start + RNTI_DCI_OFFSET_DL_PRB] = 42;
+        content[rnti_start + RNTI_DCI_OFFSET_UL_PRB] = 7;
+        bytes
+    }
+
+    #[test]
+    fn test_decode_dci() {
+        let msg = Message::from_bytes(&DUMMY_DCI).unwrap();
+        if let Message::Dci(ue_dci) = msg {
+            assert_eq!(ue_dci.cell_idx, 0);
+            assert_eq!(ue_dci.tti, 8147);
+            assert_eq!(ue_dci.rnti, 19015);
+            assert_eq!(ue_dci.dl_tbs, 0);
+            assert!(!ue_dci.dl_rv_flag);
+        } else {
+            panic!("expected Message::Dci, got {:?}", msg);
+        }
+    }
+
+    #[test]
+    fn test_decode_config() {
+        let msg = Message::from_bytes(&DUMMY_CONFIG).unwrap();
+        if let Message::Config(cell_config) = msg {
+            assert_eq!(cell_config.nof_cell, 1);
+            assert_eq!(cell_config.cell_prb, [50, 0, 0, 0]);
+            assert_eq!(cell_config.rnti, 19015);
+        } else {
+            panic!("expected Message::Config, got {:?}", msg);
+        }
+    }
+
+    #[test]
+    fn test_decode_cell_dci() {
+        let msg = Message::from_bytes(&dummy_cell_dci_bytes(1)).unwrap();
+        if let Message::CellDci(cell_dci) = msg {
+            assert_eq!(cell_dci.cell_id, 1);
+            assert_eq!(cell_dci.time_stamp, 1_700_000_000_123_456);
+            assert_eq!(cell_dci.tti, 8147);
+            assert_eq!(cell_dci.total_dl_tbs_bit, 12345);
+            assert_eq!(cell_dci.total_dl_prb, 42);
+            assert_eq!(cell_dci.total_ul_prb, 7);
+            assert_eq!(cell_dci.nof_rnti, 1);
+            assert_eq!(cell_dci.rnti_list[0].rnti, 19015);
+            assert_eq!(cell_dci.rnti_list[0].dl_tbs_bit, 12345);
+            assert_eq!(cell_dci.rnti_list[0].dl_prb, 42);
+            assert_eq!(cell_dci.rnti_list[0].ul_prb, 7);
+            assert_eq!(cell_dci.rnti_list[1].rnti, 0);
+        } else {
+            panic!("expected Message::CellDci, got {:?}", msg);
+        }
+    }
+
+    #[test]
+    fn test_decode_err_unknown_version() {
+        let mut bytes = DUMMY_DCI;
+        bytes[NGSCOPE_MESSAGE_VERSION_POSITION] = 3;
+        assert_eq!(
+            Message::from_bytes(&bytes).unwrap_err(),
+            NgScopeDecodeError::UnknownVersion {
+                message_type: MessageType::Dci,
+                version: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_err_too_many_rnti() {
+        let bytes = dummy_cell_dci_bytes(NGSCOPE_MAX_NOF_RNTI as u8 + 1);
+        assert_eq!(
+            Message::from_bytes(&bytes).unwrap_err(),
+            NgScopeDecodeError::TooManyRnti {
+                nof_rnti: NGSCOPE_MAX_NOF_RNTI as u8 + 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_err_too_few_bytes() {
+        assert_eq!(
+            Message::from_bytes(&DUMMY_DCI[..DUMMY_DCI.len() - 1]).unwrap_err(),
+            NgScopeDecodeError::TooFewBytes {
+                expected: NGSCOPE_STRUCT_SIZE_DCI,
+                actual: NGSCOPE_STRUCT_SIZE_DCI - 1
+            }
+        );
+        assert!(Message::from_bytes(&DUMMY_CONFIG[..10]).is_err());
+        assert!(Message::from_bytes(&[171, 171, 171, 171]).is_err());
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let dci_msg = Message::from_bytes(&DUMMY_DCI).unwrap();
+        let dci_bytes = dci_msg.to_bytes();
+        assert_eq!(
+            dci_bytes[..NGSCOPE_MESSAGE_CONTENT_POSITION],
+            DUMMY_DCI[..NGSCOPE_MESSAGE_CONTENT_POSITION]
+        );
+        assert_eq!(Message::from_bytes(&dci_bytes).unwrap(), dci_msg);
+        assert_eq!(
+            Message::from_bytes(&DUMMY_CONFIG).unwrap().to_bytes(),
+            DUMMY_CONFIG.to_vec()
+        );
+        assert_eq!(
+            Message::from_bytes(&DUMMY_START).unwrap().to_bytes(),
+            DUMMY_START.to_vec()
+        );
+
+        let cell_dci_bytes = dummy_cell_dci_bytes(1);
+        let msg = Message::from_bytes(&cell_dci_bytes).unwrap();
+        assert_eq!(msg.to_bytes(), cell_dci_bytes);
+    }
+
+    #[test]
+    fn test_decode_err_unknown_message_type() {
+        assert_eq!(
+            Message::from_bytes(&[1, 2, 3, 4, 0]).unwrap_err(),
+            NgScopeDecodeError::UnknownMessageType([1, 2, 3, 4])
+        );
+    }
 }
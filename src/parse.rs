@@ -1,13 +1,34 @@
 /// Credits: https://stackoverflow.com/questions/55133351/is-there-a-way-to-get-clap-to-use-default-values-from-a-file
-use anyhow::Result;
-use clap::{Args, Command, CommandFactory, Parser, ValueEnum};
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use clap::{Args, Command, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
 use std::{default, error::Error, path::PathBuf};
 
-use crate::{logic::traffic_patterns::RntiMatchingTrafficPatternType, util::print_debug};
+use crate::{
+    devicepublisher::DevicePublisherAuthType,
+    logic::{
+        rnti_matcher::{MatchingParameters, MatchingWeightsProfile, DEFAULT_RNTI_RING_BUFFER_SIZE},
+        traffic_patterns::RntiMatchingTrafficPatternType,
+    },
+    modem::ModemVendor,
+    util::print_debug,
+};
 
 pub const DEFAULT_SCENARIO: Scenario = Scenario::TrackUeAndEstimateTransportCapacity;
 pub const DEFAULT_LOG_BASE_DIR: &str = "./.logs.ue/";
+pub const DEFAULT_MODEM_DEVICE: &str = "/dev/ttyUSB2";
+pub const DEFAULT_MODEM_BAUD_RATE: u32 = 115200;
+pub const DEFAULT_CELL_FILE_PATH: &str = "./cells.yaml";
+pub const DEFAULT_DEVPUB_SCHEME: &str = "http";
+pub const DEFAULT_DEVPUB_PORT: u16 = 7353;
+pub const DEFAULT_DEVPUB_PATH: &str = "/api/v1/celldata/connected/all";
+pub const DEFAULT_DEVPUB_ALL_CELLS_PATH: &str = "/api/v1/celldata";
+pub const DEFAULT_HTTP_SOURCE_POLL_INTERVAL_MS: u64 = 5000;
+pub const DEFAULT_LOCAL_SOURCE_POLL_INTERVAL_MS: u64 = 1000;
+pub const DEFAULT_SOURCE_BACKOFF_MAX_MS: u64 = 60000;
+pub const DEFAULT_SOURCE_STALE_TIMEOUT_MS: u64 = 30000;
+pub const DEFAULT_SOURCE_HTTP_TIMEOUT_MS: u64 = 2000;
 pub const DEFAULT_DOWNLOAD_BASE_ADDR: &str = "http://some.addr";
 pub const DEFAULT_DOWNLOAD_PATHS: &[&str] = &[
     "/10s/cubic",
@@ -52,6 +73,22 @@ pub struct Arguments {
     #[command(flatten)]
     pub devicepublisher: Option<DevicePublisherArgs>,
 
+    /// Config for fetching data from a modem's AT command port
+    #[command(flatten)]
+    pub modem: Option<ModemArgs>,
+
+    /// Config for reading cell data from a file
+    #[command(flatten)]
+    pub cellfile: Option<CellFileArgs>,
+
+    /// Polling, backoff and stale-data policy shared by all cell sources
+    #[command(flatten)]
+    pub sourcepolicy: Option<SourcePolicyArgs>,
+
+    /// HTTP client settings shared by the HTTP based cell sources
+    #[command(flatten)]
+    pub sourcehttp: Option<SourceHttpArgs>,
+
     #[command(flatten)]
     pub ngscope: Option<NgScopeArgs>,
 
@@ -70,6 +107,77 @@ pub struct Arguments {
     /// Print additional information in the terminal
     #[arg(short('v'), long, required = false)]
     pub verbose: Option<bool>,
+
+    /// Run a standalone tool instead of the tracker
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub command: Option<AppCommand>,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum AppCommand {
+    /// Act as NG-Scope remote interface and send recorded or synthetic DCI to ng_local_addr
+    FakeNgScope(FakeNgScopeArgs),
+    /// Rerun the RNTI matching on logged traffic collections and compare it with the true RNTI
+    ReplayMatching(ReplayMatchingArgs),
+    /// Search the matching weightings on labelled traffic collections and write a weights profile
+    CalibrateMatching(CalibrateMatchingArgs),
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct FakeNgScopeArgs {
+    /// Arrow file or directory of Arrow files written by the DCI logger (synthetic DCI if omitted)
+    #[arg(long, required = false)]
+    pub fake_dci_source: Option<String>,
+
+    /// Number of PRBs announced in the cell config
+    #[arg(long, default_value_t = 50)]
+    pub fake_nof_prb: u16,
+
+    /// Number of RNTIs per synthetic subframe
+    #[arg(long, default_value_t = 5)]
+    pub fake_nof_rnti: u8,
+
+    /// Replay speed factor (2.0 sends twice as fast as recorded)
+    #[arg(long, default_value_t = 1.0)]
+    pub fake_speed: f64,
+
+    /// Start over once the recorded DCI are exhausted
+    #[arg(long, default_value_t = false)]
+    pub fake_loop: bool,
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct ReplayMatchingArgs {
+    /// Traffic collection JSONL file or log directory (searched for *_traffic_collection.jsonl)
+    #[arg(long)]
+    pub replay_source: String,
+
+    /// True RNTI per run: "RNTI" for all runs or "RUN=RNTI", RUN being part of the file path
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub replay_ground_truth: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct CalibrateMatchingArgs {
+    #[command(flatten)]
+    pub replay: ReplayMatchingArgs,
+
+    /// Only use collections of this traffic pattern and write a profile for it
+    #[arg(long, value_enum, required = false)]
+    pub calibrate_pattern: Option<RntiMatchingTrafficPatternType>,
+
+    /// Number of evaluated weightings
+    #[arg(long, default_value_t = 500)]
+    pub calibrate_iterations: u64,
+
+    /// Seed of the weighting search
+    #[arg(long, default_value_t = 1)]
+    pub calibrate_seed: u64,
+
+    /// Weights profile to write (YAML), load it with --matching-weights-profile
+    #[arg(long, default_value = "matching_weights.yaml")]
+    pub calibrate_output: String,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Serialize, Deserialize)]
@@ -82,18 +190,26 @@ pub enum Scenario {
     PerformMeasurement,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Serialize, Deserialize)]
+#[derive(
+    Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, ValueEnum, Debug, Serialize, Deserialize,
+)]
 pub enum CellApiConfig {
     /// Use a Milesight router as cell data API
     Milesight,
     /// Use the DevicePublisher app as cell data API
     DevicePublisher,
+    /// Use the AT command port of a Quectel or Sierra modem as cell data API
+    Modem,
+    /// Read cell data from a YAML/JSON file (static or timestamped playback)
+    File,
 }
 
 #[derive(Clone, Debug)]
 pub enum FlattenedCellApiConfig {
     Milesight(FlattenedMilesightArgs),
     DevicePublisher(FlattenedDevicePublisherArgs),
+    Modem(FlattenedModemArgs),
+    File(FlattenedCellFileArgs),
 }
 
 #[derive(Args, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -107,6 +223,9 @@ pub struct MilesightArgs {
     /// authentication: Base64 encoded password
     #[arg(long, required = false)]
     pub milesight_auth: Option<String>,
+    /// Interval between two requests in ms
+    #[arg(long, required = false)]
+    pub milesight_poll_interval_ms: Option<u64>,
 }
 
 #[derive(Clone, Debug)]
@@ -114,6 +233,7 @@ pub struct FlattenedMilesightArgs {
     pub milesight_address: String,
     pub milesight_user: String,
     pub milesight_auth: String,
+    pub milesight_poll_interval_ms: u64,
 }
 
 #[derive(Args, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -121,16 +241,118 @@ pub struct DevicePublisherArgs {
     /// Base address of DevicePublisher
     #[arg(long, required = false)]
     pub devpub_address: Option<String>,
-    /// Some authentication
+    /// Bearer token or "user:password", depending on devpub_auth_type
     #[arg(long, required = false)]
     pub devpub_auth: Option<String>,
+    /// Interval between two requests in ms
+    #[arg(long, required = false)]
+    pub devpub_poll_interval_ms: Option<u64>,
+    /// http or https (TLS settings: source_tls_*)
+    #[arg(long, required = false)]
+    pub devpub_scheme: Option<String>,
+    #[arg(long, required = false)]
+    pub devpub_port: Option<u16>,
+    /// Endpoint of the connected cells
+    #[arg(long, required = false)]
+    pub devpub_path: Option<String>,
+    /// Endpoint of all cells, including neighbours
+    #[arg(long, required = false)]
+    pub devpub_all_cells_path: Option<String>,
+    /// How devpub_auth is sent
+    #[arg(long, value_enum, required = false)]
+    pub devpub_auth_type: Option<DevicePublisherAuthType>,
+    /// Additionally request devpub_all_cells_path to track neighbour cells
+    #[arg(long, required = false)]
+    pub devpub_fetch_neighbours: Option<bool>,
 }
 
 #[derive(Clone, Debug)]
-#[allow(dead_code)]
 pub struct FlattenedDevicePublisherArgs {
     pub devpub_address: String,
     pub devpub_auth: String,
+    pub devpub_poll_interval_ms: u64,
+    pub devpub_scheme: String,
+    pub devpub_port: u16,
+    pub devpub_path: String,
+    pub devpub_all_cells_path: String,
+    pub devpub_auth_type: DevicePublisherAuthType,
+    pub devpub_fetch_neighbours: bool,
+}
+
+#[derive(Args, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModemArgs {
+    /// Serial/tty device of the modem's AT command port
+    #[arg(long, required = false)]
+    pub modem_device: Option<String>,
+    /// Baud rate of the AT command port
+    #[arg(long, required = false)]
+    pub modem_baud_rate: Option<u32>,
+    /// Modem vendor, determines the AT commands used
+    #[arg(long, value_enum, required = false)]
+    pub modem_vendor: Option<ModemVendor>,
+    /// Interval between two AT command queries in ms
+    #[arg(long, required = false)]
+    pub modem_poll_interval_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlattenedModemArgs {
+    pub modem_device: String,
+    pub modem_baud_rate: u32,
+    pub modem_vendor: ModemVendor,
+    pub modem_poll_interval_ms: u64,
+}
+
+#[derive(Args, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CellFileArgs {
+    /// YAML/JSON file with the cells, reloaded whenever it changes
+    #[arg(long, required = false)]
+    pub cellfile_path: Option<String>,
+    /// Interval between two checks of the file in ms
+    #[arg(long, required = false)]
+    pub cellfile_poll_interval_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlattenedCellFileArgs {
+    pub cellfile_path: String,
+    pub cellfile_poll_interval_ms: u64,
+}
+
+#[derive(Args, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourcePolicyArgs {
+    /// Upper bound of the exponential backoff after failed requests in ms
+    #[arg(long, required = false)]
+    pub source_backoff_max_ms: Option<u64>,
+    /// Report the cell as unknown if no request succeeded for this long in ms (0 disables)
+    #[arg(long, required = false)]
+    pub source_stale_timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlattenedSourcePolicyArgs {
+    pub source_backoff_max_ms: u64,
+    pub source_stale_timeout_ms: u64,
+}
+
+#[derive(Args, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceHttpArgs {
+    /// Timeout of a single HTTP request in ms
+    #[arg(long, required = false)]
+    pub source_http_timeout_ms: Option<u64>,
+    /// PEM file with an additional trusted (e.g. self-signed) certificate
+    #[arg(long, required = false)]
+    pub source_tls_ca_cert: Option<String>,
+    /// Accept any TLS certificate (only for testing)
+    #[arg(long, required = false)]
+    pub source_tls_accept_invalid_certs: Option<bool>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlattenedSourceHttpArgs {
+    pub source_http_timeout_ms: u64,
+    pub source_tls_ca_cert: Option<String>,
+    pub source_tls_accept_invalid_certs: bool,
 }
 
 #[derive(Args, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -162,6 +384,85 @@ pub struct NgScopeArgs {
     /// Determine the number of DCIs contained in a single log file
     #[arg(long, required = false)]
     pub ng_log_dci_batch_size: Option<u64>,
+
+    /// Log DCI loss and gap statistics per cell
+    #[arg(long, required = false)]
+    pub ng_log_dci_quality: Option<bool>,
+
+    /// SDR device arguments (rf_args) per carrier, one entry per cell that shall be decoded
+    #[arg(long, value_delimiter = ',', required = false)]
+    pub ng_rf_args: Option<Vec<String>>,
+
+    /// ng-scope .cfg file used as base config, only frequency and cells are adjusted
+    #[arg(long, required = false)]
+    pub ng_config_template: Option<String>,
+
+    /// Log events recognised in the NG-Scope output (cell found, sync lost, ..)
+    #[arg(long, required = false)]
+    pub ng_log_events: Option<bool>,
+
+    /// Restart NG-Scope after this many sync losses within a minute (0: never)
+    #[arg(long, required = false)]
+    pub ng_sync_loss_restart_count: Option<u32>,
+
+    /// Re-handshake/restart NG-Scope when no DCI arrives for this long (0: never)
+    #[arg(long, required = false)]
+    pub ng_dci_silence_timeout_ms: Option<u64>,
+
+    /* ng-scope config: unset values are taken from the template or the defaults */
+    /// SDR serial per carrier, replaces the serial in the carrier's rf_args
+    #[arg(long, value_delimiter = ',', required = false)]
+    pub ng_rf_serial: Option<Vec<String>>,
+
+    /// N_id_2 of the cell to decode (-1: any)
+    #[arg(long, required = false, allow_negative_numbers = true)]
+    pub ng_n_id_2: Option<i16>,
+
+    /// Number of decoder threads per RF device
+    #[arg(long, required = false)]
+    pub ng_nof_thread: Option<u8>,
+
+    #[arg(long, required = false)]
+    pub ng_disable_plot: Option<bool>,
+
+    #[arg(long, required = false)]
+    pub ng_rf_log_dl: Option<bool>,
+
+    #[arg(long, required = false)]
+    pub ng_rf_log_ul: Option<bool>,
+
+    #[arg(long, required = false)]
+    pub ng_rf_log_phich: Option<bool>,
+
+    /// RNTI ng-scope focuses on (decode_single_ue)
+    #[arg(long, required = false)]
+    pub ng_rnti: Option<u16>,
+
+    #[arg(long, required = false)]
+    pub ng_remote_enable: Option<bool>,
+
+    #[arg(long, required = false)]
+    pub ng_decode_single_ue: Option<bool>,
+
+    #[arg(long, required = false)]
+    pub ng_decode_sib: Option<bool>,
+
+    /// Directory for the DCI logs written by ng-scope itself
+    #[arg(long, required = false)]
+    pub ng_dci_logs_path: Option<String>,
+
+    /// Directory for the SIB logs written by ng-scope itself
+    #[arg(long, required = false)]
+    pub ng_sib_logs_path: Option<String>,
+
+    #[arg(long, required = false)]
+    pub ng_dci_log_ul: Option<bool>,
+
+    #[arg(long, required = false)]
+    pub ng_dci_log_dl: Option<bool>,
+
+    #[arg(long, required = false)]
+    pub ng_dci_log_interval: Option<u16>,
 }
 
 #[derive(Clone, Debug)]
@@ -173,6 +474,28 @@ pub struct FlattenedNgScopeArgs {
     pub ng_start_process: bool,
     pub ng_log_dci: bool,
     pub ng_log_dci_batch_size: u64,
+    pub ng_log_dci_quality: bool,
+    pub ng_rf_args: Vec<String>,
+    pub ng_config_template: Option<String>,
+    pub ng_log_events: bool,
+    pub ng_sync_loss_restart_count: u32,
+    pub ng_dci_silence_timeout_ms: u64,
+    pub ng_rf_serial: Vec<String>,
+    pub ng_n_id_2: Option<i16>,
+    pub ng_nof_thread: Option<u8>,
+    pub ng_disable_plot: Option<bool>,
+    pub ng_rf_log_dl: Option<bool>,
+    pub ng_rf_log_ul: Option<bool>,
+    pub ng_rf_log_phich: Option<bool>,
+    pub ng_rnti: Option<u16>,
+    pub ng_remote_enable: Option<bool>,
+    pub ng_decode_single_ue: Option<bool>,
+    pub ng_decode_sib: Option<bool>,
+    pub ng_dci_logs_path: Option<String>,
+    pub ng_sib_logs_path: Option<String>,
+    pub ng_dci_log_ul: Option<bool>,
+    pub ng_dci_log_dl: Option<bool>,
+    pub ng_dci_log_interval: Option<u16>,
 }
 
 #[derive(Args, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -192,6 +515,55 @@ pub struct RntiMatchingArgs {
     /// Log RNTI matching traffic and features
     #[arg(long, required = false)]
     pub matching_log_traffic: Option<bool>,
+
+    /// Feature weightings, comma separated in feature vector order (8 values)
+    #[arg(long, value_delimiter = ',', required = false)]
+    pub matching_weightings: Option<Vec<f64>>,
+
+    /// DCIs are collected for the traffic pattern's total time * factor
+    #[arg(long, required = false)]
+    pub matching_time_overlap_factor: Option<f64>,
+
+    /// Drop RNTIs with more total UL bytes than the pattern's total UL bytes * factor
+    #[arg(long, required = false)]
+    pub matching_filter_max_total_ul_factor: Option<f64>,
+
+    /// Drop RNTIs with less total UL bytes than the pattern's total UL bytes * factor
+    #[arg(long, required = false)]
+    pub matching_filter_min_total_ul_factor: Option<f64>,
+
+    /// Drop RNTIs with a single DCI above this number of UL bytes
+    #[arg(long, required = false)]
+    pub matching_filter_max_ul_per_dci: Option<u64>,
+
+    /// Drop RNTIs with less DCIs than the pattern's number of packets * factor
+    #[arg(long, required = false)]
+    pub matching_filter_min_occurences_factor: Option<f64>,
+
+    /// Number of matching results the published RNTI is voted on
+    #[arg(long, required = false)]
+    pub matching_rnti_ring_buffer_size: Option<usize>,
+
+    /// Per traffic pattern overrides of the values above (config file only)
+    #[arg(skip)]
+    pub matching_pattern_overrides: Option<Vec<MatchingPatternOverride>>,
+
+    /// Weights profiles written by calibrate-matching, comma separated.
+    /// A pattern profile wins over the configured weightings of the pattern.
+    #[arg(long, value_delimiter = ',', required = false)]
+    pub matching_weights_profile: Option<Vec<String>>,
+}
+
+/// Unset values fall back to the global matching parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchingPatternOverride {
+    pub pattern: RntiMatchingTrafficPatternType,
+    pub weightings: Option<Vec<f64>>,
+    pub time_overlap_factor: Option<f64>,
+    pub filter_max_total_ul_factor: Option<f64>,
+    pub filter_min_total_ul_factor: Option<f64>,
+    pub filter_max_ul_per_dci: Option<u64>,
+    pub filter_min_occurences_factor: Option<f64>,
 }
 
 #[derive(Clone, Debug)]
@@ -200,6 +572,9 @@ pub struct FlattenedRntiMatchingArgs {
     pub matching_traffic_pattern: Vec<RntiMatchingTrafficPatternType>,
     pub matching_traffic_destination: String,
     pub matching_log_traffic: bool,
+    pub matching_parameters: MatchingParameters,
+    pub matching_rnti_ring_buffer_size: usize,
+    pub matching_pattern_overrides: Vec<MatchingPatternOverride>,
 }
 
 #[derive(Copy, Clone, PartialEq, PartialOrd, ValueEnum, Debug, Serialize, Deserialize)]
@@ -245,11 +620,15 @@ pub struct LogArgs {
     /// Base directory for logging
     #[arg(long, required = false)]
     pub log_base_dir: Option<String>,
+    /// Log every cell source poll (signal levels and bandwidth estimates) to cell_info/
+    #[arg(long, required = false)]
+    pub log_cell_info: Option<bool>,
 }
 
 #[derive(Clone, Debug)]
 pub struct FlattenedLogArgs {
     pub log_base_dir: String,
+    pub log_cell_info: bool,
 }
 
 #[derive(Args, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -268,6 +647,7 @@ pub struct FlattenedDownloadArgs {
 
 impl default::Default for Arguments {
     fn default() -> Self {
+        let default_parameters = MatchingParameters::default();
         Arguments {
             scenario: Some(DEFAULT_SCENARIO),
             verbose: Some(true),
@@ -276,10 +656,37 @@ impl default::Default for Arguments {
                 milesight_address: Some("http://127.0.0.1".to_string()),
                 milesight_user: Some("root".to_string()),
                 milesight_auth: Some("root-password".to_string()),
+                milesight_poll_interval_ms: Some(DEFAULT_HTTP_SOURCE_POLL_INTERVAL_MS),
             }),
             devicepublisher: Some(DevicePublisherArgs {
                 devpub_address: Some("https://some.address".to_string()),
                 devpub_auth: Some("some_auth".to_string()),
+                devpub_poll_interval_ms: Some(DEFAULT_HTTP_SOURCE_POLL_INTERVAL_MS),
+                devpub_scheme: Some(DEFAULT_DEVPUB_SCHEME.to_string()),
+                devpub_port: Some(DEFAULT_DEVPUB_PORT),
+                devpub_path: Some(DEFAULT_DEVPUB_PATH.to_string()),
+                devpub_all_cells_path: Some(DEFAULT_DEVPUB_ALL_CELLS_PATH.to_string()),
+                devpub_auth_type: Some(DevicePublisherAuthType::None),
+                devpub_fetch_neighbours: Some(true),
+            }),
+            modem: Some(ModemArgs {
+                modem_device: Some(DEFAULT_MODEM_DEVICE.to_string()),
+                modem_baud_rate: Some(DEFAULT_MODEM_BAUD_RATE),
+                modem_vendor: Some(ModemVendor::Quectel),
+                modem_poll_interval_ms: Some(DEFAULT_LOCAL_SOURCE_POLL_INTERVAL_MS),
+            }),
+            cellfile: Some(CellFileArgs {
+                cellfile_path: Some(DEFAULT_CELL_FILE_PATH.to_string()),
+                cellfile_poll_interval_ms: Some(DEFAULT_LOCAL_SOURCE_POLL_INTERVAL_MS),
+            }),
+            sourcepolicy: Some(SourcePolicyArgs {
+                source_backoff_max_ms: Some(DEFAULT_SOURCE_BACKOFF_MAX_MS),
+                source_stale_timeout_ms: Some(DEFAULT_SOURCE_STALE_TIMEOUT_MS),
+            }),
+            sourcehttp: Some(SourceHttpArgs {
+                source_http_timeout_ms: Some(DEFAULT_SOURCE_HTTP_TIMEOUT_MS),
+                source_tls_ca_cert: None,
+                source_tls_accept_invalid_certs: Some(false),
             }),
             ngscope: Some(NgScopeArgs {
                 ng_path: Some("/dev_ws/dependencies/ng-scope/build_x86/ngscope/src/".to_string()),
@@ -289,12 +696,51 @@ impl default::Default for Arguments {
                 ng_start_process: Some(true),
                 ng_log_dci: Some(true),
                 ng_log_dci_batch_size: Some(60000),
+                ng_log_dci_quality: Some(true),
+                ng_rf_args: None,
+                ng_config_template: None,
+                ng_log_events: Some(true),
+                ng_sync_loss_restart_count: Some(5),
+                ng_dci_silence_timeout_ms: Some(5000),
+                ng_rf_serial: None,
+                ng_n_id_2: None,
+                ng_nof_thread: None,
+                ng_disable_plot: None,
+                ng_rf_log_dl: None,
+                ng_rf_log_ul: None,
+                ng_rf_log_phich: None,
+                ng_rnti: None,
+                ng_remote_enable: None,
+                ng_decode_single_ue: None,
+                ng_decode_sib: None,
+                ng_dci_logs_path: None,
+                ng_sib_logs_path: None,
+                ng_dci_log_ul: None,
+                ng_dci_log_dl: None,
+                ng_dci_log_interval: None,
             }),
             rntimatching: Some(RntiMatchingArgs {
                 matching_local_addr: Some("0.0.0.0:9292".to_string()),
                 matching_traffic_pattern: Some(vec![RntiMatchingTrafficPatternType::A]),
                 matching_traffic_destination: Some("1.1.1.1:53".to_string()),
                 matching_log_traffic: Some(true),
+                matching_weightings: Some(default_parameters.weightings),
+                matching_time_overlap_factor: Some(default_parameters.time_overlap_factor),
+                matching_filter_max_total_ul_factor: Some(
+                    default_parameters.basic_filter_max_total_ul_factor,
+                ),
+                matching_filter_min_total_ul_factor: Some(
+                    default_parameters.basic_filter_min_total_ul_factor,
+                ),
+                matching_filter_max_ul_per_dci: Some(
+                    default_parameters.basic_filter_max_ul_per_dci,
+                ),
+                matching_filter_min_occurences_factor: Some(
+                    default_parameters.basic_filter_min_occurences_factor,
+                ),
+                matching_rnti_ring_buffer_size: Some(DEFAULT_RNTI_RING_BUFFER_SIZE),
+                matching_pattern_overrides: Some(vec![]),
+                matching_weights_profile: None,
             }),
             model: Some(ModelArgs {
                 model_send_metric_interval_value: Some(1.0),
@@ -305,6 +751,7 @@ impl default::Default for Arguments {
             }),
             log: Some(LogArgs {
                 log_base_dir: Some(DEFAULT_LOG_BASE_DIR.to_string()),
+                log_cell_info: Some(true),
             }),
             download: Some(DownloadArgs {
                 download_base_addr: Some(DEFAULT_DOWNLOAD_BASE_ADDR.to_string()),
@@ -315,6 +762,7 @@ impl default::Default for Arguments {
                         .collect(),
                 ),
             }),
+            command: None,
         }
     }
 }
@@ -348,8 +796,12 @@ impl Arguments {
         self.cellapi = self.cellapi.or(config_file.cellapi);
         self.milesight = self.milesight.or(config_file.milesight);
         self.devicepublisher = self.devicepublisher.or(config_file.devicepublisher);
-        self.ngscope = self.ngscope.or(config_file.ngscope);
-        self.rntimatching = self.rntimatching.or(config_file.rntimatching);
+        self.modem = self.modem.or(config_file.modem);
+        self.cellfile = self.cellfile.or(config_file.cellfile);
+        self.sourcepolicy = self.sourcepolicy.or(config_file.sourcepolicy);
+        self.sourcehttp = self.sourcehttp.or(config_file.sourcehttp);
+        self.ngscope = merge_group(self.ngscope, config_file.ngscope)?;
+        self.rntimatching = merge_group(self.rntimatching, config_file.rntimatching)?;
         self.model = self.model.or(config_file.model);
         self.log = self.log.or(config_file.log);
         self.download = self.download.or(config_file.download);
@@ -383,11 +835,36 @@ impl Arguments {
     }
 }
 
+/// Merge a config group field by field, values given on the command line win.
+/// Otherwise a single CLI argument of a group hides the whole group of the config file.
+fn merge_group<T: Serialize + DeserializeOwned>(
+    cli_group: Option<T>,
+    file_group: Option<T>,
+) -> Result<Option<T>, Box<dyn Error>> {
+    let (cli_group, file_group) = match (cli_group, file_group) {
+        (Some(cli_group), Some(file_group)) => (cli_group, file_group),
+        (cli_group, file_group) => return Ok(cli_group.or(file_group)),
+    };
+    let mut merged = serde_json::to_value(file_group)?;
+    if let (Value::Object(merged_fields), Value::Object(cli_fields)) =
+        (&mut merged, serde_json::to_value(cli_group)?)
+    {
+        for (key, value) in cli_fields {
+            if !value.is_null() {
+                merged_fields.insert(key, value);
+            }
+        }
+    }
+    Ok(Some(serde_json::from_value(merged)?))
+}
+
 impl FlattenedCellApiConfig {
     pub fn from_unflattened(
         cell_api: CellApiConfig,
         milesight_args: MilesightArgs,
         devicepublisher_args: DevicePublisherArgs,
+        modem_args: ModemArgs,
+        cellfile_args: CellFileArgs,
     ) -> Result<FlattenedCellApiConfig> {
         match cell_api {
             CellApiConfig::Milesight => {
@@ -395,18 +872,107 @@ impl FlattenedCellApiConfig {
                     milesight_address: milesight_args.milesight_address.unwrap(),
                     milesight_user: milesight_args.milesight_user.unwrap(),
                     milesight_auth: milesight_args.milesight_auth.unwrap(),
+                    milesight_poll_interval_ms: milesight_args
+                        .milesight_poll_interval_ms
+                        .unwrap_or(DEFAULT_HTTP_SOURCE_POLL_INTERVAL_MS),
                 }))
             }
             CellApiConfig::DevicePublisher => Ok(FlattenedCellApiConfig::DevicePublisher(
                 FlattenedDevicePublisherArgs {
                     devpub_address: devicepublisher_args.devpub_address.unwrap(),
                     devpub_auth: devicepublisher_args.devpub_auth.unwrap(),
+                    devpub_poll_interval_ms: devicepublisher_args
+                        .devpub_poll_interval_ms
+                        .unwrap_or(DEFAULT_HTTP_SOURCE_POLL_INTERVAL_MS),
+                    devpub_scheme: devicepublisher_args
+                        .devpub_scheme
+                        .unwrap_or(DEFAULT_DEVPUB_SCHEME.to_string()),
+                    devpub_port: devicepublisher_args
+                        .devpub_port
+                        .unwrap_or(DEFAULT_DEVPUB_PORT),
+                    devpub_path: devicepublisher_args
+                        .devpub_path
+                        .unwrap_or(DEFAULT_DEVPUB_PATH.to_string()),
+                    devpub_all_cells_path: devicepublisher_args
+                        .devpub_all_cells_path
+                        .unwrap_or(DEFAULT_DEVPUB_ALL_CELLS_PATH.to_string()),
+                    devpub_auth_type: devicepublisher_args
+                        .devpub_auth_type
+                        .unwrap_or(DevicePublisherAuthType::None),
+                    devpub_fetch_neighbours: devicepublisher_args
+                        .devpub_fetch_neighbours
+                        .unwrap_or(true),
                 },
             )),
+            CellApiConfig::Modem => Ok(FlattenedCellApiConfig::Modem(FlattenedModemArgs {
+                modem_device: modem_args
+                    .modem_device
+                    .unwrap_or(DEFAULT_MODEM_DEVICE.to_string()),
+                modem_baud_rate: modem_args
+                    .modem_baud_rate
+                    .unwrap_or(DEFAULT_MODEM_BAUD_RATE),
+                modem_vendor: modem_args.modem_vendor.unwrap_or(ModemVendor::Quectel),
+                modem_poll_interval_ms: modem_args
+                    .modem_poll_interval_ms
+                    .unwrap_or(DEFAULT_LOCAL_SOURCE_POLL_INTERVAL_MS),
+            })),
+            CellApiConfig::File => Ok(FlattenedCellApiConfig::File(FlattenedCellFileArgs {
+                cellfile_path: cellfile_args
+                    .cellfile_path
+                    .unwrap_or(DEFAULT_CELL_FILE_PATH.to_string()),
+                cellfile_poll_interval_ms: cellfile_args
+                    .cellfile_poll_interval_ms
+                    .unwrap_or(DEFAULT_LOCAL_SOURCE_POLL_INTERVAL_MS),
+            })),
+        }
+    }
+
+    pub fn cell_api(&self) -> CellApiConfig {
+        match self {
+            FlattenedCellApiConfig::Milesight(_) => CellApiConfig::Milesight,
+            FlattenedCellApiConfig::DevicePublisher(_) => CellApiConfig::DevicePublisher,
+            FlattenedCellApiConfig::Modem(_) => CellApiConfig::Modem,
+            FlattenedCellApiConfig::File(_) => CellApiConfig::File,
+        }
+    }
+
+    pub fn poll_interval_ms(&self) -> u64 {
+        match self {
+            FlattenedCellApiConfig::Milesight(args) => args.milesight_poll_interval_ms,
+            FlattenedCellApiConfig::DevicePublisher(args) => args.devpub_poll_interval_ms,
+            FlattenedCellApiConfig::Modem(args) => args.modem_poll_interval_ms,
+            FlattenedCellApiConfig::File(args) => args.cellfile_poll_interval_ms,
         }
     }
 }
 
+impl FlattenedSourcePolicyArgs {
+    pub fn from_unflattened(policy_args: SourcePolicyArgs) -> Result<FlattenedSourcePolicyArgs> {
+        Ok(FlattenedSourcePolicyArgs {
+            source_backoff_max_ms: policy_args
+                .source_backoff_max_ms
+                .unwrap_or(DEFAULT_SOURCE_BACKOFF_MAX_MS),
+            source_stale_timeout_ms: policy_args
+                .source_stale_timeout_ms
+                .unwrap_or(DEFAULT_SOURCE_STALE_TIMEOUT_MS),
+        })
+    }
+}
+
+impl FlattenedSourceHttpArgs {
+    pub fn from_unflattened(http_args: SourceHttpArgs) -> Result<FlattenedSourceHttpArgs> {
+        Ok(FlattenedSourceHttpArgs {
+            source_http_timeout_ms: http_args
+                .source_http_timeout_ms
+                .unwrap_or(DEFAULT_SOURCE_HTTP_TIMEOUT_MS),
+            source_tls_ca_cert: http_args.source_tls_ca_cert,
+            source_tls_accept_invalid_certs: http_args
+                .source_tls_accept_invalid_certs
+                .unwrap_or(false),
+        })
+    }
+}
+
 impl FlattenedNgScopeArgs {
     pub fn from_unflattened(ng_args: NgScopeArgs) -> Result<FlattenedNgScopeArgs> {
         Ok(FlattenedNgScopeArgs {
@@ -417,18 +983,148 @@ impl FlattenedNgScopeArgs {
             ng_log_file: ng_args.ng_log_file,
             ng_log_dci: ng_args.ng_log_dci.unwrap(),
             ng_log_dci_batch_size: ng_args.ng_log_dci_batch_size.unwrap(),
+            ng_log_dci_quality: ng_args.ng_log_dci_quality.unwrap_or(true),
+            ng_rf_args: ng_args.ng_rf_args.unwrap_or_default(),
+            ng_config_template: ng_args.ng_config_template,
+            ng_log_events: ng_args.ng_log_events.unwrap_or(true),
+            ng_sync_loss_restart_count: ng_args.ng_sync_loss_restart_count.unwrap_or(5),
+            ng_dci_silence_timeout_ms: ng_args.ng_dci_silence_timeout_ms.unwrap_or(5000),
+            ng_rf_serial: ng_args.ng_rf_serial.unwrap_or_default(),
+            ng_n_id_2: ng_args.ng_n_id_2,
+            ng_nof_thread: ng_args.ng_nof_thread,
+            ng_disable_plot: ng_args.ng_disable_plot,
+            ng_rf_log_dl: ng_args.ng_rf_log_dl,
+            ng_rf_log_ul: ng_args.ng_rf_log_ul,
+            ng_rf_log_phich: ng_args.ng_rf_log_phich,
+            ng_rnti: ng_args.ng_rnti,
+            ng_remote_enable: ng_args.ng_remote_enable,
+            ng_decode_single_ue: ng_args.ng_decode_single_ue,
+            ng_decode_sib: ng_args.ng_decode_sib,
+            ng_dci_logs_path: ng_args.ng_dci_logs_path,
+            ng_sib_logs_path: ng_args.ng_sib_logs_path,
+            ng_dci_log_ul: ng_args.ng_dci_log_ul,
+            ng_dci_log_dl: ng_args.ng_dci_log_dl,
+            ng_dci_log_interval: ng_args.ng_dci_log_interval,
         })
     }
 }
 
 impl FlattenedRntiMatchingArgs {
     pub fn from_unflattened(rnti_args: RntiMatchingArgs) -> Result<FlattenedRntiMatchingArgs> {
-        Ok(FlattenedRntiMatchingArgs {
+        let default_parameters = MatchingParameters::default();
+        let matching_parameters = MatchingParameters {
+            weightings: rnti_args
+                .matching_weightings
+                .unwrap_or(default_parameters.weightings),
+            time_overlap_factor: rnti_args
+                .matching_time_overlap_factor
+                .unwrap_or(default_parameters.time_overlap_factor),
+            basic_filter_max_total_ul_factor: rnti_args
+                .matching_filter_max_total_ul_factor
+                .unwrap_or(default_parameters.basic_filter_max_total_ul_factor),
+            basic_filter_min_total_ul_factor: rnti_args
+                .matching_filter_min_total_ul_factor
+                .unwrap_or(default_parameters.basic_filter_min_total_ul_factor),
+            basic_filter_max_ul_per_dci: rnti_args
+                .matching_filter_max_ul_per_dci
+                .unwrap_or(default_parameters.basic_filter_max_ul_per_dci),
+            basic_filter_min_occurences_factor: rnti_args
+                .matching_filter_min_occurences_factor
+                .unwrap_or(default_parameters.basic_filter_min_occurences_factor),
+        };
+        let matching_rnti_ring_buffer_size = rnti_args
+            .matching_rnti_ring_buffer_size
+            .unwrap_or(DEFAULT_RNTI_RING_BUFFER_SIZE);
+        if matching_rnti_ring_buffer_size == 0 {
+            return Err(anyhow!("matching_rnti_ring_buffer_size must be > 0"));
+        }
+        let mut flattened_args = FlattenedRntiMatchingArgs {
             matching_local_addr: rnti_args.matching_local_addr.unwrap(),
             matching_traffic_pattern: rnti_args.matching_traffic_pattern.unwrap(),
             matching_traffic_destination: rnti_args.matching_traffic_destination.unwrap(),
             matching_log_traffic: rnti_args.matching_log_traffic.unwrap(),
-        })
+            matching_parameters,
+            matching_rnti_ring_buffer_size,
+            matching_pattern_overrides: rnti_args.matching_pattern_overrides.unwrap_or_default(),
+        };
+        for profile_path in rnti_args.matching_weights_profile.unwrap_or_default() {
+            flattened_args.apply_weights_profile(&MatchingWeightsProfile::load(&profile_path)?);
+        }
+        flattened_args.matching_parameters.validate()?;
+        for pattern_override in flattened_args.matching_pattern_overrides.iter() {
+            flattened_args
+                .parameters_for(pattern_override.pattern)
+                .validate()
+                .map_err(|err| {
+                    anyhow!(
+                        "override of pattern {:?}: {}",
+                        pattern_override.pattern,
+                        err
+                    )
+                })?;
+        }
+        Ok(flattened_args)
+    }
+
+    /// A profile without pattern replaces the global weightings
+    pub fn apply_weights_profile(&mut self, profile: &MatchingWeightsProfile) {
+        let pattern = match profile.pattern {
+            Some(pattern) => pattern,
+            None => {
+                self.matching_parameters.weightings = profile.weightings.clone();
+                return;
+            }
+        };
+        match self
+            .matching_pattern_overrides
+            .iter_mut()
+            .find(|pattern_override| pattern_override.pattern == pattern)
+        {
+            Some(pattern_override) => {
+                pattern_override.weightings = Some(profile.weightings.clone())
+            }
+            None => self
+                .matching_pattern_overrides
+                .push(MatchingPatternOverride {
+                    pattern,
+                    weightings: Some(profile.weightings.clone()),
+                    time_overlap_factor: None,
+                    filter_max_total_ul_factor: None,
+                    filter_min_total_ul_factor: None,
+                    filter_max_ul_per_dci: None,
+                    filter_min_occurences_factor: None,
+                }),
+        }
+    }
+
+    /// The first override of the pattern applies, if any
+    pub fn parameters_for(&self, pattern: RntiMatchingTrafficPatternType) -> MatchingParameters {
+        let mut parameters = self.matching_parameters.clone();
+        if let Some(pattern_override) = self
+            .matching_pattern_overrides
+            .iter()
+            .find(|pattern_override| pattern_override.pattern == pattern)
+        {
+            if let Some(weightings) = &pattern_override.weightings {
+                parameters.weightings = weightings.clone();
+            }
+            if let Some(factor) = pattern_override.time_overlap_factor {
+                parameters.time_overlap_factor = factor;
+            }
+            if let Some(factor) = pattern_override.filter_max_total_ul_factor {
+                parameters.basic_filter_max_total_ul_factor = factor;
+            }
+            if let Some(factor) = pattern_override.filter_min_total_ul_factor {
+                parameters.basic_filter_min_total_ul_factor = factor;
+            }
+            if let Some(max_ul) = pattern_override.filter_max_ul_per_dci {
+                parameters.basic_filter_max_ul_per_dci = max_ul;
+            }
+            if let Some(factor) = pattern_override.filter_min_occurences_factor {
+                parameters.basic_filter_min_occurences_factor = factor;
+            }
+        }
+        parameters
     }
 }
 
@@ -450,6 +1146,7 @@ impl FlattenedLogArgs {
     pub fn from_unflattened(log_args: LogArgs) -> Result<FlattenedLogArgs> {
         Ok(FlattenedLogArgs {
             log_base_dir: log_args.log_base_dir.unwrap(),
+            log_cell_info: log_args.log_cell_info.unwrap_or(true),
         })
     }
 }
@@ -462,3 +1159,86 @@ impl FlattenedDownloadArgs {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_group() {
+        let file_group = Arguments::default().ngscope;
+        let cli_group = Arguments::parse_from(["test", "--ng-start-process", "false"]).ngscope;
+        let merged = merge_group(cli_group, file_group.clone()).unwrap().unwrap();
+
+        let file_group = file_group.unwrap();
+        assert_eq!(merged.ng_start_process, Some(false));
+        assert_eq!(merged.ng_path, file_group.ng_path);
+        assert_eq!(merged.ng_server_addr, file_group.ng_server_addr);
+        assert!(merge_group::<NgScopeArgs>(None, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_matching_pattern_overrides() -> Result<()> {
+        let mut rnti_args = Arguments::default().rntimatching.unwrap();
+        rnti_args.matching_filter_max_ul_per_dci = Some(1_000_000);
+        rnti_args.matching_pattern_overrides = Some(vec![MatchingPatternOverride {
+            pattern: RntiMatchingTrafficPatternType::D,
+            weightings: Some(vec![0.3, 0.3, 0.1, 0.2, 0.025, 0.025, 0.025, 0.025]),
+            time_overlap_factor: Some(1.5),
+            filter_max_total_ul_factor: None,
+            filter_min_total_ul_factor: None,
+            filter_max_ul_per_dci: None,
+            filter_min_occurences_factor: None,
+        }]);
+        let matching_args = FlattenedRntiMatchingArgs::from_unflattened(rnti_args.clone())?;
+
+        let params_a = matching_args.parameters_for(RntiMatchingTrafficPatternType::A);
+        assert_eq!(
+            params_a.weightings,
+            MatchingParameters::default().weightings
+        );
+        assert_eq!(params_a.basic_filter_max_ul_per_dci, 1_000_000);
+        let params_d = matching_args.parameters_for(RntiMatchingTrafficPatternType::D);
+        assert_eq!(params_d.weightings[0], 0.3);
+        assert_eq!(params_d.time_overlap_factor, 1.5);
+        assert_eq!(params_d.basic_filter_max_ul_per_dci, 1_000_000);
+
+        rnti_args.matching_pattern_overrides.as_mut().unwrap()[0].weightings = Some(vec![1.0]);
+        assert!(FlattenedRntiMatchingArgs::from_unflattened(rnti_args).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_args_without_parameters() -> Result<()> {
+        /* config files of older versions lack the matching parameters */
+        let rnti_args = RntiMatchingArgs {
+            matching_weightings: None,
+            matching_time_overlap_factor: None,
+            matching_filter_max_total_ul_factor: None,
+            matching_filter_min_total_ul_factor: None,
+            matching_filter_max_ul_per_dci: None,
+            matching_filter_min_occurences_factor: None,
+            matching_rnti_ring_buffer_size: None,
+            matching_pattern_overrides: None,
+            matching_weights_profile: None,
+            ..Arguments::default().rntimatching.unwrap()
+        };
+        let matching_args = FlattenedRntiMatchingArgs::from_unflattened(rnti_args)?;
+        assert_eq!(
+            matching_args.matching_parameters,
+            MatchingParameters::default()
+        );
+        assert_eq!(
+            matching_args.matching_rnti_ring_buffer_size,
+            DEFAULT_RNTI_RING_BUFFER_SIZE
+        );
+
+        let cli_args = Arguments::parse_from(["test", "--matching-weightings", "1,0,0,0,0,0,0,0"]);
+        let rnti_args = merge_group(cli_args.rntimatching, Arguments::default().rntimatching)
+            .unwrap()
+            .unwrap();
+        let matching_args = FlattenedRntiMatchingArgs::from_unflattened(rnti_args)?;
+        assert_eq!(matching_args.matching_parameters.weightings[0], 1.0);
+        Ok(())
+    }
+}